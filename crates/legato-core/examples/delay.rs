@@ -7,7 +7,10 @@ use legato_core::{
         graph::{Connection, ConnectionEntry},
         port::{PortRate, Ports},
     },
-    nodes::utils::port_utils::generate_audio_outputs,
+    nodes::{
+        audio::sampler::SamplerParams,
+        utils::{interp::Interp, port_utils::generate_audio_outputs},
+    },
     out::start_runtime_audio_thread,
 };
 use std::time::Duration;
@@ -36,23 +39,26 @@ fn main() {
             },
         );
 
-    let sampler = runtime_builder.add_node(AddNode::SamplerStereo {
+    let (sampler, _) = runtime_builder.add_node(AddNode::SamplerStereo {
         sampler_name: String::from("amen"),
+        params: SamplerParams::default(),
     });
 
-    let delay_write = runtime_builder.add_node(AddNode::DelayWriteStereo {
+    let (delay_write, _) = runtime_builder.add_node(AddNode::DelayWriteStereo {
         delay_name: String::from("amen"),
         delay_length: Duration::from_secs_f32(3.0),
     });
 
-    let delay_read = runtime_builder.add_node(AddNode::DelayReadStereo {
+    let (delay_read, _) = runtime_builder.add_node(AddNode::DelayReadStereo {
         delay_name: String::from("amen"),
         offsets: vec![Duration::from_millis(12), Duration::from_millis(32)],
+        smoothing: Duration::from_millis(50),
+        interp: Interp::Linear,
     });
 
-    let mixer = runtime_builder.add_node(AddNode::TwoTrackStereoMixer);
+    let (mixer, _) = runtime_builder.add_node(AddNode::TwoTrackStereoMixer);
 
-    let delay_gain = runtime_builder.add_node(AddNode::MultStereo { props: 0.6 });
+    let (delay_gain, _) = runtime_builder.add_node(AddNode::MultStereo { props: 0.6 });
 
     let (mut runtime, mut backend) = runtime_builder.get_owned();
 
@@ -7,7 +7,7 @@ use legato_core::{
         graph::{Connection, ConnectionEntry},
         port::{PortRate, Ports},
     },
-    nodes::utils::port_utils::generate_audio_outputs,
+    nodes::{audio::sampler::SamplerParams, utils::port_utils::generate_audio_outputs},
     out::start_runtime_audio_thread,
 };
 use typenum::{U0, U2, U64, U4096, Unsigned};
@@ -117,10 +117,11 @@ fn main() {
         0.0,
     ];
 
-    let fir = runtime_builder.add_node(AddNode::FirStereo { coeffs });
+    let (fir, _) = runtime_builder.add_node(AddNode::FirStereo { coeffs });
 
-    let sampler = runtime_builder.add_node(AddNode::SamplerStereo {
+    let (sampler, _) = runtime_builder.add_node(AddNode::SamplerStereo {
         sampler_name: String::from("amen"),
+        params: SamplerParams::default(),
     });
 
     let (mut runtime, mut backend) = runtime_builder.get_owned();
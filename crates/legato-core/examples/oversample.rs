@@ -46,7 +46,7 @@ fn main() {
             },
         );
 
-    let a = oversampled_runtime_builder.add_node(AddNode::Sweep {
+    let (a, _) = oversampled_runtime_builder.add_node(AddNode::Sweep {
         range: (20.0, 32_000.0),
         duration: Duration::from_secs(5),
     });
@@ -55,7 +55,7 @@ fn main() {
 
     oversampled_runtime.set_sink_key(a).unwrap();
 
-    let b = runtime_builder.add_node(AddNode::Subgraph2XOversampled {
+    let (b, _) = runtime_builder.add_node(AddNode::Subgraph2XOversampled {
         runtime: Box::new(oversampled_runtime),
     });
 
@@ -3,7 +3,7 @@ use std::ops::Mul;
 use generic_array::ArrayLength;
 use typenum::{Prod, U0, U2};
 
-use crate::engine::{buffer::Frame, node::FrameSize, runtime::Runtime};
+use crate::engine::{buffer::Frame, midi::MidiEvent, node::FrameSize, runtime::Runtime};
 
 pub struct Application<AF, CF, C>
 where
@@ -24,6 +24,14 @@ where
     pub fn new(runtime: Runtime<AF, CF, C, U0>) -> Self {
         Self { runtime }
     }
+    /// Queue a MIDI event for the next block. Returns false if the block is full.
+    pub fn push_midi_event(&mut self, event: MidiEvent) -> bool {
+        self.runtime.get_context_mut().push_midi_event(event)
+    }
+    /// Set the tempo used by tempo synced nodes
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.runtime.get_context_mut().set_bpm(bpm);
+    }
     pub fn next_block(&mut self) -> &Frame<AF> {
         self.runtime.next_block(None)
     }
@@ -5,10 +5,14 @@ use arc_swap::ArcSwapOption;
 use crate::{
     engine::{
         buffer::Frame,
+        midi::{MidiBuffer, MidiEvent},
         node::FrameSize,
-        resources::{DelayLineKey, Resources, SampleKey, audio_sample::AudioSample},
+        resources::{
+            DelayLineKey, Resources, SampleKey, WavetableKey, audio_sample::AudioSample,
+            wavetable::Wavetable,
+        },
     },
-    nodes::audio::delay::DelayLineErased,
+    nodes::{audio::delay::DelayLineErased, utils::interp::Interpolator},
 };
 
 
@@ -18,7 +22,10 @@ where
 {
     sample_rate: f32, // avoiding frequent casting
     control_rate: f32,
+    // Tempo for synced nodes, i.e LFOs
+    bpm: f32,
     resources: Resources<N>,
+    midi: MidiBuffer,
 }
 
 impl<N> AudioContext<N>
@@ -29,7 +36,9 @@ where
         Self {
             sample_rate,
             control_rate,
+            bpm: 120.0,
             resources: Resources::new(),
+            midi: MidiBuffer::default(),
         }
     }
     #[inline(always)]
@@ -40,6 +49,13 @@ where
     pub fn get_control_rate(&self) -> f32 {
         self.control_rate
     }
+    #[inline(always)]
+    pub fn get_bpm(&self) -> f32 {
+        self.bpm
+    }
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.max(0.0);
+    }
     // Operations for resources
     pub fn write_block(&mut self, key: DelayLineKey, block: &Frame<N>) {
         self.resources.delay_write_block(key, block)
@@ -53,6 +69,20 @@ where
     ) -> f32 {
         self.resources.get_delay_linear_interp(key, channel, offset)
     }
+    #[inline(always)]
+    pub fn get_delay_interp(
+        &self,
+        key: DelayLineKey,
+        channel: usize,
+        offset: f32,
+        interp: &mut Interpolator,
+    ) -> f32 {
+        self.resources
+            .get_delay_interp(key, channel, offset, interp)
+    }
+    pub fn get_delay_capacity(&self, key: DelayLineKey) -> usize {
+        self.resources.get_delay_capacity(key)
+    }
     pub fn add_delay_line(
         &mut self,
         delay_line: Box<dyn DelayLineErased<N> + Send + 'static>,
@@ -67,4 +97,29 @@ where
     pub fn add_sample_resource(&mut self, sample: Arc<ArcSwapOption<AudioSample>>) -> SampleKey {
         self.resources.add_sample_resource(sample)
     }
+    pub fn get_wavetable(&self, key: WavetableKey) -> Option<Arc<Wavetable>> {
+        self.resources.get_wavetable(key)
+    }
+    // Like samples, the wavetable is loaded later through the backend
+    pub fn add_wavetable_resource(
+        &mut self,
+        wavetable: Arc<ArcSwapOption<Wavetable>>,
+    ) -> WavetableKey {
+        self.resources.add_wavetable_resource(wavetable)
+    }
+    // MIDI for the current block. Offsets past the end of the block are
+    // clamped to the last sample, so late events are never dropped.
+    pub fn push_midi_event(&mut self, event: MidiEvent) -> bool {
+        self.midi.push(MidiEvent {
+            offset: event.offset.min(N::USIZE - 1),
+            ..event
+        })
+    }
+    #[inline(always)]
+    pub fn get_midi_events(&self) -> &[MidiEvent] {
+        self.midi.events()
+    }
+    pub fn clear_midi_events(&mut self) {
+        self.midi.clear()
+    }
 }
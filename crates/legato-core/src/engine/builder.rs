@@ -1,4 +1,9 @@
-use std::{collections::HashMap, ops::Mul, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    ops::Mul,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use arc_swap::ArcSwapOption;
 use generic_array::ArrayLength;
@@ -7,24 +12,59 @@ use crate::{
     engine::{
         graph::NodeKey,
         node::{FrameSize, Node},
-        port::{GetPorts, Ports},
-        resources::{DelayLineKey, SampleKey, audio_sample::AudioSampleBackend},
+        port::{GetPorts, PortRate, Ports},
+        resources::{
+            DelayLineKey, SampleKey, WavetableKey, audio_sample::AudioSampleBackend,
+            wavetable::WavetableBackend,
+        },
         runtime::{Runtime, RuntimeBackend, RuntimeErased, build_runtime},
     },
     nodes::audio::{
+        analysis::{
+            EnvelopeFollower, EnvelopeFollowerParams, OnsetDetector, OnsetParams, PitchTracker,
+            PitchTrackerParams,
+        },
         audio_ops::{ApplyOpMono, ApplyOpStereo},
+        channel_mixer::{ChannelMixer, ChannelMixerParams},
         delay::{DelayLine, DelayReadMono, DelayReadStereo, DelayWriteMono, DelayWriteStereo},
-        filters::fir::{FirMono, FirStereo},
+        drum_rack::{DrumLayer, DrumRackMC, DrumSlot},
+        dynamics::{DynamicsMC, DynamicsMode, DynamicsParams},
+        envelope::{
+            EnvelopeControl, EnvelopeCurve, EnvelopeMode, EnvelopeMono, EnvelopeSegments, Segment,
+        },
+        filters::{
+            biquad::{BiquadCascade, BiquadMC, BiquadType},
+            fir::{FirMono, FirStereo},
+            svf::{FilterType, SvfMono, SvfStereo},
+            utility::{UtilityFilterMC, UtilityFilterType},
+        },
+        granular::{GranularMC, GranularParams},
+        lfo::{Lfo, LfoRate, LfoShape},
+        meter::{MeterHandle, MeterMC, MeterParams},
+        midi::{AftertouchSignal, CcSignal, Gate, NoteToFreq, Trigger, Velocity},
         mixer::*,
-        sampler::{SamplerMono, SamplerStereo},
+        modulation::{ChorusMC, ChorusParams, FlangerMC, FlangerParams, PhaserMC, PhaserParams},
+        noise::{NoiseMC, NoiseType},
+        oscillator::{OscillatorMC, Waveform},
+        panner::{Balance, MidSide, PanLaw, Panner, StereoWidth},
+        reverb::{
+            blocks::{ReverbBlockMC, ReverbBlockType},
+            fdn::{FdnMatrix, FdnParams, FdnReverb8, FdnReverb16},
+        },
+        sampler::{SamplerMono, SamplerParams, SamplerStereo},
         sine::{SineMono, SineStereo},
+        spectrum::{SpectrumHandle, SpectrumMC, SpectrumParams},
         stereo::Stereo,
         subgraph::Oversample2X,
         sweep::Sweep,
+        tap::{TapHandle, TapMC},
+        waveshaper::{WaveshaperMC, WaveshaperParams},
+        wavetable::{WavetableInterp, WavetableMono, WavetableStereo},
     },
+    nodes::utils::interp::Interp,
 };
 
-use typenum::{Prod, U0, U1, U2};
+use typenum::{Prod, U0, U1, U2, U4, U8};
 
 pub enum AddNode<AF, CF>
 where
@@ -39,14 +79,115 @@ where
     SineStereo {
         freq: f32,
     },
+    WavetableMono {
+        wavetable_name: String,
+        freq: f32,
+        frame: f32,
+        interp: WavetableInterp,
+    },
+    WavetableStereo {
+        wavetable_name: String,
+        freq: f32,
+        frame: f32,
+        interp: WavetableInterp,
+    },
+    // Band-limited classic waveforms. The channel count must be 1, 2, 4 or 8
+    Oscillator {
+        waveform: Waveform,
+        freq: f32,
+        pulse_width: f32,
+        phase: f32,
+        chans: usize,
+    },
+    // Noise, the channel count must be 1, 2, 4 or 8
+    Noise {
+        noise_type: NoiseType,
+        seed: u64,
+        chans: usize,
+    },
+    // Envelopes, with a mono audio output or a control output
+    Adsr {
+        attack: Duration,
+        decay: Duration,
+        sustain: f32,
+        release: Duration,
+        curve: EnvelopeCurve,
+        mode: EnvelopeMode,
+        rate: PortRate,
+    },
+    Envelope {
+        segments: Vec<Segment>,
+        sustain: Option<usize>,
+        loop_points: Option<(usize, usize)>,
+        mode: EnvelopeMode,
+        rate: PortRate,
+    },
+    // Control rate LFO
+    Lfo {
+        shape: LfoShape,
+        rate: LfoRate,
+        phase_offset: f32,
+        bipolar: bool,
+        seed: u64,
+    },
     // Fan mono to stereo
     Stereo,
+    // Stereo tools. Pan, balance and width are all summed with their control inputs
+    Panner {
+        law: PanLaw,
+        pan: f32,
+    },
+    Balance {
+        balance: f32,
+    },
+    MidSideEncode,
+    MidSideDecode,
+    StereoWidth {
+        width: f32,
+    },
+    // MIDI to signal. Channels are zero indexed, None listens to all channels
+    NoteToFreq {
+        bend_range: f32,
+        channel: Option<u8>,
+    },
+    Gate {
+        channel: Option<u8>,
+    },
+    Trigger {
+        channel: Option<u8>,
+    },
+    Velocity {
+        channel: Option<u8>,
+    },
+    Aftertouch {
+        channel: Option<u8>,
+    },
+    ControlChange {
+        cc: u8,
+        smoothing: Duration,
+        channel: Option<u8>,
+    },
     // Sampler utils
     SamplerMono {
         sampler_name: String,
+        params: SamplerParams,
     },
     SamplerStereo {
         sampler_name: String,
+        params: SamplerParams,
+    },
+    Granular {
+        sampler_name: String,
+        params: GranularParams,
+        chans: usize,
+    },
+    // One shot samples, with the layers named like samplers. Channels are zero indexed,
+    // None listens to all channels. The channel count must be 1, 2, 4 or 8
+    DrumRack {
+        slots: Vec<DrumSlot<String>>,
+        channel: Option<u8>,
+        interp: Interp,
+        chans: usize,
     },
     // Delays
     DelayWriteMono {
@@ -60,10 +201,51 @@ where
     DelayReadMono {
         delay_name: String,
         offsets: Vec<Duration>,
+        // How long changes to the delay time take to glide
+        smoothing: Duration,
+        interp: Interp,
     },
     DelayReadStereo {
         delay_name: String,
         offsets: Vec<Duration>,
+        // How long changes to the delay time take to glide
+        smoothing: Duration,
+        interp: Interp,
+    },
+    // Reverb. The lines must be 8 or 16
+    FdnReverb {
+        lines: usize,
+        matrix: FdnMatrix,
+        params: FdnParams,
+    },
+    // Comb, allpass, etc. The channel count must be 1, 2, 4 or 8
+    ReverbBlock {
+        block_type: ReverbBlockType,
+        chans: usize,
+    },
+    // Saturation. The channel count must be 1, 2, 4 or 8
+    Waveshaper {
+        params: WaveshaperParams,
+        chans: usize,
+    },
+    // Dynamics. The channel count must be 1, 2, 4 or 8
+    Dynamics {
+        mode: DynamicsMode,
+        params: DynamicsParams,
+        chans: usize,
+    },
+    // Modulation. The channel count must be 1, 2, 4 or 8
+    Chorus {
+        params: ChorusParams,
+        chans: usize,
+    },
+    Flanger {
+        params: FlangerParams,
+        chans: usize,
+    },
+    Phaser {
+        params: PhaserParams,
+        chans: usize,
     },
     // Filter
     FirMono {
@@ -72,6 +254,20 @@ where
     FirStereo {
         coeffs: Vec<f32>,
     },
+    // The channel count must be 1, 2, 4 or 8
+    Biquad {
+        filter_type: BiquadType,
+        cutoff: f32,
+        q: f32,
+        gain_db: f32,
+        cascade: BiquadCascade,
+        chans: usize,
+    },
+    // One pole, DC blocker, slew, etc. The channel count must be 1, 2, 4 or 8
+    UtilityFilter {
+        filter_type: UtilityFilterType,
+        chans: usize,
+    },
     // Ops
     AddMono {
         props: f32,
@@ -86,6 +282,11 @@ where
         props: f32,
     },
     // Mixers
+    // A channel strip mixer, the tracks are mono or stereo
+    ChannelMixer {
+        chans: usize,
+        params: ChannelMixerParams,
+    },
     StereoMixer,           // U2 -> U2
     StereoToMono,          // U2 -> U1
     TwoTrackStereoMixer,   // U4 -> U2
@@ -93,8 +294,18 @@ where
     EightTrackStereoMixer, // U16 -> U2
     FourToMonoMixer,       // U8  -> U1
     TwoTrackMonoMixer,     // U4 -> U1
-    // SvfMono,
-    // SvfStereo
+    SvfMono {
+        filter_type: FilterType,
+        cutoff: f32,
+        q: f32,
+        gain: f32,
+    },
+    SvfStereo {
+        filter_type: FilterType,
+        cutoff: f32,
+        q: f32,
+        gain: f32,
+    },
     // Subgraph
     Subgraph {
         runtime: Box<dyn RuntimeErased<AF, CF> + Send + 'static>,
@@ -107,6 +318,30 @@ where
         range: (f32, f32),
         duration: Duration,
     },
+    // Analysis, mono in and control out
+    EnvelopeFollower {
+        params: EnvelopeFollowerParams,
+    },
+    OnsetDetector {
+        params: OnsetParams,
+    },
+    PitchTracker {
+        params: PitchTrackerParams,
+    },
+    // Analysis. The channel count must be 1, 2, 4 or 8
+    Meter {
+        params: MeterParams,
+        chans: usize,
+    },
+    Spectrum {
+        params: SpectrumParams,
+        chans: usize,
+    },
+    // Copies audio to another thread, holding at least capacity worth of frames
+    Tap {
+        capacity: Duration,
+        chans: usize,
+    },
     // User defined nodes
     UserDefined {
         node: Box<dyn Node<AF, CF> + Send + 'static>,
@@ -116,6 +351,15 @@ where
     },
 }
 
+/// The non-audio side of a node, for nodes that publish to other threads
+#[derive(Debug, Clone)]
+pub enum NodeHandle {
+    Meter(MeterHandle),
+    // A tap only has one reader, so clones share it
+    Tap(Arc<Mutex<TapHandle>>),
+    Spectrum(SpectrumHandle),
+}
+
 pub struct RuntimeBuilder<AF, CF, C, Ci>
 where
     AF: FrameSize + Mul<U2>,
@@ -128,6 +372,8 @@ where
     delay_resource_lookup: HashMap<String, DelayLineKey>,
     sample_key_lookup: HashMap<String, SampleKey>,
     sample_backend_lookup: HashMap<String, AudioSampleBackend>,
+    wavetable_key_lookup: HashMap<String, WavetableKey>,
+    wavetable_backend_lookup: HashMap<String, WavetableBackend>,
 }
 
 impl<AF, CF, C, Ci> RuntimeBuilder<AF, CF, C, Ci>
@@ -144,6 +390,8 @@ where
             delay_resource_lookup: HashMap::default(),
             sample_key_lookup: HashMap::default(),
             sample_backend_lookup: HashMap::default(),
+            wavetable_key_lookup: HashMap::default(),
+            wavetable_backend_lookup: HashMap::default(),
         }
     }
     fn get_runtime_mut(&mut self) -> &mut Runtime<AF, CF, C, Ci> {
@@ -152,7 +400,10 @@ where
 
     // Get owned runtime value. In practice, you won't use this struct anymore after this
     pub fn get_owned(self) -> (Runtime<AF, CF, C, Ci>, RuntimeBackend) {
-        (self.runtime,RuntimeBackend::new(self.sample_backend_lookup))
+        (
+            self.runtime,
+            RuntimeBackend::new(self.sample_backend_lookup, self.wavetable_backend_lookup),
+        )
     }
 
     fn get_sample_rate(&self) -> f32 {
@@ -163,8 +414,46 @@ where
         self.runtime.get_node_ports(&node_key)
     }
 
+    // Wavetables can be shared between oscillators, so only make a new resource if the name is new
+    fn get_or_add_wavetable(&mut self, wavetable_name: String) -> WavetableKey {
+        if let Some(&key) = self.wavetable_key_lookup.get(&wavetable_name) {
+            return key;
+        }
+        let ctx = self.runtime.get_context_mut();
+
+        let data = Arc::new(ArcSwapOption::new(None));
+        let backend = WavetableBackend::new(data.clone());
+        let key = ctx.add_wavetable_resource(data);
+
+        self.wavetable_backend_lookup
+            .insert(wavetable_name.clone(), backend);
+        self.wavetable_key_lookup.insert(wavetable_name, key);
+
+        key
+    }
+
+    // Samples can be shared between samplers, so only make a new resource if the name is new
+    fn get_or_add_sample(&mut self, sample_name: String) -> SampleKey {
+        if let Some(&key) = self.sample_key_lookup.get(&sample_name) {
+            return key;
+        }
+        let ctx = self.runtime.get_context_mut();
+
+        let data = Arc::new(ArcSwapOption::new(None));
+        let backend = AudioSampleBackend::new(data.clone());
+        let key = ctx.add_sample_resource(data);
+
+        self.sample_backend_lookup
+            .insert(sample_name.clone(), backend);
+        self.sample_key_lookup.insert(sample_name, key);
+
+        key
+    }
+
     // Add nodes to runtime
-    pub fn add_node(&mut self, node_to_add: AddNode<AF, CF>) -> NodeKey {
+    /// Adds a node, returning its key, and a handle if the node publishes to other threads
+    pub fn add_node(&mut self, node_to_add: AddNode<AF, CF>) -> (NodeKey, Option<NodeHandle>) {
+        let mut handle = None;
         let node: Box<dyn Node<AF, CF> + Send + 'static> = match node_to_add {
             // Ops
             AddNode::AddMono { props } => Box::new(ApplyOpMono::new(|a, b| a + b, props)),
@@ -173,7 +462,14 @@ where
             AddNode::MultStereo { props } => Box::new(ApplyOpStereo::new(|a, b| a * b, props)),
             // Mono to stereo
             AddNode::Stereo => Box::new(Stereo::default()),
+            // Stereo tools
+            AddNode::Panner { law, pan } => Box::new(Panner::new(law, pan)),
+            AddNode::Balance { balance } => Box::new(Balance::new(balance)),
+            AddNode::MidSideEncode => Box::new(MidSide::encode()),
+            AddNode::MidSideDecode => Box::new(MidSide::decode()),
+            AddNode::StereoWidth { width } => Box::new(StereoWidth::new(width)),
             // Mixers
+            AddNode::ChannelMixer { chans, params } => Box::new(ChannelMixer::new(chans, params)),
             AddNode::StereoMixer => Box::new(StereoMixer::default()),
             AddNode::StereoToMono => Box::new(StereoToMonoMixer::default()),
             AddNode::FourToMonoMixer => Box::new(FourToMonoMixer::default()),
@@ -184,45 +480,215 @@ where
             // Filters
             AddNode::FirMono { coeffs } => Box::new(FirMono::new(coeffs)),
             AddNode::FirStereo { coeffs } => Box::new(FirStereo::new(coeffs)),
+            AddNode::SvfMono {
+                filter_type,
+                cutoff,
+                q,
+                gain,
+            } => Box::new(SvfMono::new(filter_type, cutoff, q, gain)),
+            AddNode::SvfStereo {
+                filter_type,
+                cutoff,
+                q,
+                gain,
+            } => Box::new(SvfStereo::new(filter_type, cutoff, q, gain)),
+            AddNode::Biquad {
+                filter_type,
+                cutoff,
+                q,
+                gain_db,
+                cascade,
+                chans,
+            } => match chans {
+                1 => Box::new(BiquadMC::<U1>::new(
+                    filter_type,
+                    cutoff,
+                    q,
+                    gain_db,
+                    cascade,
+                )),
+                2 => Box::new(BiquadMC::<U2>::new(
+                    filter_type,
+                    cutoff,
+                    q,
+                    gain_db,
+                    cascade,
+                )),
+                4 => Box::new(BiquadMC::<U4>::new(
+                    filter_type,
+                    cutoff,
+                    q,
+                    gain_db,
+                    cascade,
+                )),
+                8 => Box::new(BiquadMC::<U8>::new(
+                    filter_type,
+                    cutoff,
+                    q,
+                    gain_db,
+                    cascade,
+                )),
+                _ => panic!("Unsupported biquad channel count {}", chans),
+            },
+            AddNode::UtilityFilter { filter_type, chans } => match chans {
+                1 => Box::new(UtilityFilterMC::<U1>::new(filter_type)),
+                2 => Box::new(UtilityFilterMC::<U2>::new(filter_type)),
+                4 => Box::new(UtilityFilterMC::<U4>::new(filter_type)),
+                8 => Box::new(UtilityFilterMC::<U8>::new(filter_type)),
+                _ => panic!("Unsupported utility filter channel count {}", chans),
+            },
             // Osc
             AddNode::SineMono { freq } => Box::new(SineMono::new(freq, 0.0)),
             AddNode::SineStereo { freq } => Box::new(SineStereo::new(freq, 0.0)),
+            AddNode::WavetableMono {
+                wavetable_name,
+                freq,
+                frame,
+                interp,
+            } => {
+                let key = self.get_or_add_wavetable(wavetable_name);
+                Box::new(WavetableMono::new(key, freq, frame, interp))
+            }
+            AddNode::WavetableStereo {
+                wavetable_name,
+                freq,
+                frame,
+                interp,
+            } => {
+                let key = self.get_or_add_wavetable(wavetable_name);
+                Box::new(WavetableStereo::new(key, freq, frame, interp))
+            }
+            AddNode::Oscillator {
+                waveform,
+                freq,
+                pulse_width,
+                phase,
+                chans,
+            } => match chans {
+                1 => Box::new(OscillatorMC::<U1>::new(waveform, freq, pulse_width, phase)),
+                2 => Box::new(OscillatorMC::<U2>::new(waveform, freq, pulse_width, phase)),
+                4 => Box::new(OscillatorMC::<U4>::new(waveform, freq, pulse_width, phase)),
+                8 => Box::new(OscillatorMC::<U8>::new(waveform, freq, pulse_width, phase)),
+                _ => panic!("Unsupported oscillator channel count {}", chans),
+            },
+            // Noise
+            AddNode::Noise {
+                noise_type,
+                seed,
+                chans,
+            } => match chans {
+                1 => Box::new(NoiseMC::<U1>::new(noise_type, seed)),
+                2 => Box::new(NoiseMC::<U2>::new(noise_type, seed)),
+                4 => Box::new(NoiseMC::<U4>::new(noise_type, seed)),
+                8 => Box::new(NoiseMC::<U8>::new(noise_type, seed)),
+                _ => panic!("Unsupported noise channel count {}", chans),
+            },
+            // Envelopes
+            AddNode::Adsr {
+                attack,
+                decay,
+                sustain,
+                release,
+                curve,
+                mode,
+                rate,
+            } => {
+                let env = EnvelopeSegments::adsr(attack, decay, sustain, release, curve, mode);
+                match rate {
+                    PortRate::Audio => Box::new(EnvelopeMono::new(env)),
+                    PortRate::Control => Box::new(EnvelopeControl::new(env)),
+                }
+            }
+            AddNode::Envelope {
+                segments,
+                sustain,
+                loop_points,
+                mode,
+                rate,
+            } => {
+                let env = EnvelopeSegments::new(segments, sustain, loop_points, mode);
+                match rate {
+                    PortRate::Audio => Box::new(EnvelopeMono::new(env)),
+                    PortRate::Control => Box::new(EnvelopeControl::new(env)),
+                }
+            }
+            AddNode::Lfo {
+                shape,
+                rate,
+                phase_offset,
+                bipolar,
+                seed,
+            } => Box::new(Lfo::new(shape, rate, phase_offset, bipolar, seed)),
+            // MIDI
+            AddNode::NoteToFreq {
+                bend_range,
+                channel,
+            } => Box::new(NoteToFreq::new(bend_range, channel)),
+            AddNode::Gate { channel } => Box::new(Gate::new(channel)),
+            AddNode::Trigger { channel } => Box::new(Trigger::new(channel)),
+            AddNode::Velocity { channel } => Box::new(Velocity::new(channel)),
+            AddNode::Aftertouch { channel } => Box::new(AftertouchSignal::new(channel)),
+            AddNode::ControlChange {
+                cc,
+                smoothing,
+                channel,
+            } => Box::new(CcSignal::new(cc, smoothing, channel)),
             // Samplers
             AddNode::SamplerMono {
-                sampler_name: sample_name,
+                sampler_name,
+                params,
+            } => Box::new(SamplerMono::new(
+                self.get_or_add_sample(sampler_name),
+                params,
+            )),
+            AddNode::SamplerStereo {
+                sampler_name,
+                params,
+            } => Box::new(SamplerStereo::new(
+                self.get_or_add_sample(sampler_name),
+                params,
+            )),
+            AddNode::Granular {
+                sampler_name,
+                params,
+                chans,
             } => {
-                let sample_key = if let Some(&key) = self.sample_key_lookup.get(&sample_name) {
-                    key
-                } else {
-                    let ctx = self.runtime.get_context_mut();
-
-                    let data = Arc::new(ArcSwapOption::new(None));
-                    let backend = AudioSampleBackend::new(data.clone());
-
-                    self.sample_backend_lookup.insert(sample_name, backend);
-
-                    ctx.add_sample_resource(data)
-                };
-
-                Box::new(SamplerMono::new(sample_key))
+                let sample_key = self.get_or_add_sample(sampler_name);
+                match chans {
+                    1 => Box::new(GranularMC::<U1>::new(sample_key, params)),
+                    2 => Box::new(GranularMC::<U2>::new(sample_key, params)),
+                    4 => Box::new(GranularMC::<U4>::new(sample_key, params)),
+                    8 => Box::new(GranularMC::<U8>::new(sample_key, params)),
+                    _ => panic!("Unsupported granular channel count {}", chans),
+                }
             }
-            AddNode::SamplerStereo {
-                sampler_name: sample_name,
+            AddNode::DrumRack {
+                slots,
+                channel,
+                interp,
+                chans,
             } => {
-                let sample_key = if let Some(&key) = self.sample_key_lookup.get(&sample_name) {
-                    key
-                } else {
-                    let ctx = self.runtime.get_context_mut();
-
-                    let data = Arc::new(ArcSwapOption::new(None));
-                    let backend = AudioSampleBackend::new(data.clone());
-
-                    self.sample_backend_lookup.insert(sample_name, backend);
-
-                    ctx.add_sample_resource(data)
-                };
-
-                Box::new(SamplerStereo::new(sample_key))
+                let slots = slots
+                    .into_iter()
+                    .map(|slot| DrumSlot {
+                        layers: slot
+                            .layers
+                            .into_iter()
+                            .map(|layer| DrumLayer {
+                                sample: self.get_or_add_sample(layer.sample),
+                                min_velocity: layer.min_velocity,
+                            })
+                            .collect(),
+                        params: slot.params,
+                    })
+                    .collect();
+                match chans {
+                    1 => Box::new(DrumRackMC::<U1>::new(slots, channel, interp)),
+                    2 => Box::new(DrumRackMC::<U2>::new(slots, channel, interp)),
+                    4 => Box::new(DrumRackMC::<U4>::new(slots, channel, interp)),
+                    8 => Box::new(DrumRackMC::<U8>::new(slots, channel, interp)),
+                    _ => panic!("Unsupported drum rack channel count {}", chans),
+                }
             }
             // Delay Line
             AddNode::DelayWriteMono {
@@ -258,25 +724,153 @@ where
             AddNode::DelayReadMono {
                 delay_name,
                 offsets,
+                smoothing,
+                interp,
             } => {
                 let delay_key = self
                     .delay_resource_lookup
                     .get(&delay_name)
                     .expect("Delay read instantiated before line initialized");
-                Box::new(DelayReadMono::new(delay_key.clone(), offsets))
+                Box::new(DelayReadMono::new(
+                    delay_key.clone(),
+                    offsets,
+                    smoothing,
+                    interp,
+                ))
             }
             AddNode::DelayReadStereo {
                 delay_name,
                 offsets,
+                smoothing,
+                interp,
             } => {
                 let delay_key = self
                     .delay_resource_lookup
                     .get(&delay_name)
                     .expect("Delay read instantiated before line initialized");
-                Box::new(DelayReadStereo::new(delay_key.clone(), offsets))
+                Box::new(DelayReadStereo::new(
+                    delay_key.clone(),
+                    offsets,
+                    smoothing,
+                    interp,
+                ))
             }
+            // Reverb
+            AddNode::FdnReverb {
+                lines,
+                matrix,
+                params,
+            } => {
+                let sr = self.get_sample_rate();
+                match lines {
+                    8 => Box::new(FdnReverb8::<AF>::new(params, matrix, sr)),
+                    16 => Box::new(FdnReverb16::<AF>::new(params, matrix, sr)),
+                    _ => panic!("Unsupported FDN line count {}", lines),
+                }
+            }
+            AddNode::ReverbBlock { block_type, chans } => {
+                let sr = self.get_sample_rate();
+                match chans {
+                    1 => Box::new(ReverbBlockMC::<AF, U1>::new(block_type, sr)),
+                    2 => Box::new(ReverbBlockMC::<AF, U2>::new(block_type, sr)),
+                    4 => Box::new(ReverbBlockMC::<AF, U4>::new(block_type, sr)),
+                    8 => Box::new(ReverbBlockMC::<AF, U8>::new(block_type, sr)),
+                    _ => panic!("Unsupported reverb block channel count {}", chans),
+                }
+            }
+            // Saturation
+            AddNode::Waveshaper { params, chans } => match chans {
+                1 => Box::new(WaveshaperMC::<U1>::new(params)),
+                2 => Box::new(WaveshaperMC::<U2>::new(params)),
+                4 => Box::new(WaveshaperMC::<U4>::new(params)),
+                8 => Box::new(WaveshaperMC::<U8>::new(params)),
+                _ => panic!("Unsupported waveshaper channel count {}", chans),
+            },
+            // Dynamics
+            AddNode::Dynamics {
+                mode,
+                params,
+                chans,
+            } => {
+                let sr = self.get_sample_rate();
+                match chans {
+                    1 => Box::new(DynamicsMC::<AF, U1>::new(mode, params, sr)),
+                    2 => Box::new(DynamicsMC::<AF, U2>::new(mode, params, sr)),
+                    4 => Box::new(DynamicsMC::<AF, U4>::new(mode, params, sr)),
+                    8 => Box::new(DynamicsMC::<AF, U8>::new(mode, params, sr)),
+                    _ => panic!("Unsupported dynamics channel count {}", chans),
+                }
+            }
+            // Modulation
+            AddNode::Chorus { params, chans } => {
+                let sr = self.get_sample_rate();
+                match chans {
+                    1 => Box::new(ChorusMC::<AF, U1>::new(params, sr)),
+                    2 => Box::new(ChorusMC::<AF, U2>::new(params, sr)),
+                    4 => Box::new(ChorusMC::<AF, U4>::new(params, sr)),
+                    8 => Box::new(ChorusMC::<AF, U8>::new(params, sr)),
+                    _ => panic!("Unsupported chorus channel count {}", chans),
+                }
+            }
+            AddNode::Flanger { params, chans } => {
+                let sr = self.get_sample_rate();
+                match chans {
+                    1 => Box::new(FlangerMC::<AF, U1>::new(params, sr)),
+                    2 => Box::new(FlangerMC::<AF, U2>::new(params, sr)),
+                    4 => Box::new(FlangerMC::<AF, U4>::new(params, sr)),
+                    8 => Box::new(FlangerMC::<AF, U8>::new(params, sr)),
+                    _ => panic!("Unsupported flanger channel count {}", chans),
+                }
+            }
+            AddNode::Phaser { params, chans } => match chans {
+                1 => Box::new(PhaserMC::<U1>::new(params)),
+                2 => Box::new(PhaserMC::<U2>::new(params)),
+                4 => Box::new(PhaserMC::<U4>::new(params)),
+                8 => Box::new(PhaserMC::<U8>::new(params)),
+                _ => panic!("Unsupported phaser channel count {}", chans),
+            },
             // Utils
             AddNode::Sweep { range, duration } => Box::new(Sweep::new(range, duration)),
+            // Analysis
+            AddNode::EnvelopeFollower { params } => Box::new(EnvelopeFollower::new(params)),
+            AddNode::OnsetDetector { params } => Box::new(OnsetDetector::new(params)),
+            AddNode::PitchTracker { params } => {
+                Box::new(PitchTracker::new(params, self.get_sample_rate()))
+            }
+            AddNode::Meter { params, chans } => {
+                let (node, meter): (Box<dyn Node<AF, CF> + Send>, MeterHandle) = match chans {
+                    1 => boxed(MeterMC::<U1>::new(params)),
+                    2 => boxed(MeterMC::<U2>::new(params)),
+                    4 => boxed(MeterMC::<U4>::new(params)),
+                    8 => boxed(MeterMC::<U8>::new(params)),
+                    _ => panic!("Unsupported meter channel count {}", chans),
+                };
+                handle = Some(NodeHandle::Meter(meter));
+                node
+            }
+            AddNode::Spectrum { params, chans } => {
+                let (node, spectrum): (Box<dyn Node<AF, CF> + Send>, SpectrumHandle) = match chans {
+                    1 => boxed(SpectrumMC::<U1>::new(params)),
+                    2 => boxed(SpectrumMC::<U2>::new(params)),
+                    4 => boxed(SpectrumMC::<U4>::new(params)),
+                    8 => boxed(SpectrumMC::<U8>::new(params)),
+                    _ => panic!("Unsupported spectrum channel count {}", chans),
+                };
+                handle = Some(NodeHandle::Spectrum(spectrum));
+                node
+            }
+            AddNode::Tap { capacity, chans } => {
+                let frames = (capacity.as_secs_f32() * self.get_sample_rate()) as usize;
+                let (node, tap): (Box<dyn Node<AF, CF> + Send>, TapHandle) = match chans {
+                    1 => boxed(TapMC::<U1>::new(frames)),
+                    2 => boxed(TapMC::<U2>::new(frames)),
+                    4 => boxed(TapMC::<U4>::new(frames)),
+                    8 => boxed(TapMC::<U8>::new(frames)),
+                    _ => panic!("Unsupported tap channel count {}", chans),
+                };
+                handle = Some(NodeHandle::Tap(Arc::new(Mutex::new(tap))));
+                node
+            }
             // Oversampler
             AddNode::Subgraph { runtime } => runtime,
             AddNode::Subgraph2XOversampled { runtime } => {
@@ -286,10 +880,20 @@ where
             AddNode::UserDefined { node } => node,
             AddNode::UserDefinedFactory { factory } => factory(),
         };
-        self.runtime.add_node(node)
+        (self.runtime.add_node(node), handle)
     }
 }
 
+/// Boxes the node half of a node and handle pair
+fn boxed<AF, CF, N, H>((node, handle): (N, H)) -> (Box<dyn Node<AF, CF> + Send>, H)
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    N: Node<AF, CF> + Send + 'static,
+{
+    (Box::new(node), handle)
+}
+
 pub fn get_runtime_builder<AF, CF, C, Ci>(
     initial_capacity: usize,
     sample_rate: f32,
@@ -98,6 +98,10 @@ where
         self.nodes.len()
     }
 
+    pub fn nodes(&self) -> impl Iterator<Item = &AudioNode<AF, CF>> {
+        self.nodes.values()
+    }
+
     pub fn get_sort_order_nodes_and_runtime_info(
         &mut self,
     ) -> (
@@ -0,0 +1,197 @@
+/// A minimal set of channel voice messages.
+///
+/// Values are normalized where it makes sense, so nodes
+/// don't have to care about 7 vs 14 bit values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: f32,
+    },
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    /// Polyphonic key pressure
+    PolyAftertouch {
+        channel: u8,
+        note: u8,
+        pressure: f32,
+    },
+    /// Channel pressure
+    Aftertouch {
+        channel: u8,
+        pressure: f32,
+    },
+    ControlChange {
+        channel: u8,
+        cc: u8,
+        value: f32,
+    },
+    /// Bipolar, -1.0 to 1.0
+    PitchBend {
+        channel: u8,
+        value: f32,
+    },
+}
+
+impl MidiMessage {
+    /// Parse a raw MIDI message, i.e from midir.
+    ///
+    /// Anything that is not a channel voice message we care
+    /// about returns None. A note on with zero velocity is
+    /// treated as a note off, as per the spec.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.first()?;
+        let channel = status & 0x0F;
+        let data = |i: usize| bytes.get(i).map(|x| x & 0x7F);
+
+        match status & 0xF0 {
+            0x80 => Some(Self::NoteOff {
+                channel,
+                note: data(1)?,
+            }),
+            0x90 => {
+                let note = data(1)?;
+                let velocity = data(2)?;
+                if velocity == 0 {
+                    Some(Self::NoteOff { channel, note })
+                } else {
+                    Some(Self::NoteOn {
+                        channel,
+                        note,
+                        velocity: velocity as f32 / 127.0,
+                    })
+                }
+            }
+            0xA0 => Some(Self::PolyAftertouch {
+                channel,
+                note: data(1)?,
+                pressure: data(2)? as f32 / 127.0,
+            }),
+            0xB0 => Some(Self::ControlChange {
+                channel,
+                cc: data(1)?,
+                value: data(2)? as f32 / 127.0,
+            }),
+            0xD0 => Some(Self::Aftertouch {
+                channel,
+                pressure: data(1)? as f32 / 127.0,
+            }),
+            0xE0 => {
+                let raw = (data(1)? as u16) | ((data(2)? as u16) << 7);
+                Some(Self::PitchBend {
+                    channel,
+                    value: ((raw as f32 - 8192.0) / 8192.0).clamp(-1.0, 1.0),
+                })
+            }
+            _ => None,
+        }
+    }
+    pub fn channel(&self) -> u8 {
+        match *self {
+            Self::NoteOn { channel, .. }
+            | Self::NoteOff { channel, .. }
+            | Self::PolyAftertouch { channel, .. }
+            | Self::Aftertouch { channel, .. }
+            | Self::ControlChange { channel, .. }
+            | Self::PitchBend { channel, .. } => channel,
+        }
+    }
+}
+
+/// A message, with the sample offset into the current block
+/// that it should be applied at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiEvent {
+    /// Clamped to the block when pushed to the audio context
+    pub offset: usize,
+    pub message: MidiMessage,
+}
+
+// Arbitrary max events per block
+pub const MAX_MIDI_EVENTS: usize = 256;
+
+/// Preallocated per-block MIDI events.
+///
+/// The host pushes events before calling next_block, nodes
+/// read them during the block, and the runtime clears
+/// them once the block is done.
+///
+/// For the time being, events are expected to be pushed in order.
+pub struct MidiBuffer {
+    events: Vec<MidiEvent>,
+}
+
+impl Default for MidiBuffer {
+    fn default() -> Self {
+        Self {
+            events: Vec::with_capacity(MAX_MIDI_EVENTS),
+        }
+    }
+}
+
+impl MidiBuffer {
+    /// Returns false if the buffer is full, rather than allocating on the audio thread
+    pub fn push(&mut self, event: MidiEvent) -> bool {
+        if self.events.len() < MAX_MIDI_EVENTS {
+            self.events.push(event);
+            true
+        } else {
+            false
+        }
+    }
+    #[inline(always)]
+    pub fn events(&self) -> &[MidiEvent] {
+        &self.events
+    }
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MidiMessage;
+
+    #[test]
+    fn parse_note_messages() {
+        assert_eq!(
+            MidiMessage::from_bytes(&[0x91, 60, 127]),
+            Some(MidiMessage::NoteOn {
+                channel: 1,
+                note: 60,
+                velocity: 1.0
+            })
+        );
+        // Running note on with zero velocity is a note off
+        assert_eq!(
+            MidiMessage::from_bytes(&[0x90, 60, 0]),
+            Some(MidiMessage::NoteOff {
+                channel: 0,
+                note: 60
+            })
+        );
+        assert_eq!(MidiMessage::from_bytes(&[0x90, 60]), None);
+        assert_eq!(MidiMessage::from_bytes(&[0xF8]), None);
+    }
+
+    #[test]
+    fn parse_pitch_bend() {
+        let centre = MidiMessage::from_bytes(&[0xE0, 0x00, 0x40]);
+        assert_eq!(
+            centre,
+            Some(MidiMessage::PitchBend {
+                channel: 0,
+                value: 0.0
+            })
+        );
+        let Some(MidiMessage::PitchBend { value, .. }) =
+            MidiMessage::from_bytes(&[0xE0, 0x7F, 0x7F])
+        else {
+            panic!("Expected pitch bend");
+        };
+        assert!((value - 1.0).abs() < 1e-3);
+    }
+}
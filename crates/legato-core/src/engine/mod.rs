@@ -2,6 +2,7 @@ pub mod audio_context;
 pub mod buffer;
 pub mod builder;
 pub mod graph;
+pub mod midi;
 pub mod node;
 pub mod port;
 pub mod resources;
@@ -31,6 +31,7 @@ impl AudioSample {
 pub enum AudioSampleError {
     PathNotFound,
     FailedDecoding,
+    InvalidTable,
 }
 
 /// The audio sample backend is a quick trick to load a sample
@@ -49,7 +50,7 @@ impl AudioSampleBackend {
         Self { data }
     }
     pub fn load_file(&self, path: &str, chans: usize, sr: u32) -> Result<(), AudioSampleError> {
-        match decode_with_ffmpeg(path, chans, sr) {
+        match decode_with_ffmpeg(path, chans, Some(sr)) {
             Ok(decoded) => {
                 self.data.store(Some(Arc::new(decoded)));
                 Ok(())
@@ -1,4 +1,5 @@
 pub mod audio_sample;
+pub mod wavetable;
 
 use std::sync::Arc;
 
@@ -6,14 +7,19 @@ use arc_swap::ArcSwapOption;
 use slotmap::{SlotMap, new_key_type};
 
 use crate::{
-    engine::{buffer::Frame, node::FrameSize, resources::audio_sample::AudioSample},
-    nodes::audio::delay::DelayLineErased,
+    engine::{
+        buffer::Frame,
+        node::FrameSize,
+        resources::{audio_sample::AudioSample, wavetable::Wavetable},
+    },
+    nodes::{audio::delay::DelayLineErased, utils::interp::Interpolator},
 };
 
 // TODO: Maybe use a hashmap to get string -> index pairs,
 // then use the index at runtime?
 new_key_type! { pub struct DelayLineKey; }
 new_key_type! { pub struct SampleKey; }
+new_key_type! { pub struct WavetableKey; }
 
 /// Resources are shared resources provided
 /// to Nodes by the runtime context.
@@ -27,6 +33,7 @@ where
 {
     delay_lines: SlotMap<DelayLineKey, Box<dyn DelayLineErased<N>>>,
     samples: SlotMap<SampleKey, Arc<ArcSwapOption<AudioSample>>>,
+    wavetables: SlotMap<WavetableKey, Arc<ArcSwapOption<Wavetable>>>,
 }
 
 impl<N> Resources<N>
@@ -37,6 +44,7 @@ where
         Self {
             delay_lines: SlotMap::default(),
             samples: SlotMap::default(),
+            wavetables: SlotMap::default(),
         }
     }
     pub fn delay_write_block(&mut self, key: DelayLineKey, block: &Frame<N>) {
@@ -53,6 +61,21 @@ where
         let delay_line = self.delay_lines.get(key).unwrap();
         delay_line.get_delay_linear_interp_erased(channel, offset)
     }
+    #[inline(always)]
+    pub fn get_delay_interp(
+        &self,
+        key: DelayLineKey,
+        channel: usize,
+        offset: f32,
+        interp: &mut Interpolator,
+    ) -> f32 {
+        let delay_line = self.delay_lines.get(key).unwrap();
+        delay_line.get_delay_interp_erased(channel, offset, interp)
+    }
+    pub fn get_delay_capacity(&self, key: DelayLineKey) -> usize {
+        let delay_line = self.delay_lines.get(key).unwrap();
+        delay_line.get_capacity_erased()
+    }
     pub fn add_delay_line(
         &mut self,
         delay_line: Box<dyn DelayLineErased<N> + Send + 'static>,
@@ -71,4 +94,13 @@ where
         }
         None
     }
+    pub fn add_wavetable_resource(
+        &mut self,
+        wavetable: Arc<ArcSwapOption<Wavetable>>,
+    ) -> WavetableKey {
+        self.wavetables.insert(wavetable)
+    }
+    pub fn get_wavetable(&self, key: WavetableKey) -> Option<Arc<Wavetable>> {
+        self.wavetables.get(key).and_then(|inner| inner.load_full())
+    }
 }
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwapOption;
+
+use crate::{
+    engine::resources::audio_sample::{AudioSample, AudioSampleError},
+    nodes::utils::{ffmpeg::decode_with_ffmpeg, fft::fft, interp::lerp},
+};
+
+/// A band-limited, mip-mapped wavetable.
+///
+/// Tables are stored per octave, so that each level has half the
+/// harmonics of the previous level. The oscillator picks the level
+/// whose highest harmonic stays under nyquist for the current pitch.
+///
+/// Building the levels does a few FFTs per frame, so this should
+/// happen on the loading thread, never the audio thread.
+pub struct Wavetable {
+    frame_size: usize,
+    frames: usize,
+    // [level][frame][sample]
+    levels: Vec<Vec<Vec<f32>>>,
+}
+
+impl Wavetable {
+    /// Build a table from raw data. The frame size must be a power of two.
+    ///
+    /// If the data length is a multiple of the frame size, it is treated
+    /// as a multi-frame table. Data shorter than a frame is treated as a
+    /// single cycle, and resampled to the frame size. Any other length
+    /// would leave a partial frame, so it is rejected.
+    pub fn from_data(data: &[f32], frame_size: usize) -> Result<Self, AudioSampleError> {
+        if !frame_size.is_power_of_two() || frame_size < 4 || data.is_empty() {
+            return Err(AudioSampleError::InvalidTable);
+        }
+
+        let raw_frames: Vec<Vec<f32>> = if data.len().is_multiple_of(frame_size) {
+            data.chunks(frame_size).map(|x| x.to_vec()).collect()
+        } else if data.len() > frame_size {
+            return Err(AudioSampleError::InvalidTable);
+        } else {
+            let ratio = data.len() as f32 / frame_size as f32;
+            let resampled = (0..frame_size)
+                .map(|i| {
+                    let pos = i as f32 * ratio;
+                    let index = pos as usize;
+                    let next = (index + 1) % data.len();
+                    lerp(data[index], data[next], pos.fract())
+                })
+                .collect();
+            vec![resampled]
+        };
+
+        let frames = raw_frames.len();
+        // The top level only keeps the fundamental
+        let num_levels = (frame_size / 2).trailing_zeros() as usize;
+
+        let mut levels = vec![Vec::with_capacity(frames); num_levels];
+
+        let mut re = vec![0.0; frame_size];
+        let mut im = vec![0.0; frame_size];
+
+        for frame in raw_frames.iter() {
+            // Forward transform once per frame, then band limit per level
+            let mut spectrum_re = frame.clone();
+            let mut spectrum_im = vec![0.0; frame_size];
+            fft(&mut spectrum_re, &mut spectrum_im, false);
+
+            for (level, tables) in levels.iter_mut().enumerate() {
+                let max_harmonic = (frame_size / 2) >> level;
+
+                re.copy_from_slice(&spectrum_re);
+                im.copy_from_slice(&spectrum_im);
+
+                // Zero everything from the max harmonic, and its mirror, up. Nyquist is always removed.
+                for k in max_harmonic..=(frame_size - max_harmonic) {
+                    re[k] = 0.0;
+                    im[k] = 0.0;
+                }
+
+                fft(&mut re, &mut im, true);
+                tables.push(re.clone());
+            }
+        }
+
+        Ok(Self {
+            frame_size,
+            frames,
+            levels,
+        })
+    }
+    /// Builds a table from the first channel of a sample
+    pub fn from_sample(sample: &AudioSample, frame_size: usize) -> Result<Self, AudioSampleError> {
+        let data = sample
+            .data()
+            .first()
+            .ok_or(AudioSampleError::InvalidTable)?;
+        Self::from_data(data, frame_size)
+    }
+    #[inline(always)]
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+    #[inline(always)]
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+    #[inline(always)]
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+    /// The band-limited level to use at a given frequency.
+    #[inline(always)]
+    pub fn level_for(&self, freq: f32, fs: f32) -> usize {
+        let freq = freq.abs();
+        if freq <= 0.0 {
+            return 0;
+        }
+        // We need (frame_size / 2) >> level <= (fs / 2) / freq
+        let level = (self.frame_size as f32 * freq / fs).log2().ceil();
+        (level.max(0.0) as usize).min(self.levels.len() - 1)
+    }
+    #[inline(always)]
+    pub fn get_frame(&self, level: usize, frame: usize) -> &[f32] {
+        &self.levels[level][frame]
+    }
+}
+
+/// Loads and builds wavetables off of the audio thread,
+/// in the same fashion as the AudioSampleBackend.
+#[derive(Clone)]
+pub struct WavetableBackend {
+    data: Arc<ArcSwapOption<Wavetable>>,
+}
+impl WavetableBackend {
+    pub fn new(data: Arc<ArcSwapOption<Wavetable>>) -> Self {
+        Self { data }
+    }
+    /// Loads a table from a file. It is not resampled, as that would
+    /// change the length, and split the frames in the wrong places.
+    pub fn load_file(&self, path: &str, frame_size: usize) -> Result<(), AudioSampleError> {
+        let decoded =
+            decode_with_ffmpeg(path, 1, None).map_err(|_| AudioSampleError::FailedDecoding)?;
+        self.load_sample(&decoded, frame_size)
+    }
+    pub fn load_sample(
+        &self,
+        sample: &AudioSample,
+        frame_size: usize,
+    ) -> Result<(), AudioSampleError> {
+        let table = Wavetable::from_sample(sample, frame_size)?;
+        self.data.store(Some(Arc::new(table)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Wavetable;
+
+    fn saw(len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| 2.0 * i as f32 / len as f32 - 1.0)
+            .collect()
+    }
+
+    #[test]
+    fn top_level_is_band_limited_to_the_fundamental() {
+        let table = Wavetable::from_data(&saw(256), 256).unwrap();
+        assert_eq!(table.levels(), 7);
+
+        let top = table.get_frame(table.levels() - 1, 0);
+        // A saw with only the fundamental is a sine, peaking at 2 / pi
+        let peak = top.iter().fold(0.0_f32, |acc, x| acc.max(x.abs()));
+        assert!((peak - 2.0 / std::f32::consts::PI).abs() < 1e-2);
+    }
+
+    #[test]
+    fn partial_frames_are_rejected() {
+        // A single cycle shorter than a frame is resampled, but a partial frame is an error
+        assert_eq!(Wavetable::from_data(&saw(200), 256).unwrap().frames(), 1);
+        assert!(Wavetable::from_data(&saw(256 + 100), 256).is_err());
+    }
+
+    #[test]
+    fn level_selection_keeps_harmonics_under_nyquist() {
+        let table = Wavetable::from_data(&saw(2048 * 2), 2048).unwrap();
+        assert_eq!(table.frames(), 2);
+
+        let fs = 48_000.0;
+        assert_eq!(table.level_for(20.0, fs), 0);
+        for freq in [55.0, 440.0, 1000.0, 5000.0, 12_000.0] {
+            let level = table.level_for(freq, fs);
+            let highest_harmonic = ((2048 / 2) >> level) - 1;
+            assert!(highest_harmonic as f32 * freq <= fs / 2.0);
+        }
+    }
+}
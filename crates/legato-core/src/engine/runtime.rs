@@ -2,18 +2,27 @@ use std::ops::Mul;
 
 use crate::engine::{
     audio_context::AudioContext,
+    builder::NodeHandle,
     buffer::{Buffer, Frame},
     graph::{AudioGraph, AudioNode, Connection, GraphError, NodeKey},
     node::{FrameSize, Node},
-    port::{GetPorts, PortRate, PortedErased, Ports}, resources::audio_sample::AudioSampleBackend,
+    port::{GetPorts, PortRate, PortedErased, Ports}, resources::{audio_sample::AudioSampleBackend, wavetable::WavetableBackend},
 };
 use generic_array::ArrayLength;
 use slotmap::SecondaryMap;
 use typenum::{Prod, U0, U2};
 
-// Arbitrary max init. inputs
+// Arbitrary max init. inputs, the scratch buffers grow past this for bigger nodes
 pub const MAX_INITIAL_INPUTS: usize = 32;
 
+// The audio and control input counts of a node, for sizing the scratch buffers
+fn input_counts<AF: FrameSize, CF: FrameSize>(node: &AudioNode<AF, CF>) -> (usize, usize) {
+    (
+        node.get_audio_inputs().map_or(0, |f| f.len()),
+        node.get_control_inputs().map_or(0, |f| f.len()),
+    )
+}
+
 pub struct Runtime<AF, CF, C, Ci>
 where
     AF: FrameSize + Mul<U2>,
@@ -50,13 +59,17 @@ where
     ) -> Self {
         let audio_sources = SecondaryMap::with_capacity(graph.len());
         let control_sources = SecondaryMap::with_capacity(graph.len());
+        let (audio_inputs, control_inputs) = graph.nodes().map(input_counts).fold(
+            (MAX_INITIAL_INPUTS, MAX_INITIAL_INPUTS),
+            |(audio, control), (a, c)| (audio.max(a), control.max(c)),
+        );
         Self {
             context,
             graph,
             port_sources_audio: audio_sources,
             port_sources_control: control_sources,
-            audio_inputs_scratch_buffers: vec![Buffer::default(); MAX_INITIAL_INPUTS],
-            control_inputs_scratch_buffers: vec![Buffer::default(); MAX_INITIAL_INPUTS],
+            audio_inputs_scratch_buffers: vec![Buffer::default(); audio_inputs],
+            control_inputs_scratch_buffers: vec![Buffer::default(); control_inputs],
             sink_key: None,
             ports,
         }
@@ -65,6 +78,17 @@ where
         let audio_inputs_length = node.get_audio_outputs().map_or(0, |f| f.len());
         let control_inputs_length = node.get_control_outputs().map_or(0, |f| f.len());
 
+        // Grow the scratch buffers here, rather than on the audio thread
+        let (audio_inputs, control_inputs) = input_counts(&node);
+        if audio_inputs > self.audio_inputs_scratch_buffers.len() {
+            self.audio_inputs_scratch_buffers
+                .resize(audio_inputs, Buffer::default());
+        }
+        if control_inputs > self.control_inputs_scratch_buffers.len() {
+            self.control_inputs_scratch_buffers
+                .resize(control_inputs, Buffer::default());
+        }
+
         let node_key = self.graph.add_node(node);
 
         self.port_sources_audio
@@ -127,9 +151,13 @@ where
             // Pass in inputs if they exist to source node. In the future, maybe make this explicity rather than from topo sort
             if i == 0 && external_inputs.is_some() {
                 let (ai, ci) = external_inputs.unwrap();
-                for c in 0..C::USIZE {
-                    self.audio_inputs_scratch_buffers[c].copy_from_slice(&ai[c]);
-                    self.control_inputs_scratch_buffers[c].copy_from_slice(&ci[c]);
+                let audio_inputs = self.audio_inputs_scratch_buffers.iter_mut().zip(ai);
+                for (buf, chan) in audio_inputs.take(C::USIZE) {
+                    buf.copy_from_slice(chan);
+                }
+                let control_inputs = self.control_inputs_scratch_buffers.iter_mut().zip(ci);
+                for (buf, chan) in control_inputs.take(Ci::USIZE) {
+                    buf.copy_from_slice(chan);
                 }
             } else {
                 let incoming = incoming.get(*node_key).expect("Invalid connection!");
@@ -146,7 +174,7 @@ where
                             }
                         }
                         (PortRate::Control, PortRate::Control) => {
-                            for n in 0..AF::USIZE {
+                            for n in 0..CF::USIZE {
                                 self.control_inputs_scratch_buffers[connection.sink.port_index]
                                     [n] += self.port_sources_control[connection.source.node_key]
                                     [connection.source.port_index][n];
@@ -156,7 +184,13 @@ where
                             panic!("Audio to control not currently supported")
                         }
                         (PortRate::Control, PortRate::Audio) => {
-                            todo!("Control to audio not currently supported")
+                            // Sample and hold each control value across the audio samples it covers
+                            let source = &self.port_sources_control[connection.source.node_key]
+                                [connection.source.port_index];
+                            for n in 0..AF::USIZE {
+                                self.audio_inputs_scratch_buffers[connection.sink.port_index][n] +=
+                                    source[n * CF::USIZE / AF::USIZE];
+                            }
                         }
                     };
                 }
@@ -173,11 +207,14 @@ where
                 &mut self.context,
                 &self.audio_inputs_scratch_buffers[0..audio_input_size],
                 audio_output_buffer.as_mut_slice(),
-                &self.control_inputs_scratch_buffers[0..audio_input_size],
+                &self.control_inputs_scratch_buffers[0..control_input_size],
                 control_output_buffer.as_mut_slice(),
             );
         }
 
+        // MIDI is only valid for the block it was pushed in
+        self.context.clear_midi_events();
+
         let sink_key = self.sink_key.expect("Sink node must be provided");
         self.port_sources_audio
             .get(sink_key)
@@ -233,18 +270,25 @@ where
 
 /// The backend that sends commands to the runtime.
 /// 
-/// For the time being, this is primarily used to load new samples,
+/// For the time being, this is primarily used to load new samples and wavetables,
 /// but in the future, it will likely use channels for invoking certain
 /// functions on certain nodes.
 /// 
 /// TOOD: Tidy this up a bit, needs better error handling
 pub struct RuntimeBackend {
-    audio_sample_backend: std::collections::HashMap<String, AudioSampleBackend>
+    audio_sample_backend: std::collections::HashMap<String, AudioSampleBackend>,
+    wavetable_backend: std::collections::HashMap<String, WavetableBackend>,
+    node_handles: std::collections::HashMap<String, NodeHandle>,
 }
 impl RuntimeBackend {
-    pub fn new(sample_backend: std::collections::HashMap<String, AudioSampleBackend>) -> Self {
+    pub fn new(
+        sample_backend: std::collections::HashMap<String, AudioSampleBackend>,
+        wavetable_backend: std::collections::HashMap<String, WavetableBackend>,
+    ) -> Self {
         Self {
-            audio_sample_backend: sample_backend
+            audio_sample_backend: sample_backend,
+            wavetable_backend,
+            node_handles: std::collections::HashMap::new(),
         }
     }
     pub fn load_sample(&mut self, sampler: &String, path: &str, chans: usize, sr: u32){
@@ -252,6 +296,26 @@ impl RuntimeBackend {
             backend.load_file(path, chans, sr).unwrap();
         }
     }
+    /// Loads a single cycle or multi-frame table. The frame size must be a power of two.
+    pub fn load_wavetable(&mut self, wavetable: &String, path: &str, frame_size: usize) {
+        if let Some(backend) = self.wavetable_backend.get(wavetable) {
+            backend.load_file(path, frame_size).unwrap();
+        }
+    }
+    pub fn get_wavetable_backend(&self, wavetable: &String) -> Option<&WavetableBackend> {
+        self.wavetable_backend.get(wavetable)
+    }
+    /// Keeps a node's handle, i.e a meter, so it can be found by name
+    pub fn add_node_handle(&mut self, node: String, handle: NodeHandle) {
+        self.node_handles.insert(node, handle);
+    }
+    pub fn get_node_handle(&self, node: &str) -> Option<&NodeHandle> {
+        self.node_handles.get(node)
+    }
+    /// Moves a handle out, i.e to give a tap's reader to another thread
+    pub fn take_node_handle(&mut self, node: &str) -> Option<NodeHandle> {
+        self.node_handles.remove(node)
+    }
 }
 
 pub fn build_runtime<AF, CF, C, Ci>(
@@ -0,0 +1,573 @@
+use std::time::Duration;
+
+use generic_array::arr;
+use typenum::{U0, U1, U2};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::{
+        audio::dynamics::Detection,
+        utils::{
+            filters::time_coeff,
+            gain::{db_to_gain, gain_to_db},
+            port_utils::generate_audio_inputs,
+        },
+    },
+};
+
+fn control_output(name: &'static str, index: usize) -> ControlOutputPort {
+    ControlOutputPort {
+        meta: PortMeta { name, index },
+    }
+}
+
+/// Follows the level of the input, with separate attack and release times
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeFollowerParams {
+    pub attack: Duration,
+    pub release: Duration,
+    pub detection: Detection,
+    /// How long the RMS detector averages over
+    pub rms_window: Duration,
+}
+
+impl Default for EnvelopeFollowerParams {
+    fn default() -> Self {
+        Self {
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(100),
+            detection: Detection::Peak,
+            rms_window: Duration::from_millis(10),
+        }
+    }
+}
+
+/// An envelope follower, turning the level of a mono input into
+/// an "env" control signal, from 0.0 up.
+pub struct EnvelopeFollower {
+    params: EnvelopeFollowerParams,
+    coeffs_fs: f32,
+    attack: f32,
+    release: f32,
+    rms: f32,
+    mean_square: f32,
+    env: f32,
+    ports: Ports<U1, U0, U0, U1>,
+}
+
+impl EnvelopeFollower {
+    pub fn new(params: EnvelopeFollowerParams) -> Self {
+        Self {
+            params,
+            coeffs_fs: 0.0,
+            attack: 0.0,
+            release: 0.0,
+            rms: 0.0,
+            mean_square: 0.0,
+            env: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: None,
+                control_inputs: None,
+                control_outputs: Some(arr![control_output("env", 0)]),
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for EnvelopeFollower
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        _: &mut Frame<AF>,
+        _: &Frame<CF>,
+        co: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 1);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.coeffs_fs {
+            self.attack = time_coeff(self.params.attack, fs);
+            self.release = time_coeff(self.params.release, fs);
+            self.rms = time_coeff(self.params.rms_window, fs);
+            self.coeffs_fs = fs;
+        }
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        for (n, x) in ai[0].iter().enumerate() {
+            let level = match self.params.detection {
+                Detection::Peak => x.abs(),
+                Detection::Rms => {
+                    let square = x * x;
+                    self.mean_square = square + self.rms * (self.mean_square - square);
+                    self.mean_square.sqrt()
+                }
+            };
+            let coeff = if level > self.env {
+                self.attack
+            } else {
+                self.release
+            };
+            self.env = level + coeff * (self.env - level);
+
+            // The last sample of each chunk wins
+            if let Some(out) = co.first_mut() {
+                out[(n / chunk).min(CF::USIZE - 1)] = self.env;
+            }
+        }
+    }
+}
+
+impl PortedErased for EnvelopeFollower {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        None
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        self.ports.get_control_outputs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnsetParams {
+    /// How far the fast envelope has to jump over the slow one, in dB
+    pub threshold: f32,
+    /// Anything quieter than this is ignored, in dB
+    pub floor: f32,
+    /// The shortest time between two onsets
+    pub min_interval: Duration,
+}
+
+impl Default for OnsetParams {
+    fn default() -> Self {
+        Self {
+            threshold: 9.0,
+            floor: -50.0,
+            min_interval: Duration::from_millis(50),
+        }
+    }
+}
+
+// A fast envelope for the transient, and a slow one for what came before it
+const ONSET_FAST_ATTACK: Duration = Duration::from_micros(500);
+const ONSET_FAST_RELEASE: Duration = Duration::from_millis(20);
+const ONSET_SLOW_ATTACK: Duration = Duration::from_millis(50);
+const ONSET_SLOW_RELEASE: Duration = Duration::from_millis(200);
+
+/// Finds transients in a mono input, sending a 1.0 on the "trig" control
+/// output for one control sample at each onset.
+///
+/// An onset is when a fast envelope jumps the threshold over a slow one.
+/// The detector re-arms once the jump falls back under half the threshold,
+/// so a single hit doesn't fire twice.
+pub struct OnsetDetector {
+    params: OnsetParams,
+    coeffs_fs: f32,
+    // Attack and release, for the fast then slow envelopes
+    coeffs: [f32; 4],
+    fast: f32,
+    slow: f32,
+    armed: bool,
+    since_onset: usize,
+    min_interval: usize,
+    ports: Ports<U1, U0, U0, U1>,
+}
+
+impl OnsetDetector {
+    pub fn new(params: OnsetParams) -> Self {
+        Self {
+            params,
+            coeffs_fs: 0.0,
+            coeffs: [0.0; 4],
+            fast: 0.0,
+            slow: 0.0,
+            armed: true,
+            since_onset: usize::MAX,
+            min_interval: 0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: None,
+                control_inputs: None,
+                control_outputs: Some(arr![control_output("trig", 0)]),
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for OnsetDetector
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        _: &mut Frame<AF>,
+        _: &Frame<CF>,
+        co: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 1);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.coeffs_fs {
+            self.coeffs = [
+                ONSET_FAST_ATTACK,
+                ONSET_FAST_RELEASE,
+                ONSET_SLOW_ATTACK,
+                ONSET_SLOW_RELEASE,
+            ]
+            .map(|t| time_coeff(t, fs));
+            self.min_interval = (self.params.min_interval.as_secs_f32() * fs) as usize;
+            self.coeffs_fs = fs;
+        }
+
+        if let Some(out) = co.first_mut() {
+            out.fill(0.0);
+        }
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let floor = db_to_gain(self.params.floor);
+        let [fast_attack, fast_release, slow_attack, slow_release] = self.coeffs;
+
+        for (n, x) in ai[0].iter().enumerate() {
+            let level = x.abs();
+            let coeff = if level > self.fast {
+                fast_attack
+            } else {
+                fast_release
+            };
+            self.fast = level + coeff * (self.fast - level);
+            let coeff = if level > self.slow {
+                slow_attack
+            } else {
+                slow_release
+            };
+            self.slow = level + coeff * (self.slow - level);
+
+            let jump = gain_to_db(self.fast) - gain_to_db(self.slow);
+            self.since_onset = self.since_onset.saturating_add(1);
+
+            if jump < self.params.threshold * 0.5 {
+                self.armed = true;
+            } else if self.armed
+                && jump >= self.params.threshold
+                && self.fast >= floor
+                && self.since_onset >= self.min_interval
+            {
+                self.armed = false;
+                self.since_onset = 0;
+                if let Some(out) = co.first_mut() {
+                    out[(n / chunk).min(CF::USIZE - 1)] = 1.0;
+                }
+            }
+        }
+    }
+}
+
+impl PortedErased for OnsetDetector {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        None
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        self.ports.get_control_outputs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchTrackerParams {
+    /// The lowest pitch to look for, this sets the window length
+    pub min_freq: f32,
+    pub max_freq: f32,
+    /// The YIN threshold, lower is stricter about what counts as pitched
+    pub threshold: f32,
+    /// Anything quieter than this is unpitched, in dB
+    pub floor: f32,
+}
+
+impl Default for PitchTrackerParams {
+    fn default() -> Self {
+        Self {
+            min_freq: 60.0,
+            max_freq: 1000.0,
+            threshold: 0.15,
+            floor: -50.0,
+        }
+    }
+}
+
+/// A monophonic YIN pitch tracker, with "freq" and "confidence" control outputs.
+///
+/// The window is one period of the lowest pitch, and a new estimate is made
+/// every half window. When the input stops being pitched, the frequency holds
+/// the last estimate, and the confidence drops to 0.0.
+///
+/// The difference function is done directly rather than with an FFT, so
+/// lower minimum frequencies cost quadratically more.
+pub struct PitchTracker {
+    params: PitchTrackerParams,
+    // The last two windows of input, and the unrolled copy
+    history: Vec<f32>,
+    scratch: Vec<f32>,
+    write_index: usize,
+    since_estimate: usize,
+    // The cumulative mean normalized difference
+    difference: Vec<f32>,
+    freq: f32,
+    confidence: f32,
+    ports: Ports<U1, U0, U0, U2>,
+}
+
+impl PitchTracker {
+    /// The buffers are sized for the sample rate, so the window is
+    /// shortened if the graph runs faster than this
+    pub fn new(params: PitchTrackerParams, sample_rate: f32) -> Self {
+        let params = PitchTrackerParams {
+            min_freq: params.min_freq.max(1.0),
+            max_freq: params.max_freq.max(params.min_freq.max(1.0)),
+            ..params
+        };
+        let window = (sample_rate / params.min_freq).ceil() as usize + 2;
+        Self {
+            params,
+            history: vec![0.0; 2 * window],
+            scratch: vec![0.0; 2 * window],
+            write_index: 0,
+            since_estimate: 0,
+            difference: vec![0.0; window + 1],
+            freq: 0.0,
+            confidence: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: None,
+                control_inputs: None,
+                control_outputs: Some(arr![
+                    control_output("freq", 0),
+                    control_output("confidence", 1)
+                ]),
+            },
+        }
+    }
+    fn window(&self, fs: f32) -> usize {
+        ((fs / self.params.min_freq).ceil() as usize).min(self.difference.len() - 1)
+    }
+    fn estimate(&mut self, fs: f32) {
+        let window = self.window(fs);
+        let len = self.history.len();
+        for (n, x) in self.scratch.iter_mut().enumerate() {
+            *x = self.history[(self.write_index + n) % len];
+        }
+        // The most recent two windows
+        let x = &self.scratch[len - 2 * window..];
+
+        let power = x[..window].iter().map(|x| x * x).sum::<f32>() / window as f32;
+        if power.sqrt() < db_to_gain(self.params.floor) {
+            self.confidence = 0.0;
+            return;
+        }
+
+        let min_tau = ((fs / self.params.max_freq) as usize).max(2);
+        let max_tau = window;
+
+        // Cumulative mean normalized difference
+        self.difference[0] = 1.0;
+        let mut running = 0.0;
+        for tau in 1..=max_tau {
+            let d: f32 = x[..window]
+                .iter()
+                .zip(&x[tau..tau + window])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            running += d;
+            self.difference[tau] = if running > 0.0 {
+                d * tau as f32 / running
+            } else {
+                1.0
+            };
+        }
+
+        // The first dip under the threshold, then down to the bottom of it
+        let d = &self.difference[..=max_tau];
+        let Some(mut tau) = (min_tau..max_tau).find(|&tau| d[tau] < self.params.threshold) else {
+            self.confidence = 0.0;
+            return;
+        };
+        while tau + 1 < max_tau && d[tau + 1] < d[tau] {
+            tau += 1;
+        }
+
+        // Parabolic interpolation between the neighbours
+        let (a, b, c) = (d[tau - 1], d[tau], d[tau + 1]);
+        let curve = a - 2.0 * b + c;
+        let offset = if curve > 0.0 {
+            (0.5 * (a - c) / curve).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        self.freq = fs / (tau as f32 + offset);
+        self.confidence = (1.0 - b).clamp(0.0, 1.0);
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for PitchTracker
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        _: &mut Frame<AF>,
+        _: &Frame<CF>,
+        co: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 1);
+
+        let fs = ctx.get_sample_rate();
+        let hop = (self.window(fs) / 2).max(1);
+        let len = self.history.len();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        for (n, x) in ai[0].iter().enumerate() {
+            self.history[self.write_index] = *x;
+            self.write_index = (self.write_index + 1) % len;
+
+            self.since_estimate += 1;
+            if self.since_estimate >= hop {
+                self.since_estimate = 0;
+                self.estimate(fs);
+            }
+
+            let m = (n / chunk).min(CF::USIZE - 1);
+            if let [freq, confidence, ..] = co {
+                freq[m] = self.freq;
+                confidence[m] = self.confidence;
+            }
+        }
+    }
+}
+
+impl PortedErased for PitchTracker {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        None
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        self.ports.get_control_outputs()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::TAU;
+
+    use typenum::{U16, U64, Unsigned};
+
+    use super::*;
+    use crate::nodes::utils::{
+        rng::Rng,
+        test_utils::{FS, NodeRunner},
+    };
+
+    /// Runs a node over a signal, returning its control outputs
+    fn run(
+        node: &mut dyn Node<U64, U16>,
+        secs: f32,
+        signal: impl Fn(usize) -> f32,
+    ) -> Vec<Vec<f32>> {
+        let mut runner = NodeRunner::new(node);
+        let mut out = vec![vec![]; runner.co.len()];
+        for b in 0..(secs * FS) as usize / U64::USIZE {
+            for (n, x) in runner.ai[0].iter_mut().enumerate() {
+                *x = signal(b * U64::USIZE + n);
+            }
+            runner.process(node);
+            for (out, co) in out.iter_mut().zip(runner.co.iter()) {
+                out.extend_from_slice(co);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn follower_attacks_and_releases() {
+        let params = EnvelopeFollowerParams {
+            attack: Duration::from_millis(5),
+            release: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let mut follower = EnvelopeFollower::new(params);
+        let env = &run(&mut follower, 0.2, |n| if n < 4800 { 0.5 } else { 0.0 })[0];
+
+        // Control samples are every 4 audio samples, so 5ms is 60 of them
+        assert!((env[60] - 0.5 * (1.0 - (-1.0_f32).exp())).abs() < 0.01);
+        assert!((env[1199] - 0.5).abs() < 1e-3);
+        assert!((env[1200 + 600] - 0.5 * (-1.0_f32).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn onsets_fire_once_per_hit() {
+        let mut detector = OnsetDetector::new(OnsetParams::default());
+        let mut rng = Rng::new(7);
+        let noise: Vec<f32> = (0..2 * FS as usize).map(|_| rng.next_bipolar()).collect();
+        // A decaying burst of noise every 250ms, over a quiet hum
+        let trig = &run(&mut detector, 2.0, |n| {
+            let t = (n % 12_000) as f32 / FS;
+            (-t * 40.0).exp() * noise[n] + 0.01 * (TAU * 100.0 * n as f32 / FS).sin()
+        })[0];
+
+        let onsets: Vec<usize> = (0..trig.len()).filter(|m| trig[*m] == 1.0).collect();
+        assert_eq!(onsets.len(), 8, "{:?}", onsets);
+        for (i, m) in onsets.iter().enumerate() {
+            // Within a couple of milliseconds of each hit
+            assert!(m.abs_diff(i * 3000) < 10);
+        }
+    }
+
+    #[test]
+    fn pitch_tracks_a_sine_and_rejects_silence() {
+        let mut tracker = PitchTracker::new(PitchTrackerParams::default(), FS);
+        let out = run(&mut tracker, 0.5, |n| {
+            // A harmonic rich tone, so the octave errors would show up
+            let phase = TAU * 220.0 * n as f32 / FS;
+            0.5 * phase.sin() + 0.3 * (2.0 * phase).sin() + 0.2 * (3.0 * phase).sin()
+        });
+        let (freq, confidence) = (&out[0], &out[1]);
+        let last = freq.len() - 1;
+        assert!((freq[last] - 220.0).abs() < 0.5, "{}", freq[last]);
+        assert!(confidence[last] > 0.9);
+
+        let out = run(&mut tracker, 0.1, |_| 0.0);
+        let last = out[0].len() - 1;
+        assert!((out[0][last] - 220.0).abs() < 0.5);
+        assert_eq!(out[1][last], 0.0);
+    }
+}
@@ -0,0 +1,420 @@
+use std::time::Duration;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::{
+            AudioInputPort, AudioOutputPort, ControlInputPort, ControlOutputPort, PortMeta,
+            PortedErased,
+        },
+    },
+    nodes::{
+        audio::panner::{PanLaw, pan_gains},
+        utils::{filters::OnePoleLp, gain::db_to_gain},
+    },
+};
+
+pub const MAX_TRACKS: usize = 16;
+pub const MAX_SENDS: usize = 4;
+
+/// How long gain, pan, mute and send changes take to glide
+const SMOOTHING: Duration = Duration::from_millis(10);
+
+// Port names have to be static, so these are spelled out up to the max track count
+macro_rules! track_names {
+    ($prefix:literal) => {
+        [
+            concat!($prefix, "_0"),
+            concat!($prefix, "_1"),
+            concat!($prefix, "_2"),
+            concat!($prefix, "_3"),
+            concat!($prefix, "_4"),
+            concat!($prefix, "_5"),
+            concat!($prefix, "_6"),
+            concat!($prefix, "_7"),
+            concat!($prefix, "_8"),
+            concat!($prefix, "_9"),
+            concat!($prefix, "_10"),
+            concat!($prefix, "_11"),
+            concat!($prefix, "_12"),
+            concat!($prefix, "_13"),
+            concat!($prefix, "_14"),
+            concat!($prefix, "_15"),
+        ]
+    };
+}
+
+const GAIN_NAMES: [&str; MAX_TRACKS] = track_names!("gain");
+const PAN_NAMES: [&str; MAX_TRACKS] = track_names!("pan");
+const MUTE_NAMES: [&str; MAX_TRACKS] = track_names!("mute");
+const SOLO_NAMES: [&str; MAX_TRACKS] = track_names!("solo");
+const SEND_NAMES: [[&str; MAX_TRACKS]; MAX_SENDS] = [
+    track_names!("send0"),
+    track_names!("send1"),
+    track_names!("send2"),
+    track_names!("send3"),
+];
+const SEND_OUTPUT_NAMES: [[&str; 2]; MAX_SENDS] = [
+    ["send0_l", "send0_r"],
+    ["send1_l", "send1_r"],
+    ["send2_l", "send2_r"],
+    ["send3_l", "send3_r"],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackParams {
+    /// In dB
+    pub gain: f32,
+    /// -1.0 to 1.0. Only used by stereo mixers, where it works as a balance.
+    pub pan: f32,
+    pub mute: bool,
+    pub solo: bool,
+    /// The level going to each send, in dB
+    pub sends: Vec<f32>,
+}
+
+impl Default for TrackParams {
+    fn default() -> Self {
+        Self {
+            gain: 0.0,
+            pan: 0.0,
+            mute: false,
+            solo: false,
+            sends: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SendParams {
+    /// Tap the track before its gain and pan. Mute still applies.
+    pub pre_fader: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChannelMixerParams {
+    pub tracks: Vec<TrackParams>,
+    pub sends: Vec<SendParams>,
+    /// In dB
+    pub master: f32,
+}
+
+impl ChannelMixerParams {
+    /// Unity gain tracks, with every send turned all the way down
+    pub fn new(tracks: usize, sends: Vec<SendParams>) -> Self {
+        let track = TrackParams {
+            sends: vec![f32::NEG_INFINITY; sends.len()],
+            ..Default::default()
+        };
+        Self {
+            tracks: vec![track; tracks],
+            sends,
+            master: 0.0,
+        }
+    }
+}
+
+/// A mixing desk, with gain, pan, mute, solo and sends on each track, and a master gain.
+///
+/// The audio inputs are the tracks, with their channels interleaved, i.e
+/// [L0, R0, L1, R1, ...] for stereo. The outputs are the master bus,
+/// followed by each of the send buses. Unlike `Mixer`, the tracks are
+/// summed at unity, so the faders do the work.
+///
+/// Each track parameter has a control input, "gain_N" (dB), "pan_N",
+/// "mute_N", "solo_N" and "sendS_N" (dB), plus "master" (dB). Gains and
+/// pans are added to the params, and mute and solo are on above 0.5.
+/// Everything is smoothed.
+pub struct ChannelMixer {
+    chans: usize,
+    params: ChannelMixerParams,
+    // The targets for the current control chunk
+    gain_targets: Vec<f32>,
+    pan_targets: Vec<f32>,
+    audible_targets: Vec<f32>,
+    send_targets: Vec<f32>,
+    master_target: f32,
+    // Smoothers, per track, and per send and track
+    gains: Vec<OnePoleLp>,
+    pans: Vec<OnePoleLp>,
+    audible: Vec<OnePoleLp>,
+    send_levels: Vec<OnePoleLp>,
+    master: OnePoleLp,
+    smoothing_fs: f32,
+    audio_inputs: Vec<AudioInputPort>,
+    audio_outputs: Vec<AudioOutputPort>,
+    control_inputs: Vec<ControlInputPort>,
+}
+
+impl ChannelMixer {
+    /// Mixes params.tracks tracks of chans channels, which must be 1 or 2
+    pub fn new(chans: usize, params: ChannelMixerParams) -> Self {
+        let tracks = params.tracks.len();
+        let sends = params.sends.len();
+        assert!(
+            (1..=MAX_TRACKS).contains(&tracks),
+            "The mixer needs 1 to 16 tracks"
+        );
+        assert!(sends <= MAX_SENDS, "The mixer has up to 4 sends");
+        assert!(
+            chans == 1 || chans == 2,
+            "Mixer tracks must be mono or stereo"
+        );
+
+        let channel_name = |c: usize| match (chans, c) {
+            (2, 0) => "l",
+            (2, _) => "r",
+            _ => "in",
+        };
+        let audio_inputs = (0..tracks * chans)
+            .map(|i| AudioInputPort {
+                meta: PortMeta {
+                    name: channel_name(i % chans),
+                    index: i,
+                },
+            })
+            .collect();
+        let audio_outputs = (0..(sends + 1) * chans)
+            .map(|i| {
+                let (bus, c) = (i / chans, i % chans);
+                let name = match (bus, chans) {
+                    (0, 2) => ["l", "r"][c],
+                    (0, _) => "out",
+                    (bus, 2) => SEND_OUTPUT_NAMES[bus - 1][c],
+                    (bus, _) => SEND_OUTPUT_NAMES[bus - 1][0].trim_end_matches("_l"),
+                };
+                AudioOutputPort {
+                    meta: PortMeta { name, index: i },
+                }
+            })
+            .collect();
+
+        let mut names: Vec<&'static str> = Vec::new();
+        for kind in [&GAIN_NAMES, &PAN_NAMES, &MUTE_NAMES, &SOLO_NAMES]
+            .into_iter()
+            .chain(SEND_NAMES.iter().take(sends))
+        {
+            names.extend_from_slice(&kind[..tracks]);
+        }
+        names.push("master");
+        let control_inputs = names
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| ControlInputPort {
+                meta: PortMeta { name, index },
+            })
+            .collect();
+
+        Self {
+            chans,
+            params,
+            gain_targets: vec![0.0; tracks],
+            pan_targets: vec![0.0; tracks],
+            audible_targets: vec![0.0; tracks],
+            send_targets: vec![0.0; tracks * sends],
+            master_target: 0.0,
+            gains: vec![OnePoleLp::default(); tracks],
+            pans: vec![OnePoleLp::default(); tracks],
+            audible: vec![OnePoleLp::default(); tracks],
+            send_levels: vec![OnePoleLp::default(); tracks * sends],
+            master: OnePoleLp::default(),
+            smoothing_fs: 0.0,
+            audio_inputs,
+            audio_outputs,
+            control_inputs,
+        }
+    }
+    /// Works out the targets from the params and the control inputs, at control frame m
+    fn set_targets<CF: FrameSize>(&mut self, ci: &Frame<CF>, m: usize) {
+        let tracks = self.params.tracks.len();
+        let control = |index: usize| ci.get(index).map_or(0.0, |x| x[m]);
+
+        let soloed = |t: usize| self.params.tracks[t].solo || control(3 * tracks + t) > 0.5;
+        let any_solo = (0..tracks).any(soloed);
+
+        for t in 0..tracks {
+            let track = &self.params.tracks[t];
+            let muted = track.mute || control(2 * tracks + t) > 0.5;
+            let audible = !muted && (!any_solo || soloed(t));
+
+            self.gain_targets[t] = db_to_gain(track.gain + control(t));
+            self.pan_targets[t] = (track.pan + control(tracks + t)).clamp(-1.0, 1.0);
+            self.audible_targets[t] = if audible { 1.0 } else { 0.0 };
+
+            for s in 0..self.params.sends.len() {
+                let level = track.sends.get(s).copied().unwrap_or(f32::NEG_INFINITY);
+                self.send_targets[s * tracks + t] =
+                    db_to_gain(level + control((4 + s) * tracks + t));
+            }
+        }
+        let master = (4 + self.params.sends.len()) * tracks;
+        self.master_target = db_to_gain(self.params.master + control(master));
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for ChannelMixer
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        let chans = self.chans;
+        let tracks = self.params.tracks.len();
+        debug_assert_eq!(ai.len(), tracks * chans);
+        debug_assert_eq!(ao.len(), (self.params.sends.len() + 1) * chans);
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.smoothing_fs {
+            let first = self.smoothing_fs == 0.0;
+            if first {
+                self.set_targets(ci, 0);
+            }
+            let smoothers = self
+                .gains
+                .iter_mut()
+                .zip(self.gain_targets.iter())
+                .chain(self.pans.iter_mut().zip(self.pan_targets.iter()))
+                .chain(self.audible.iter_mut().zip(self.audible_targets.iter()))
+                .chain(self.send_levels.iter_mut().zip(self.send_targets.iter()))
+                .chain(std::iter::once((&mut self.master, &self.master_target)));
+            for (smoother, target) in smoothers {
+                smoother.set_time(SMOOTHING, fs);
+                // Start at the targets, rather than fading in from zero
+                if first {
+                    smoother.reset(*target);
+                }
+            }
+            self.smoothing_fs = fs;
+        }
+
+        for buffer in ao.iter_mut() {
+            buffer.fill(0.0);
+        }
+
+        for n in 0..AF::USIZE {
+            if n % chunk == 0 {
+                self.set_targets(ci, (n / chunk).min(CF::USIZE - 1));
+            }
+
+            for t in 0..tracks {
+                let gain = self.gains[t].tick(self.gain_targets[t]);
+                let audible = self.audible[t].tick(self.audible_targets[t]);
+                let pan = self.pans[t].tick(self.pan_targets[t]);
+                let balance = if chans == 2 {
+                    pan_gains(PanLaw::Linear, pan)
+                } else {
+                    (1.0, 1.0)
+                };
+                // Tick each send once per sample, and share it between the channels
+                let mut levels = [0.0; MAX_SENDS];
+                for (s, level) in levels.iter_mut().enumerate().take(self.params.sends.len()) {
+                    *level =
+                        self.send_levels[s * tracks + t].tick(self.send_targets[s * tracks + t]);
+                }
+
+                for c in 0..chans {
+                    let pre = ai[t * chans + c][n] * audible;
+                    let post = pre * gain * if c == 0 { balance.0 } else { balance.1 };
+                    ao[c][n] += post;
+
+                    for (s, send) in self.params.sends.iter().enumerate() {
+                        let tap = if send.pre_fader { pre } else { post };
+                        ao[(s + 1) * chans + c][n] += tap * levels[s];
+                    }
+                }
+            }
+
+            let master = self.master.tick(self.master_target);
+            for buffer in ao.iter_mut().take(chans) {
+                buffer[n] *= master;
+            }
+        }
+    }
+}
+
+impl PortedErased for ChannelMixer {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        Some(&self.audio_inputs)
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        Some(&self.audio_outputs)
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        Some(&self.control_inputs)
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use typenum::{U16, U64};
+
+    use crate::nodes::utils::{gain::db_to_gain, test_utils::NodeRunner};
+
+    use super::{ChannelMixer, ChannelMixerParams, SendParams};
+
+    /// Runs a second of constant input, returning the last sample of each output
+    fn run(
+        mixer: &mut ChannelMixer,
+        runner: &mut NodeRunner<U64, U16>,
+        inputs: &[f32],
+    ) -> Vec<f32> {
+        for (buffer, x) in runner.ai.iter_mut().zip(inputs) {
+            buffer.fill(*x);
+        }
+        runner.render(mixer, 750);
+        runner.ao.iter().map(|x| x[x.len() - 1]).collect()
+    }
+
+    #[test]
+    fn faders_and_solo() {
+        let mut params = ChannelMixerParams::new(3, vec![]);
+        params.tracks[1].gain = -6.0;
+        params.tracks[2].mute = true;
+        let mut mixer = ChannelMixer::new(1, params);
+        let mut runner = NodeRunner::new(&mixer);
+        assert_eq!(runner.ci.len(), 3 * 4 + 1);
+
+        let out = run(&mut mixer, &mut runner, &[1.0, 1.0, 1.0]);
+        assert!((out[0] - (1.0 + db_to_gain(-6.0))).abs() < 1e-4);
+
+        // Soloing the muted track, through its control input, leaves it muted
+        runner.ci[3 * 3 + 2].fill(1.0);
+        let out = run(&mut mixer, &mut runner, &[1.0, 1.0, 1.0]);
+        assert!(out[0].abs() < 1e-4);
+    }
+
+    #[test]
+    fn sends_tap_before_or_after_the_fader() {
+        let sends = vec![
+            SendParams { pre_fader: true },
+            SendParams { pre_fader: false },
+        ];
+        let mut params = ChannelMixerParams::new(1, sends);
+        params.tracks[0].gain = -12.0;
+        params.tracks[0].pan = 1.0;
+        params.tracks[0].sends = vec![0.0, 0.0];
+        let mut mixer = ChannelMixer::new(2, params);
+        let mut runner = NodeRunner::new(&mixer);
+
+        let out = run(&mut mixer, &mut runner, &[1.0, 1.0]);
+        let post = db_to_gain(-12.0);
+        let expected = [0.0, post, 1.0, 1.0, 0.0, post];
+        for (x, y) in out.iter().zip(expected) {
+            assert!((x - y).abs() < 1e-4, "{:?}", out);
+        }
+    }
+}
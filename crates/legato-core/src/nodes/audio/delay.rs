@@ -9,17 +9,20 @@ use crate::{
         buffer::Frame,
         node::{FrameSize, Node},
         port::{
-            AudioInputPort, AudioOutputPort, ControlInputPort, ControlOutputPort, Mono,
+            AudioInputPort, AudioOutputPort, ControlInputPort, ControlOutputPort, Mono, PortMeta,
             PortedErased, Ports, Stereo,
         },
         resources::DelayLineKey,
     },
-    nodes::utils::port_utils::{generate_audio_inputs, generate_audio_outputs},
+    nodes::utils::{
+        filters::OnePoleLp,
+        interp::{Interp, Interpolator},
+        port_utils::{generate_audio_inputs, generate_audio_outputs},
+    },
 };
 
-pub fn lerp(v0: f32, v1: f32, t: f32) -> f32 {
-    (1.0 - t) * v0 + t * v1
-}
+/// Moved to the shared interp module, kept here for existing users
+pub use crate::nodes::utils::interp::lerp;
 
 #[derive(Clone)]
 pub struct DelayLine<N, C>
@@ -41,6 +44,13 @@ where
     fn get_write_pos_erased(&self, channel: usize) -> &usize;
     fn write_block_erased(&mut self, block: &Frame<N>);
     fn get_delay_linear_interp_erased(&self, channel: usize, offset: f32) -> f32;
+    fn get_delay_interp_erased(
+        &self,
+        channel: usize,
+        offset: f32,
+        interp: &mut Interpolator,
+    ) -> f32;
+    fn get_capacity_erased(&self) -> usize;
 }
 
 impl<N, C> DelayLine<N, C>
@@ -58,6 +68,10 @@ where
         }
     }
     #[inline(always)]
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+    #[inline(always)]
     pub fn get_write_pos(&self, channel: usize) -> &usize {
         &self.write_pos[channel]
     }
@@ -83,6 +97,12 @@ where
             self.write_pos[c] = (self.write_pos[c] + N::USIZE) % self.capacity;
         }
     }
+    /// Writes a single sample, for when a node needs feedback shorter than a block
+    #[inline(always)]
+    pub fn write_sample(&mut self, channel: usize, x: f32) {
+        self.buffers[channel][self.write_pos[channel]] = x;
+        self.write_pos[channel] = (self.write_pos[channel] + 1) % self.capacity;
+    }
     /// This uses f32 sample indexes, as we allow for interpolated values
     #[inline(always)]
     pub fn get_delay_linear_interp(&self, channel: usize, offset: f32) -> f32 {
@@ -102,6 +122,12 @@ where
             read_pos - pos_floor as f32,
         )
     }
+    /// Like the linear read, but with any of the interpolation modes
+    #[inline(always)]
+    pub fn get_delay_interp(&self, channel: usize, offset: f32, interp: &mut Interpolator) -> f32 {
+        let read_pos = (self.write_pos[channel] as f32 - offset).rem_euclid(self.capacity as f32);
+        interp.read_wrapped(&self.buffers[channel], read_pos)
+    }
 }
 
 impl<N, C> DelayLineErased<N> for DelayLine<N, C>
@@ -115,6 +141,17 @@ where
     fn get_write_pos_erased(&self, channel: usize) -> &usize {
         self.get_write_pos(channel)
     }
+    fn get_delay_interp_erased(
+        &self,
+        channel: usize,
+        offset: f32,
+        interp: &mut Interpolator,
+    ) -> f32 {
+        self.get_delay_interp(channel, offset, interp)
+    }
+    fn get_capacity_erased(&self) -> usize {
+        self.get_capacity()
+    }
     fn write_block_erased(&mut self, block: &Frame<N>) {
         self.write_block(block)
     }
@@ -181,6 +218,12 @@ where
     }
 }
 
+/// Reads a delay line, with a delay time per channel.
+///
+/// Each channel has a "time" input at both audio and control rate, in
+/// milliseconds, that is added to the base delay time. The total time is
+/// smoothed with a one pole filter so that changes glide rather than jump,
+/// and clamped to what the delay line can actually hold.
 pub struct DelayRead<AF, Ao>
 where
     AF: FrameSize,
@@ -188,7 +231,12 @@ where
 {
     delay_line_key: DelayLineKey,
     delay_times: GenericArray<Duration, Ao>, // Different times for each channel if desired
-    ports: Ports<U0, Ao, U0, U0>,
+    smoothing: Duration,
+    // Smoothed delay times, in samples
+    smoothers: GenericArray<OnePoleLp, Ao>,
+    smoothing_fs: f32,
+    interpolators: GenericArray<Interpolator, Ao>,
+    ports: Ports<Ao, Ao, Ao, U0>,
     phantom: PhantomData<AF>,
 }
 impl<AF, Ao> DelayRead<AF, Ao>
@@ -196,7 +244,12 @@ where
     AF: FrameSize,
     Ao: ArrayLength,
 {
-    pub fn new(delay_line_key: DelayLineKey, delay_times: Vec<Duration>) -> Self {
+    pub fn new(
+        delay_line_key: DelayLineKey,
+        delay_times: Vec<Duration>,
+        smoothing: Duration,
+        interp: Interp,
+    ) -> Self {
         let delay_read_times = GenericArray::<Duration, Ao>::generate(|i| {
             delay_times
                 .get(i)
@@ -204,13 +257,31 @@ where
                 .unwrap_or_else(|| Duration::from_millis(200))
         });
 
+        let audio_inputs = GenericArray::generate(|i| AudioInputPort {
+            meta: PortMeta {
+                name: time_port_name::<Ao>(i),
+                index: i,
+            },
+        });
+        let control_inputs = GenericArray::generate(|i| ControlInputPort {
+            meta: PortMeta {
+                name: time_port_name::<Ao>(i),
+                index: i,
+            },
+        });
+
         Self {
             delay_line_key,
             delay_times: delay_read_times,
+            smoothing,
+            smoothers: GenericArray::generate(|_| OnePoleLp::default()),
+            // Zero so the first block sets up the smoothers
+            smoothing_fs: 0.0,
+            interpolators: GenericArray::generate(|_| Interpolator::new(interp)),
             ports: Ports {
-                audio_inputs: None,
+                audio_inputs: Some(audio_inputs),
                 audio_outputs: Some(generate_audio_outputs()),
-                control_inputs: None, // TODO: modulate delay times per channel
+                control_inputs: Some(control_inputs),
                 control_outputs: None,
             },
             phantom: PhantomData::<AF>,
@@ -218,6 +289,14 @@ where
     }
 }
 
+fn time_port_name<Ao: ArrayLength>(i: usize) -> &'static str {
+    match (Ao::USIZE, i) {
+        (2, 0) => "time_l",
+        (2, _) => "time_r",
+        _ => "time",
+    }
+}
+
 impl<AF, CF, Ao> Node<AF, CF> for DelayRead<AF, Ao>
 where
     AF: FrameSize,
@@ -227,18 +306,49 @@ where
     fn process(
         &mut self,
         ctx: &mut AudioContext<AF>,
-        _: &Frame<AF>,
+        ai: &Frame<AF>,
         ao: &mut Frame<AF>,
-        _: &Frame<CF>,
+        ci: &Frame<CF>,
         _: &mut Frame<CF>,
     ) {
+        debug_assert_eq!(Ao::USIZE, ai.len());
         debug_assert_eq!(Ao::USIZE, ao.len());
+        debug_assert_eq!(Ao::USIZE, ci.len());
+
+        let fs = ctx.get_sample_rate();
+        let ms_to_samples = fs / 1000.0;
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        // We read the block after it has been written, so the interpolation can't reach
+        // past the newest sample, or further back than the line holds
+        let (before, after) = self.interpolators[0].interp().reach();
+        let min_delay = after.saturating_sub(1) as f32;
+        let max_delay = (ctx
+            .get_delay_capacity(self.delay_line_key)
+            .saturating_sub(AF::USIZE + 2 + before) as f32)
+            .max(min_delay);
+
+        if fs != self.smoothing_fs {
+            for (smoother, time) in self.smoothers.iter_mut().zip(self.delay_times.iter()) {
+                smoother.set_time(self.smoothing, fs);
+                // Start at the base time, rather than gliding in from zero
+                if self.smoothing_fs == 0.0 {
+                    smoother.reset((time.as_secs_f32() * fs).min(max_delay));
+                }
+            }
+            self.smoothing_fs = fs;
+        }
+
         for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
             for c in 0..Ao::USIZE {
-                let offset = (self.delay_times[c].as_secs_f32() * ctx.get_sample_rate())
-                    + (AF::USIZE - n) as f32;
-                // Read delay line based on per channel delay time. Must cast to sample index.
-                ao[c][n] = ctx.get_delay_linear_interp(self.delay_line_key, c, offset)
+                let target =
+                    self.delay_times[c].as_secs_f32() * fs + (ai[c][n] + ci[c][m]) * ms_to_samples;
+                let delay = self.smoothers[c].tick(target.clamp(min_delay, max_delay));
+                let offset = delay + (AF::USIZE - n) as f32;
+                // Read delay line based on per channel delay time
+                ao[c][n] =
+                    ctx.get_delay_interp(self.delay_line_key, c, offset, &mut self.interpolators[c])
             }
         }
     }
@@ -268,3 +378,59 @@ pub type DelayReadStereo<AF> = DelayRead<AF, Stereo>;
 
 pub type DelayWriteMono = DelayWrite<Mono>;
 pub type DelayWriteStereo = DelayWrite<Stereo>;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use typenum::{U1, U16, U64};
+
+    use crate::{
+        engine::{buffer::Buffer, node::Node},
+        nodes::utils::{
+            interp::Interp,
+            test_utils::{FS, NodeRunner, context},
+        },
+    };
+
+    use super::{DelayLine, DelayReadMono, DelayWriteMono};
+
+    /// Writes an impulse, and returns where it shows up in the read output
+    fn impulse_position(base: Duration, time_ms: f32, capacity: usize) -> Option<usize> {
+        let mut ctx = context();
+        let key = ctx.add_delay_line(Box::new(DelayLine::<U64, U1>::new(capacity)));
+
+        let mut write = DelayWriteMono::new(key);
+        let mut read = DelayReadMono::<U64>::new(key, vec![base], Duration::ZERO, Interp::Linear);
+
+        // The write and read share the context, so the runner drives the read
+        let mut runner = NodeRunner::with_context(ctx, &read);
+        runner.ci[0].fill(time_ms);
+        let mut input = vec![Buffer::<U64>::silent(); 1];
+
+        let mut rendered = Vec::new();
+        for block in 0..32 {
+            input[0][0] = if block == 0 { 1.0 } else { 0.0 };
+            let (ctx, ci, co) = (&mut runner.ctx, &runner.ci, &mut runner.co);
+            Node::<U64, U16>::process(&mut write, ctx, &input, &mut [], ci, co);
+            runner.process(&mut read);
+            rendered.extend_from_slice(&runner.ao[0]);
+        }
+        rendered.iter().position(|x| *x > 0.5)
+    }
+
+    #[test]
+    fn control_input_offsets_the_delay_time() {
+        let base = Duration::from_secs_f32(100.0 / FS);
+        assert_eq!(impulse_position(base, 0.0, 4096), Some(100));
+        // 1ms is 48 samples
+        assert_eq!(impulse_position(base, 1.0, 4096), Some(148));
+    }
+
+    #[test]
+    fn delay_time_is_clamped_to_the_capacity() {
+        // Asking for far more than the line holds reads the oldest sample instead
+        let position = impulse_position(Duration::from_secs(10), 0.0, 1024);
+        assert_eq!(position, Some(1024 - 64 - 2));
+    }
+}
@@ -0,0 +1,543 @@
+use std::{sync::Arc, time::Duration};
+
+use assert_no_alloc::permit_alloc;
+use generic_array::{ArrayLength, GenericArray, sequence::GenericSequence};
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        midi::MidiMessage,
+        node::{FrameSize, Node},
+        port::*,
+        resources::{SampleKey, audio_sample::AudioSample},
+    },
+    nodes::{
+        audio::panner::{PanLaw, pan_gains},
+        utils::{
+            gain::db_to_gain,
+            interp::{Interp, Interpolator},
+            port_utils::generate_audio_outputs,
+        },
+    },
+};
+
+pub const MAX_SLOTS: usize = 16;
+
+/// How long a choked voice takes to fade out
+const CHOKE: Duration = Duration::from_millis(5);
+
+// Port names have to be static, so these are spelled out up to the max slot count
+const TRIG_NAMES: [&str; MAX_SLOTS] = [
+    "trig_0", "trig_1", "trig_2", "trig_3", "trig_4", "trig_5", "trig_6", "trig_7", "trig_8",
+    "trig_9", "trig_10", "trig_11", "trig_12", "trig_13", "trig_14", "trig_15",
+];
+
+/// A velocity layer, played for hits at or above its min velocity
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrumLayer<S> {
+    pub sample: S,
+    pub min_velocity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrumSlotParams {
+    /// The MIDI note that plays the slot, if any
+    pub note: Option<u8>,
+    /// In dB
+    pub gain: f32,
+    /// -1.0 to 1.0. Only used for stereo.
+    pub pan: f32,
+    /// In semitones
+    pub pitch: f32,
+    /// Slots in the same choke group cut each other off, i.e an open and closed hat
+    pub choke: Option<u8>,
+    /// How many hits of this slot can ring at once. Past this, the oldest is cut.
+    pub voices: usize,
+}
+
+impl Default for DrumSlotParams {
+    fn default() -> Self {
+        Self {
+            note: None,
+            gain: 0.0,
+            pan: 0.0,
+            pitch: 0.0,
+            choke: None,
+            voices: 4,
+        }
+    }
+}
+
+/// A slot, where S is how the samples are found. The builder takes
+/// sample names, and the node takes the keys they resolve to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrumSlot<S> {
+    pub layers: Vec<DrumLayer<S>>,
+    pub params: DrumSlotParams,
+}
+
+#[derive(Debug, Clone)]
+struct DrumVoice<C>
+where
+    C: ArrayLength,
+{
+    active: bool,
+    // Into the flattened layers of every slot
+    layer: usize,
+    pos: f64,
+    // Counts up on every hit, so the oldest voice can be stolen
+    started: u64,
+    gains: GenericArray<f32, C>,
+    fade: f32,
+    fade_step: f32,
+    interpolators: GenericArray<Interpolator, C>,
+}
+
+/// A drum rack, playing one shot samples from a set of slots.
+///
+/// Slots are played by their MIDI note, or by their "trig_n" control input
+/// rising over 0.5. MIDI hits use the note velocity, which scales the level
+/// and picks the layer, and trigger inputs hit at full velocity.
+///
+/// Every slot has its own voices, made up front, so overlapping hits ring out
+/// without the audio thread allocating. Each output channel reads the matching
+/// channel of the sample, or the last one if the sample has fewer.
+pub struct DrumRack<C>
+where
+    C: ArrayLength,
+{
+    slots: Vec<DrumSlotParams>,
+    // The layers of every slot, flattened, and where each slot's layers start
+    layers: Vec<DrumLayer<SampleKey>>,
+    layer_offsets: Vec<usize>,
+    // The voices of every slot, flattened the same way
+    voices: Vec<DrumVoice<C>>,
+    voice_offsets: Vec<usize>,
+    // The samples for each layer, loaded once per block
+    loaded: Vec<Option<Arc<AudioSample>>>,
+    channel: Option<u8>,
+    hits: u64,
+    last_trigs: Vec<f32>,
+    ports: Ports<U0, C, U0, U0>,
+    control_inputs: Vec<ControlInputPort>,
+}
+
+impl<C> DrumRack<C>
+where
+    C: ArrayLength,
+{
+    /// Listens to the given MIDI channel, or all channels with None
+    pub fn new(slots: Vec<DrumSlot<SampleKey>>, channel: Option<u8>, interp: Interp) -> Self {
+        assert!(
+            slots.len() <= MAX_SLOTS,
+            "A drum rack can have up to {} slots",
+            MAX_SLOTS
+        );
+
+        let voice = DrumVoice {
+            active: false,
+            layer: 0,
+            pos: 0.0,
+            started: 0,
+            gains: GenericArray::generate(|_| 0.0),
+            fade: 1.0,
+            fade_step: 0.0,
+            interpolators: GenericArray::generate(|_| Interpolator::new(interp)),
+        };
+
+        let mut layers = Vec::new();
+        let mut layer_offsets = Vec::with_capacity(slots.len() + 1);
+        let mut voices = Vec::new();
+        let mut voice_offsets = Vec::with_capacity(slots.len() + 1);
+        let mut params = Vec::with_capacity(slots.len());
+        for mut slot in slots {
+            // Quietest first, for the layer lookup
+            slot.layers
+                .sort_by(|a, b| a.min_velocity.total_cmp(&b.min_velocity));
+            layer_offsets.push(layers.len());
+            layers.extend(slot.layers);
+            voice_offsets.push(voices.len());
+            voices.extend(std::iter::repeat_n(
+                voice.clone(),
+                slot.params.voices.max(1),
+            ));
+            params.push(slot.params);
+        }
+        layer_offsets.push(layers.len());
+        voice_offsets.push(voices.len());
+
+        let control_inputs = TRIG_NAMES[..params.len()]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| ControlInputPort {
+                meta: PortMeta { name, index },
+            })
+            .collect();
+
+        Self {
+            last_trigs: vec![0.0; params.len()],
+            slots: params,
+            loaded: Vec::with_capacity(layers.len()),
+            layers,
+            layer_offsets,
+            voices,
+            voice_offsets,
+            channel,
+            hits: 0,
+            ports: Ports {
+                audio_inputs: None,
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+            control_inputs,
+        }
+    }
+    fn hit(&mut self, slot: usize, velocity: f32, fs: f32) {
+        let params = self.slots[slot];
+        let velocity = velocity.clamp(0.0, 1.0);
+
+        // Cut off the rest of the choke group
+        if let Some(group) = params.choke {
+            let choke_step = 1.0 / (CHOKE.as_secs_f32() * fs).max(1.0);
+            for other in 0..self.slots.len() {
+                if other == slot || self.slots[other].choke != Some(group) {
+                    continue;
+                }
+                let voices = self.voice_offsets[other]..self.voice_offsets[other + 1];
+                for voice in self.voices[voices].iter_mut().filter(|v| v.active) {
+                    voice.fade_step = choke_step;
+                }
+            }
+        }
+
+        let layers = &self.layers[self.layer_offsets[slot]..self.layer_offsets[slot + 1]];
+        if layers.is_empty() {
+            return;
+        }
+        // The loudest layer the velocity reaches, or the quietest if it reaches none
+        let layer = layers
+            .iter()
+            .rposition(|l| l.min_velocity <= velocity)
+            .unwrap_or(0);
+
+        // A free voice, or the oldest one
+        let voices = &mut self.voices[self.voice_offsets[slot]..self.voice_offsets[slot + 1]];
+        let voice = match voices.iter().position(|v| !v.active) {
+            Some(i) => &mut voices[i],
+            None => voices.iter_mut().min_by_key(|v| v.started).unwrap(),
+        };
+
+        let level = db_to_gain(params.gain) * velocity;
+        if C::USIZE == 2 {
+            let (l, r) = pan_gains(PanLaw::ConstantPower, params.pan);
+            // Scaled, so a centered slot is as loud as it is in mono
+            voice.gains[0] = level * l * std::f32::consts::SQRT_2;
+            voice.gains[1] = level * r * std::f32::consts::SQRT_2;
+        } else {
+            voice.gains.iter_mut().for_each(|g| *g = level);
+        }
+        voice.active = true;
+        voice.layer = self.layer_offsets[slot] + layer;
+        voice.pos = 0.0;
+        voice.started = self.hits;
+        voice.fade = 1.0;
+        voice.fade_step = 0.0;
+        self.hits += 1;
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for DrumRack<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        _: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        for chan in ao.iter_mut() {
+            chan.fill(0.0);
+        }
+
+        let fs = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        // The loads allocate a little, like in the sampler. The Vec has room for every layer.
+        permit_alloc(|| {
+            self.loaded.clear();
+            for layer in self.layers.iter() {
+                self.loaded.push(ctx.get_sample(layer.sample));
+            }
+        });
+
+        let events = ctx.get_midi_events();
+        let mut cursor = 0;
+        for n in 0..AF::USIZE {
+            // Apply everything scheduled up to and including this sample
+            while cursor < events.len() && events[cursor].offset <= n {
+                let message = events[cursor].message;
+                cursor += 1;
+                let MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                } = message
+                else {
+                    continue;
+                };
+                if self.channel.is_some_and(|c| c != channel) {
+                    continue;
+                }
+                for slot in 0..self.slots.len() {
+                    if self.slots[slot].note == Some(note) {
+                        self.hit(slot, velocity, fs);
+                    }
+                }
+            }
+
+            let m = (n / chunk).min(CF::USIZE - 1);
+            for slot in 0..self.slots.len() {
+                let trig = ci.get(slot).map_or(0.0, |x| x[m]);
+                if self.last_trigs[slot] <= 0.5 && trig > 0.5 {
+                    self.hit(slot, 1.0, fs);
+                }
+                self.last_trigs[slot] = trig;
+            }
+
+            for (slot, params) in self.slots.iter().enumerate() {
+                let step = 2.0_f64.powf(params.pitch as f64 / 12.0);
+                let voices =
+                    &mut self.voices[self.voice_offsets[slot]..self.voice_offsets[slot + 1]];
+                for voice in voices.iter_mut().filter(|v| v.active) {
+                    let Some(sample) = &self.loaded[voice.layer] else {
+                        voice.active = false;
+                        continue;
+                    };
+                    let buf = sample.data();
+                    let len = buf.first().map_or(0, |chan| chan.len());
+                    if voice.pos >= len as f64 {
+                        voice.active = false;
+                        continue;
+                    }
+
+                    let index = voice.pos.floor();
+                    let t = (voice.pos - index) as f32;
+                    for (c, out) in ao.iter_mut().enumerate() {
+                        let chan = &buf[c.min(buf.len() - 1)];
+                        let x = voice.interpolators[c].read(index as isize, t, |i| {
+                            if i >= 0 && (i as usize) < len {
+                                chan[i as usize]
+                            } else {
+                                0.0
+                            }
+                        });
+                        out[n] += x * voice.gains[c] * voice.fade;
+                    }
+
+                    voice.pos += step;
+                    if voice.fade_step > 0.0 {
+                        voice.fade -= voice.fade_step;
+                        voice.active = voice.fade > 0.0;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C> PortedErased for DrumRack<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        None
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        Some(&self.control_inputs)
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type DrumRackMono = DrumRack<Mono>;
+pub type DrumRackStereo = DrumRack<Stereo>;
+pub type DrumRackMC<C> = DrumRack<C>;
+
+#[cfg(test)]
+mod test {
+    use typenum::{U16, U64};
+
+    use super::*;
+    use crate::{
+        engine::{buffer::Buffer, midi::MidiEvent},
+        nodes::utils::test_utils::{NodeRunner, add_sample, context},
+    };
+
+    fn slot(layers: Vec<DrumLayer<SampleKey>>, params: DrumSlotParams) -> DrumSlot<SampleKey> {
+        DrumSlot { layers, params }
+    }
+
+    fn layer(sample: SampleKey) -> DrumLayer<SampleKey> {
+        DrumLayer {
+            sample,
+            min_velocity: 0.0,
+        }
+    }
+
+    /// Runs a block, with a value for each trigger input
+    fn run<C: ArrayLength>(
+        rack: &mut DrumRack<C>,
+        runner: &mut NodeRunner<U64, U16>,
+        trigs: &[f32],
+    ) -> Vec<Buffer<U64>> {
+        runner.fit(rack);
+        for (n, input) in runner.ci.iter_mut().enumerate() {
+            input.fill(trigs.get(n).copied().unwrap_or(0.0));
+        }
+        runner.process(rack);
+        runner.ao.clone()
+    }
+
+    #[test]
+    fn hits_overlap_and_steal_the_oldest() {
+        let mut ctx = context();
+        let ones = add_sample(&mut ctx, vec![vec![1.0; 1000]]);
+        let params = DrumSlotParams {
+            voices: 2,
+            ..Default::default()
+        };
+        let mut rack =
+            DrumRackMono::new(vec![slot(vec![layer(ones)], params)], None, Interp::Linear);
+        assert_eq!(
+            rack.get_control_inputs().map(|x| x[0].meta.name),
+            Some("trig_0")
+        );
+        let mut runner = NodeRunner::with_context(ctx, &rack);
+
+        // Each hit adds another voice, up to the voice count
+        let mut levels = vec![];
+        for _ in 0..3 {
+            levels.push(run(&mut rack, &mut runner, &[1.0])[0][63]);
+            levels.push(run(&mut rack, &mut runner, &[0.0])[0][63]);
+        }
+        assert_eq!(levels, [1.0, 1.0, 2.0, 2.0, 2.0, 2.0]);
+
+        // And stop at the end of the sample, the stolen voice last
+        let out: Vec<f32> = (0..16)
+            .map(|_| run(&mut rack, &mut runner, &[0.0])[0][0])
+            .collect();
+        assert_eq!(out[..12], [2.0; 12]);
+        assert_eq!(out[12..14], [1.0; 2]);
+        assert_eq!(out[14..], [0.0; 2]);
+    }
+
+    fn note<C: ArrayLength>(
+        rack: &mut DrumRack<C>,
+        runner: &mut NodeRunner<U64, U16>,
+        note: u8,
+        velocity: f32,
+        channel: u8,
+    ) -> Vec<Buffer<U64>> {
+        runner.ctx.push_midi_event(MidiEvent {
+            offset: 0,
+            message: MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            },
+        });
+        let out = run(rack, runner, &[]);
+        runner.ctx.clear_midi_events();
+        out
+    }
+
+    #[test]
+    fn notes_pick_layers_and_choke() {
+        let mut ctx = context();
+        let soft = add_sample(&mut ctx, vec![vec![1.0; 48_000]]);
+        let hard = add_sample(&mut ctx, vec![vec![-1.0; 48_000]]);
+
+        let snare = || {
+            let layers = vec![
+                DrumLayer {
+                    sample: hard,
+                    min_velocity: 0.5,
+                },
+                layer(soft),
+            ];
+            let params = DrumSlotParams {
+                note: Some(38),
+                gain: -6.0,
+                pan: 1.0,
+                ..Default::default()
+            };
+            DrumRackStereo::new(vec![slot(layers, params)], Some(0), Interp::Linear)
+        };
+        // Scaled by the gain and velocity, and panned hard right
+        let level = db_to_gain(-6.0) * std::f32::consts::SQRT_2;
+        let mut runner = NodeRunner::with_context(ctx, &snare());
+
+        let out = note(&mut snare(), &mut runner, 38, 0.25, 0);
+        assert!(out[0][63].abs() < 1e-6);
+        assert!((out[1][63] - 0.25 * level).abs() < 1e-5);
+
+        let out = note(&mut snare(), &mut runner, 38, 0.75, 0);
+        assert!((out[1][63] + 0.75 * level).abs() < 1e-5);
+
+        // Other channels and notes are ignored
+        let out = note(&mut snare(), &mut runner, 38, 1.0, 3);
+        assert_eq!(out[1][63], 0.0);
+        let out = note(&mut snare(), &mut runner, 40, 1.0, 0);
+        assert_eq!(out[1][63], 0.0);
+
+        // An open hat, cut off by the closed one
+        let hat = DrumSlotParams {
+            choke: Some(1),
+            ..Default::default()
+        };
+        let half = add_sample(&mut runner.ctx, vec![vec![0.5; 48_000]]);
+        let slots = vec![
+            slot(
+                vec![layer(soft)],
+                DrumSlotParams {
+                    note: Some(46),
+                    ..hat
+                },
+            ),
+            slot(
+                vec![layer(half)],
+                DrumSlotParams {
+                    note: Some(42),
+                    ..hat
+                },
+            ),
+        ];
+        let mut rack = DrumRackMono::new(slots, None, Interp::Linear);
+        assert_eq!(note(&mut rack, &mut runner, 46, 1.0, 0)[0][63], 1.0);
+
+        // 5ms is 240 samples
+        let out = note(&mut rack, &mut runner, 42, 1.0, 0);
+        assert!((out[0][63] - (1.0 - 63.0 / 240.0) - 0.5).abs() < 1e-4);
+        for _ in 0..3 {
+            run(&mut rack, &mut runner, &[]);
+        }
+        assert_eq!(run(&mut rack, &mut runner, &[])[0][0], 0.5);
+
+        // But it doesn't choke itself
+        assert_eq!(note(&mut rack, &mut runner, 42, 1.0, 0)[0][63], 1.0);
+    }
+}
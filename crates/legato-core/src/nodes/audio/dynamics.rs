@@ -0,0 +1,569 @@
+use std::{collections::VecDeque, ops::Mul, time::Duration};
+
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{Prod, U0, U1, U2};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::{
+        audio::delay::DelayLine,
+        utils::{
+            filters::{TruePeak, time_coeff},
+            gain::{db_to_gain, gain_to_db},
+            interp::{Interp, Interpolator},
+            port_utils::generate_audio_outputs,
+        },
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DynamicsMode {
+    /// Turns down anything over the threshold by the ratio
+    Compressor,
+    /// A brickwall compressor. The gain is held and ramped over the look-ahead
+    /// instead of using the attack, so nothing gets over the threshold.
+    Limiter {
+        lookahead: Duration,
+        /// Also catch the peaks between samples, at the cost of a few samples of latency
+        true_peak: bool,
+    },
+    /// Turns down anything under the threshold by the ratio, down to the range
+    Expander,
+    /// Mutes anything under the threshold, down to the range
+    Gate {
+        /// How long the gate stays open after the signal drops
+        hold: Duration,
+        /// How far under the threshold the signal has to drop to close the gate, in dB
+        hysteresis: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Detection {
+    #[default]
+    Peak,
+    Rms,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynamicsParams {
+    /// In dB
+    pub threshold: f32,
+    /// i.e 4.0 for 4:1. Not used by the limiter or gate.
+    pub ratio: f32,
+    /// The width of the soft knee in dB, 0.0 is a hard knee
+    pub knee: f32,
+    pub attack: Duration,
+    pub release: Duration,
+    /// In dB
+    pub makeup: f32,
+    /// The most gain reduction for the expander and gate, in dB
+    pub range: f32,
+    pub detection: Detection,
+    /// How long the RMS detector averages over
+    pub rms_window: Duration,
+    /// 0.0 keys each channel on its own, 1.0 keys every channel off the loudest
+    pub link: f32,
+    /// Key off the sidechain inputs rather than the main inputs
+    pub sidechain: bool,
+}
+
+impl Default for DynamicsParams {
+    fn default() -> Self {
+        Self {
+            threshold: -18.0,
+            ratio: 4.0,
+            knee: 6.0,
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(100),
+            makeup: 0.0,
+            range: 60.0,
+            detection: Detection::Peak,
+            rms_window: Duration::from_millis(10),
+            link: 1.0,
+            sidechain: false,
+        }
+    }
+}
+
+/// Holds the lowest gain over the look-ahead, then ramps to it with a moving
+/// average the same length, so the gain is all the way down by the time the
+/// peak comes out of the delay.
+#[derive(Clone)]
+struct Lookahead {
+    // (sample index, gain), increasing in gain, so the front is the lowest
+    queue: VecDeque<(usize, f32)>,
+    window: Vec<f32>,
+    pos: usize,
+    sum: f64,
+    released: f32,
+}
+
+impl Lookahead {
+    fn new(len: usize) -> Self {
+        let len = len.max(1);
+        Self {
+            queue: VecDeque::with_capacity(len + 1),
+            window: vec![0.0; len],
+            pos: 0,
+            sum: 0.0,
+            released: 0.0,
+        }
+    }
+    /// Takes the target gain in dB, and returns the gain for the sample len - 1 samples ago
+    #[inline(always)]
+    fn tick(&mut self, index: usize, target: f32, release: f32) -> f32 {
+        let len = self.window.len();
+        while self.queue.back().is_some_and(|(_, g)| *g >= target) {
+            self.queue.pop_back();
+        }
+        self.queue.push_back((index, target));
+        while self
+            .queue
+            .front()
+            .is_some_and(|(i, _)| index.wrapping_sub(*i) >= len)
+        {
+            self.queue.pop_front();
+        }
+        let held = self.queue.front().map_or(target, |(_, g)| *g);
+
+        self.released = if held < self.released {
+            held
+        } else {
+            held + release * (self.released - held)
+        };
+
+        self.sum += (self.released - self.window[self.pos]) as f64;
+        self.window[self.pos] = self.released;
+        self.pos = (self.pos + 1) % len;
+
+        (self.sum / len as f64) as f32
+    }
+}
+
+/// The main inputs, followed by the sidechain inputs
+fn dynamics_audio_inputs<C, Ai>() -> GenericArray<AudioInputPort, Ai>
+where
+    C: ArrayLength,
+    Ai: ArrayLength,
+{
+    GenericArray::generate(|i| {
+        let sidechain = i >= C::USIZE;
+        AudioInputPort {
+            meta: PortMeta {
+                name: match (C::USIZE, i % C::USIZE, sidechain) {
+                    (2, 0, false) => "l",
+                    (2, 1, false) => "r",
+                    (2, 0, true) => "sc_l",
+                    (2, 1, true) => "sc_r",
+                    (_, _, false) => "in",
+                    (_, _, true) => "sc",
+                },
+                index: i,
+            },
+        }
+    })
+}
+
+/// Compressor, limiter, expander and gate, with a channel count of C.
+///
+/// There are C main inputs followed by C sidechain inputs. The sidechain
+/// is only used for the key when the params ask for it, so it can be left
+/// unconnected otherwise. The "gr" control output is the gain reduction
+/// of the most reduced channel, in dB.
+pub struct Dynamics<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Mul<U2> + Send + Sync + 'static,
+    Prod<C, U2>: ArrayLength,
+{
+    mode: DynamicsMode,
+    params: DynamicsParams,
+    // Coefficients, built for the current sample rate
+    coeffs_fs: f32,
+    attack: f32,
+    release: f32,
+    rms: f32,
+    hold: usize,
+    latency: usize,
+    // Detector and gain state, per channel
+    mean_square: GenericArray<f32, C>,
+    true_peak: GenericArray<TruePeak, C>,
+    levels: GenericArray<f32, C>,
+    gain: GenericArray<f32, C>,
+    gate_open: GenericArray<bool, C>,
+    hold_count: GenericArray<usize, C>,
+    lookahead: Vec<Lookahead>,
+    index: usize,
+    // Delays the main inputs for the limiter look-ahead
+    line: DelayLine<AF, C>,
+    interpolator: Interpolator,
+    ports: Ports<Prod<C, U2>, C, U0, U1>,
+}
+
+impl<AF, C> Dynamics<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Mul<U2> + Send + Sync + 'static,
+    Prod<C, U2>: ArrayLength,
+{
+    pub fn new(mode: DynamicsMode, params: DynamicsParams, sample_rate: f32) -> Self {
+        let capacity = match mode {
+            DynamicsMode::Limiter { lookahead, .. } => {
+                (lookahead.as_secs_f32() * sample_rate) as usize + TruePeak::LATENCY + 2
+            }
+            _ => 1,
+        };
+        let mut node = Self {
+            mode,
+            params,
+            coeffs_fs: 0.0,
+            attack: 0.0,
+            release: 0.0,
+            rms: 0.0,
+            hold: 0,
+            latency: 0,
+            mean_square: GenericArray::generate(|_| 0.0),
+            true_peak: GenericArray::generate(|_| TruePeak::default()),
+            levels: GenericArray::generate(|_| 0.0),
+            gain: GenericArray::generate(|_| 0.0),
+            gate_open: GenericArray::generate(|_| false),
+            hold_count: GenericArray::generate(|_| 0),
+            lookahead: Vec::new(),
+            index: 0,
+            line: DelayLine::new(capacity),
+            interpolator: Interpolator::new(Interp::Truncate),
+            ports: Ports {
+                audio_inputs: Some(dynamics_audio_inputs::<C, Prod<C, U2>>()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: Some(arr![ControlOutputPort {
+                    meta: PortMeta {
+                        name: "gr",
+                        index: 0
+                    },
+                }]),
+            },
+        };
+        node.set_coeffs(sample_rate);
+        node
+    }
+    fn set_coeffs(&mut self, fs: f32) {
+        self.attack = time_coeff(self.params.attack, fs);
+        self.release = time_coeff(self.params.release, fs);
+        self.rms = time_coeff(self.params.rms_window, fs);
+
+        match self.mode {
+            DynamicsMode::Limiter {
+                lookahead,
+                true_peak,
+            } => {
+                // The line was sized at construction, so stay inside of it if the rate goes up
+                let max = self.line.get_capacity() - TruePeak::LATENCY - 2;
+                let samples = ((lookahead.as_secs_f32() * fs) as usize).min(max);
+                self.latency = samples + if true_peak { TruePeak::LATENCY } else { 0 };
+                self.lookahead = (0..C::USIZE).map(|_| Lookahead::new(samples + 1)).collect();
+            }
+            DynamicsMode::Gate { hold, .. } => {
+                self.hold = (hold.as_secs_f32() * fs) as usize;
+            }
+            _ => (),
+        }
+        self.coeffs_fs = fs;
+    }
+    /// The static curve, from the key level to the target gain, both in dB
+    #[inline(always)]
+    fn target_gain(&self, level: f32, c: usize) -> f32 {
+        let DynamicsParams {
+            threshold,
+            ratio,
+            knee,
+            range,
+            ..
+        } = self.params;
+        let over = level - threshold;
+        let ratio = ratio.max(1.0);
+
+        match self.mode {
+            DynamicsMode::Compressor | DynamicsMode::Limiter { .. } => {
+                let slope = match self.mode {
+                    DynamicsMode::Limiter { .. } => -1.0,
+                    _ => 1.0 / ratio - 1.0,
+                };
+                if 2.0 * over <= -knee {
+                    0.0
+                } else if 2.0 * over < knee {
+                    slope * (over + knee / 2.0).powi(2) / (2.0 * knee)
+                } else {
+                    slope * over
+                }
+            }
+            DynamicsMode::Expander => {
+                let slope = ratio - 1.0;
+                let gain = if 2.0 * over >= knee {
+                    0.0
+                } else if 2.0 * over > -knee {
+                    -slope * (over - knee / 2.0).powi(2) / (2.0 * knee)
+                } else {
+                    slope * over
+                };
+                gain.max(-range)
+            }
+            DynamicsMode::Gate { .. } => {
+                if self.gate_open[c] {
+                    0.0
+                } else {
+                    -range
+                }
+            }
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Dynamics<AF, C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength + Mul<U2> + Send + Sync + 'static,
+    Prod<C, U2>: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        co: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE * 2);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.coeffs_fs {
+            self.set_coeffs(fs);
+        }
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let makeup = self.params.makeup;
+        let link = self.params.link.clamp(0.0, 1.0);
+        let key_offset = if self.params.sidechain { C::USIZE } else { 0 };
+        let true_peak = matches!(
+            self.mode,
+            DynamicsMode::Limiter {
+                true_peak: true,
+                ..
+            }
+        );
+        // Compressors attack as the gain goes down, expanders and gates as it comes back up
+        let attack_down = matches!(
+            self.mode,
+            DynamicsMode::Compressor | DynamicsMode::Limiter { .. }
+        );
+
+        if let Some(gr) = co.first_mut() {
+            gr.fill(0.0);
+        }
+
+        for n in 0..AF::USIZE {
+            // Detect the level of each channel
+            let mut loudest = 0.0_f32;
+            for c in 0..C::USIZE {
+                let x = ai[key_offset + c][n];
+                let peak = if true_peak {
+                    self.true_peak[c].tick(x)
+                } else {
+                    x.abs()
+                };
+                let level = match self.params.detection {
+                    Detection::Peak => peak,
+                    Detection::Rms => {
+                        let square = peak * peak;
+                        self.mean_square[c] = square + self.rms * (self.mean_square[c] - square);
+                        self.mean_square[c].sqrt()
+                    }
+                };
+                self.levels[c] = level;
+                loudest = loudest.max(level);
+            }
+
+            let mut most_reduction = 0.0_f32;
+            for c in 0..C::USIZE {
+                let level = self.levels[c] + link * (loudest - self.levels[c]);
+                let level = gain_to_db(level);
+
+                if let DynamicsMode::Gate { hysteresis, .. } = self.mode {
+                    if level >= self.params.threshold {
+                        self.gate_open[c] = true;
+                        self.hold_count[c] = self.hold;
+                    } else if level < self.params.threshold - hysteresis.max(0.0) {
+                        if self.hold_count[c] > 0 {
+                            self.hold_count[c] -= 1;
+                        } else {
+                            self.gate_open[c] = false;
+                        }
+                    }
+                }
+
+                let target = self.target_gain(level, c);
+                let gain = match self.mode {
+                    DynamicsMode::Limiter { .. } => {
+                        self.lookahead[c].tick(self.index, target, self.release)
+                    }
+                    _ => {
+                        let current = self.gain[c];
+                        let coeff = if (target < current) == attack_down {
+                            self.attack
+                        } else {
+                            self.release
+                        };
+                        target + coeff * (current - target)
+                    }
+                };
+                self.gain[c] = gain;
+                most_reduction = most_reduction.max(-gain);
+
+                let x = ai[c][n];
+                let dry = if self.latency > 0 {
+                    let delayed =
+                        self.line
+                            .get_delay_interp(c, self.latency as f32, &mut self.interpolator);
+                    self.line.write_sample(c, x);
+                    delayed
+                } else {
+                    x
+                };
+                ao[c][n] = dry * db_to_gain(gain + makeup);
+            }
+
+            if let Some(gr) = co.first_mut() {
+                let m = (n / chunk).min(CF::USIZE - 1);
+                gr[m] = gr[m].max(most_reduction);
+            }
+            self.index = self.index.wrapping_add(1);
+        }
+    }
+}
+
+impl<AF, C> PortedErased for Dynamics<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Mul<U2> + Send + Sync + 'static,
+    Prod<C, U2>: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        self.ports.get_control_outputs()
+    }
+}
+
+pub type DynamicsMono<AF> = Dynamics<AF, Mono>;
+pub type DynamicsStereo<AF> = Dynamics<AF, Stereo>;
+pub type DynamicsMC<AF, C> = Dynamics<AF, C>;
+
+#[cfg(test)]
+mod test {
+    use std::{f32::consts::TAU, time::Duration};
+
+    use typenum::{U64, Unsigned};
+
+    use crate::nodes::utils::{
+        gain::db_to_gain,
+        test_utils::{FS, NodeRunner},
+    };
+
+    use super::{DynamicsMode, DynamicsMono, DynamicsParams, DynamicsStereo};
+
+    #[test]
+    fn compressor_follows_the_ratio() {
+        let params = DynamicsParams {
+            threshold: -20.0,
+            ratio: 4.0,
+            knee: 0.0,
+            ..Default::default()
+        };
+        let mut comp = DynamicsMono::<U64>::new(DynamicsMode::Compressor, params, FS);
+        let mut runner = NodeRunner::new(&comp);
+        runner.ai[0].fill(1.0);
+
+        runner.render(&mut comp, 750);
+        // 20dB over at 4:1 comes out 5dB over, so 15dB of reduction
+        assert!((runner.ao[0][0] - db_to_gain(-15.0)).abs() < 1e-3);
+        assert!((runner.co[0][0] - 15.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn limiter_catches_the_first_peak() {
+        let threshold = -1.0;
+        let params = DynamicsParams {
+            threshold,
+            knee: 0.0,
+            ..Default::default()
+        };
+        let mode = DynamicsMode::Limiter {
+            lookahead: Duration::from_millis(5),
+            true_peak: true,
+        };
+        let mut limiter = DynamicsStereo::<U64>::new(mode, params, FS);
+        let mut runner = NodeRunner::new(&limiter);
+
+        let ceiling = db_to_gain(threshold) + 1e-3;
+        for block in 0..200 {
+            // A loud sine that starts right away, with a quiet right channel
+            let (left, right) = runner.ai.split_at_mut(1);
+            for (n, (l, r)) in left[0].iter_mut().zip(right[0].iter_mut()).enumerate() {
+                *l = 4.0 * (TAU * 997.0 * (block * U64::USIZE + n) as f32 / FS).sin();
+                *r = 0.1 * *l;
+            }
+            runner.process(&mut limiter);
+            assert!(runner.ao[0].iter().all(|x| x.abs() <= ceiling));
+        }
+        // Linked, so the right channel is turned down just as much
+        let right = runner.ao[1].iter().fold(0.0_f32, |acc, x| acc.max(x.abs()));
+        assert!(right < 0.1 * ceiling);
+    }
+
+    #[test]
+    fn gate_closes_on_quiet_signals() {
+        let params = DynamicsParams {
+            threshold: -40.0,
+            range: 40.0,
+            sidechain: true,
+            ..Default::default()
+        };
+        let mode = DynamicsMode::Gate {
+            hold: Duration::from_millis(10),
+            hysteresis: 3.0,
+        };
+        let mut gate = DynamicsMono::<U64>::new(mode, params, FS);
+        let mut runner = NodeRunner::new(&gate);
+        runner.ai[0].fill(0.5);
+
+        let mut run = |key: f32| {
+            runner.ai[1].fill(key);
+            runner.render(&mut gate, 750);
+            (runner.ao[0][0], runner.co[0][0])
+        };
+
+        // The sidechain key is loud, so the gate is open
+        let (open, gr) = run(0.1);
+        assert!((open - 0.5).abs() < 1e-3 && gr < 1e-2);
+
+        // The key drops away, so the gate closes down to the range
+        let (closed, gr) = run(0.0);
+        assert!((closed - 0.5 * db_to_gain(-40.0)).abs() < 1e-4);
+        assert!((gr - 40.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,522 @@
+use std::time::Duration;
+
+use generic_array::{ArrayLength, GenericArray, arr};
+use typenum::{U0, U1, U2};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::port_utils::generate_audio_outputs,
+};
+
+/// The shape of a segment, going from the start level to the target.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EnvelopeCurve {
+    #[default]
+    Linear,
+    /// Fast at the start, slowing into the target, like an analog RC envelope
+    Exponential,
+    /// Positive values are slow then fast, negative values fast then slow.
+    /// Zero is linear.
+    Curve(f32),
+}
+
+impl EnvelopeCurve {
+    /// Maps the progress through a segment, 0.0 to 1.0, to the shaped progress.
+    #[inline(always)]
+    pub fn apply(&self, p: f32) -> f32 {
+        match *self {
+            EnvelopeCurve::Linear => p,
+            EnvelopeCurve::Exponential => EnvelopeCurve::Curve(-5.0).apply(p),
+            EnvelopeCurve::Curve(c) => {
+                if c.abs() < 1e-3 {
+                    p
+                } else {
+                    ((c * p).exp() - 1.0) / (c.exp() - 1.0)
+                }
+            }
+        }
+    }
+}
+
+/// What happens when a new gate or trigger arrives while the envelope is still running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnvelopeMode {
+    /// Restart from zero on every new note
+    #[default]
+    Retrigger,
+    /// Carry on through triggers while the gate is held, i.e from overlapping notes.
+    /// A new gate restarts the first segment from the current level, so there are no clicks.
+    Legato,
+}
+
+/// A breakpoint, moving to the target level over the duration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub target: f32,
+    pub duration: Duration,
+    pub curve: EnvelopeCurve,
+}
+
+impl Segment {
+    pub fn new(target: f32, duration: Duration, curve: EnvelopeCurve) -> Self {
+        Self {
+            target,
+            duration,
+            curve,
+        }
+    }
+}
+
+/// The envelope state machine, shared by the audio and control rate nodes.
+///
+/// While the gate is held, the envelope stops at the end of the sustain
+/// segment, or loops between the loop points. Once released, it jumps to the
+/// segment after the sustain or loop, from wherever the level currently is.
+///
+/// Without a sustain or loop point, the envelope runs through once per trigger.
+///
+/// A rising "trig" starts the envelope without holding it, so with no gate
+/// connected an ADSR plays through attack, decay then release.
+pub struct EnvelopeSegments {
+    segments: Vec<Segment>,
+    sustain: Option<usize>,
+    loop_points: Option<(usize, usize)>,
+    mode: EnvelopeMode,
+    // None when idle or sustaining
+    stage: Option<usize>,
+    start: f32,
+    level: f32,
+    elapsed: f32,
+    held: bool,
+    last_gate: f32,
+    last_trig: f32,
+}
+
+impl EnvelopeSegments {
+    /// Segment indices for the sustain and loop points are clamped to the segments.
+    pub fn new(
+        segments: Vec<Segment>,
+        sustain: Option<usize>,
+        loop_points: Option<(usize, usize)>,
+        mode: EnvelopeMode,
+    ) -> Self {
+        assert!(
+            !segments.is_empty(),
+            "An envelope needs at least one segment"
+        );
+        let last = segments.len() - 1;
+
+        Self {
+            sustain: sustain.map(|x| x.min(last)),
+            loop_points: loop_points
+                .map(|(start, end)| (start.min(last), end.min(last).max(start))),
+            segments,
+            mode,
+            stage: None,
+            start: 0.0,
+            level: 0.0,
+            elapsed: 0.0,
+            held: false,
+            last_gate: 0.0,
+            last_trig: 0.0,
+        }
+    }
+    /// A classic ADSR, with the sustain as a level from 0.0 to 1.0.
+    pub fn adsr(
+        attack: Duration,
+        decay: Duration,
+        sustain: f32,
+        release: Duration,
+        curve: EnvelopeCurve,
+        mode: EnvelopeMode,
+    ) -> Self {
+        let segments = vec![
+            Segment::new(1.0, attack, curve),
+            Segment::new(sustain.clamp(0.0, 1.0), decay, curve),
+            Segment::new(0.0, release, curve),
+        ];
+        Self::new(segments, Some(1), None, mode)
+    }
+    fn release_index(&self) -> Option<usize> {
+        let sustain = self.sustain;
+        let loop_end = self.loop_points.map(|(_, end)| end);
+        sustain.max(loop_end).map(|x| x + 1)
+    }
+    fn start_stage(&mut self, stage: usize) {
+        self.stage = if stage < self.segments.len() {
+            Some(stage)
+        } else {
+            None
+        };
+        self.start = self.level;
+        self.elapsed = 0.0;
+    }
+    fn note_on(&mut self) {
+        if self.mode == EnvelopeMode::Retrigger {
+            self.level = 0.0;
+        }
+        self.start_stage(0);
+    }
+    fn note_off(&mut self) {
+        let Some(release) = self.release_index() else {
+            // One shot envelopes ignore the gate
+            return;
+        };
+        if self.stage.is_none_or(|stage| stage < release) {
+            self.start_stage(release);
+        }
+    }
+    /// Advances the envelope by dt seconds.
+    ///
+    /// Gate and trig are considered high above 0.5.
+    #[inline(always)]
+    pub fn tick(&mut self, gate: f32, trig: f32, dt: f32) -> f32 {
+        if self.last_gate <= 0.5 && gate > 0.5 {
+            self.held = true;
+            self.note_on();
+        } else if self.last_gate > 0.5 && gate <= 0.5 {
+            self.held = false;
+            self.note_off();
+        }
+        self.last_gate = gate;
+
+        // Legato carries on through the triggers of overlapping notes
+        let legato_held = self.held && self.mode == EnvelopeMode::Legato;
+        if self.last_trig <= 0.5 && trig > 0.5 && !legato_held {
+            self.note_on();
+        }
+        self.last_trig = trig;
+
+        let Some(stage) = self.stage else {
+            return self.level;
+        };
+
+        let segment = self.segments[stage];
+        let duration = segment.duration.as_secs_f32();
+
+        self.elapsed += dt;
+        let p = if duration > 0.0 {
+            (self.elapsed / duration).min(1.0)
+        } else {
+            1.0
+        };
+
+        self.level = self.start + (segment.target - self.start) * segment.curve.apply(p);
+
+        if p >= 1.0 {
+            match (self.loop_points, self.sustain) {
+                (Some((loop_start, loop_end)), _) if self.held && stage == loop_end => {
+                    self.start_stage(loop_start)
+                }
+                (_, Some(sustain)) if self.held && stage == sustain => self.stage = None,
+                _ => self.start_stage(stage + 1),
+            }
+        }
+
+        self.level
+    }
+}
+
+/// An audio rate envelope, with "gate" and "trig" audio inputs.
+///
+/// Every output channel gets the same envelope, so it can be
+/// multiplied straight onto a stereo signal.
+pub struct Envelope<Ao>
+where
+    Ao: ArrayLength,
+{
+    env: EnvelopeSegments,
+    ports: Ports<U2, Ao, U0, U0>,
+}
+
+impl<Ao> Envelope<Ao>
+where
+    Ao: ArrayLength,
+{
+    pub fn new(env: EnvelopeSegments) -> Self {
+        let audio_inputs = arr![
+            AudioInputPort {
+                meta: PortMeta {
+                    name: "gate",
+                    index: 0
+                },
+            },
+            AudioInputPort {
+                meta: PortMeta {
+                    name: "trig",
+                    index: 1
+                },
+            },
+        ];
+
+        let audio_outputs: GenericArray<AudioOutputPort, Ao> = generate_audio_outputs::<Ao>();
+
+        Self {
+            env,
+            ports: Ports {
+                audio_inputs: Some(audio_inputs),
+                audio_outputs: Some(audio_outputs),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF, Ao> Node<AF, CF> for Envelope<Ao>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    Ao: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 2);
+        debug_assert_eq!(ao.len(), Ao::USIZE);
+
+        let dt = 1.0 / ctx.get_sample_rate();
+
+        for n in 0..AF::USIZE {
+            let level = self.env.tick(ai[0][n], ai[1][n], dt);
+            for chan in ao.iter_mut() {
+                chan[n] = level;
+            }
+        }
+    }
+}
+
+impl<Ao> PortedErased for Envelope<Ao>
+where
+    Ao: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+/// A control rate envelope, with "gate" and "trig" control inputs
+/// and a single control output.
+///
+/// Cheaper than the audio rate version, but the gate is only
+/// seen once per control sample.
+pub struct EnvelopeControl {
+    env: EnvelopeSegments,
+    ports: Ports<U0, U0, U2, U1>,
+}
+
+impl EnvelopeControl {
+    pub fn new(env: EnvelopeSegments) -> Self {
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "gate",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "trig",
+                    index: 1
+                },
+            },
+        ];
+        let control_outputs = arr![ControlOutputPort {
+            meta: PortMeta {
+                name: "out",
+                index: 0
+            },
+        }];
+
+        Self {
+            env,
+            ports: Ports {
+                audio_inputs: None,
+                audio_outputs: None,
+                control_inputs: Some(control_inputs),
+                control_outputs: Some(control_outputs),
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for EnvelopeControl
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        _: &Frame<AF>,
+        _: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        co: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ci.len(), 2);
+        debug_assert_eq!(co.len(), 1);
+
+        let dt = 1.0 / ctx.get_control_rate();
+
+        for n in 0..CF::USIZE {
+            co[0][n] = self.env.tick(ci[0][n], ci[1][n], dt);
+        }
+    }
+}
+
+impl PortedErased for EnvelopeControl {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        None
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        None
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        self.ports.get_control_outputs()
+    }
+}
+
+pub type EnvelopeMono = Envelope<Mono>;
+pub type EnvelopeStereo = Envelope<Stereo>;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{EnvelopeCurve, EnvelopeMode, EnvelopeSegments, Segment};
+
+    const DT: f32 = 1.0 / 1000.0;
+
+    fn ms(x: u64) -> Duration {
+        Duration::from_millis(x)
+    }
+
+    #[test]
+    fn adsr_stages() {
+        let mut env = EnvelopeSegments::adsr(
+            ms(10),
+            ms(10),
+            0.5,
+            ms(10),
+            EnvelopeCurve::Linear,
+            EnvelopeMode::Retrigger,
+        );
+
+        // Attack peaks after 10ms
+        let attack: Vec<f32> = (0..10).map(|_| env.tick(1.0, 0.0, DT)).collect();
+        assert!((attack[4] - 0.5).abs() < 1e-4);
+        assert!((attack[9] - 1.0).abs() < 1e-4);
+
+        // Then decays to the sustain, and holds while the gate is high
+        for _ in 0..100 {
+            env.tick(1.0, 0.0, DT);
+        }
+        assert!((env.tick(1.0, 0.0, DT) - 0.5).abs() < 1e-4);
+
+        // Then releases to zero
+        let release: Vec<f32> = (0..10).map(|_| env.tick(0.0, 0.0, DT)).collect();
+        assert!((release[4] - 0.25).abs() < 1e-4);
+        assert!(release[9].abs() < 1e-4);
+        assert!(env.tick(0.0, 0.0, DT).abs() < 1e-4);
+    }
+
+    #[test]
+    fn trigger_runs_through_without_a_gate() {
+        let mut env = EnvelopeSegments::adsr(
+            ms(10),
+            ms(10),
+            0.5,
+            ms(10),
+            EnvelopeCurve::Exponential,
+            EnvelopeMode::Retrigger,
+        );
+
+        env.tick(0.0, 1.0, DT);
+        let out: Vec<f32> = (0..40).map(|_| env.tick(0.0, 0.0, DT)).collect();
+        let peak = out.iter().fold(0.0_f32, |acc, x| acc.max(*x));
+
+        assert!((peak - 1.0).abs() < 1e-4);
+        assert!(out[39].abs() < 1e-4);
+    }
+
+    #[test]
+    fn legato_starts_from_current_level() {
+        let make = |mode| {
+            EnvelopeSegments::adsr(ms(10), ms(10), 0.5, ms(100), EnvelopeCurve::Linear, mode)
+        };
+        let mut retrigger = make(EnvelopeMode::Retrigger);
+        let mut legato = make(EnvelopeMode::Legato);
+
+        for env in [&mut retrigger, &mut legato] {
+            for _ in 0..50 {
+                env.tick(1.0, 0.0, DT);
+            }
+            for _ in 0..10 {
+                env.tick(0.0, 0.0, DT);
+            }
+        }
+
+        // Retrigger snaps back down, legato keeps climbing from the release level
+        let r = retrigger.tick(1.0, 0.0, DT);
+        let l = legato.tick(1.0, 0.0, DT);
+        assert!(r < 0.2, "{r}");
+        assert!(l > 0.45, "{l}");
+    }
+
+    #[test]
+    fn legato_ignores_triggers_while_held() {
+        let make = |mode| {
+            EnvelopeSegments::adsr(ms(10), ms(10), 0.5, ms(100), EnvelopeCurve::Linear, mode)
+        };
+        let mut retrigger = make(EnvelopeMode::Retrigger);
+        let mut legato = make(EnvelopeMode::Legato);
+
+        for env in [&mut retrigger, &mut legato] {
+            for _ in 0..50 {
+                env.tick(1.0, 0.0, DT);
+            }
+        }
+
+        // A second note comes in before the first is let go
+        let r = retrigger.tick(1.0, 1.0, DT);
+        let l = legato.tick(1.0, 1.0, DT);
+        assert!(r < 0.2, "{r}");
+        assert!((l - 0.5).abs() < 1e-4, "{l}");
+    }
+
+    #[test]
+    fn loops_while_held() {
+        let segments = vec![
+            Segment::new(1.0, ms(5), EnvelopeCurve::Linear),
+            Segment::new(0.0, ms(5), EnvelopeCurve::Linear),
+            Segment::new(0.0, ms(5), EnvelopeCurve::Linear),
+        ];
+        let mut env = EnvelopeSegments::new(segments, None, Some((0, 1)), EnvelopeMode::Legato);
+
+        let out: Vec<f32> = (0..40).map(|_| env.tick(1.0, 0.0, DT)).collect();
+        let peaks = out.iter().filter(|x| (**x - 1.0).abs() < 1e-4).count();
+        assert_eq!(peaks, 4);
+    }
+}
@@ -0,0 +1,557 @@
+use std::f32::consts::{PI, TAU};
+
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{U0, U3};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::port_utils::{generate_audio_inputs, generate_audio_outputs},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BiquadType {
+    LowPass,
+    HighPass,
+    /// Constant 0dB peak gain
+    BandPass,
+    Notch,
+    /// Bell, using the gain
+    Peak,
+    LowShelf,
+    HighShelf,
+    AllPass,
+}
+
+/// How many sections to cascade, and how to tune them.
+///
+/// Higher order responses are only meaningful for low and high pass filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BiquadCascade {
+    /// One section, using the given Q
+    #[default]
+    Single,
+    /// Butterworth of the given order, i.e 4 is 24dB/oct
+    Butterworth(usize),
+    /// Linkwitz-Riley of the given even order, two Butterworth filters of half the order.
+    /// Summing the low and high pass gives a flat response.
+    LinkwitzRiley(usize),
+}
+
+// Keep the parameters in a range where the filter is stable and the maths is well behaved
+const MIN_CUTOFF: f32 = 1.0;
+const MIN_Q: f32 = 0.05;
+const MAX_Q: f32 = 100.0;
+const MAX_GAIN_DB: f32 = 48.0;
+
+/// Normalized coefficients, with a0 = 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiquadCoeffs {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl Default for BiquadCoeffs {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl BiquadCoeffs {
+    pub const IDENTITY: Self = Self {
+        b0: 1.0,
+        b1: 0.0,
+        b2: 0.0,
+        a1: 0.0,
+        a2: 0.0,
+    };
+    /// RBJ audio EQ cookbook coefficients.
+    ///
+    /// Parameters are clamped to a stable range, so this is safe to call
+    /// with modulated values.
+    pub fn design(filter_type: BiquadType, fs: f32, cutoff: f32, q: f32, gain_db: f32) -> Self {
+        let cutoff = clamp_cutoff(cutoff, fs);
+        let q = if q.is_finite() {
+            q.clamp(MIN_Q, MAX_Q)
+        } else {
+            MIN_Q
+        };
+        let gain_db = if gain_db.is_finite() {
+            gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB)
+        } else {
+            0.0
+        };
+
+        let w0 = TAU * cutoff / fs;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a = 10.0_f32.powf(gain_db / 40.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match filter_type {
+            BiquadType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::AllPass => (
+                1.0 - alpha,
+                -2.0 * cos,
+                1.0 + alpha,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadType::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BiquadType::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    (a + 1.0) + (a - 1.0) * cos + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - k,
+                )
+            }
+            BiquadType::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    (a + 1.0) - (a - 1.0) * cos + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - k,
+                )
+            }
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+    /// A first order section via the bilinear transform, for odd order cascades.
+    ///
+    /// Only low and high pass have a first order form, anything else passes through.
+    pub fn first_order(filter_type: BiquadType, fs: f32, cutoff: f32) -> Self {
+        let k = (PI * clamp_cutoff(cutoff, fs) / fs).tan();
+        let a1 = (k - 1.0) / (k + 1.0);
+        match filter_type {
+            BiquadType::LowPass => Self {
+                b0: k / (1.0 + k),
+                b1: k / (1.0 + k),
+                b2: 0.0,
+                a1,
+                a2: 0.0,
+            },
+            BiquadType::HighPass => Self {
+                b0: 1.0 / (1.0 + k),
+                b1: -1.0 / (1.0 + k),
+                b2: 0.0,
+                a1,
+                a2: 0.0,
+            },
+            _ => Self::IDENTITY,
+        }
+    }
+    /// The magnitude response at a frequency, handy for tests and plotting.
+    pub fn magnitude(&self, freq: f32, fs: f32) -> f32 {
+        let w = TAU * freq / fs;
+        let (s1, c1) = w.sin_cos();
+        let (s2, c2) = (2.0 * w).sin_cos();
+
+        let num_re = self.b0 + self.b1 * c1 + self.b2 * c2;
+        let num_im = -(self.b1 * s1 + self.b2 * s2);
+        let den_re = 1.0 + self.a1 * c1 + self.a2 * c2;
+        let den_im = -(self.a1 * s1 + self.a2 * s2);
+
+        ((num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im)).sqrt()
+    }
+}
+
+#[inline(always)]
+fn clamp_cutoff(cutoff: f32, fs: f32) -> f32 {
+    if cutoff.is_finite() {
+        cutoff.clamp(MIN_CUTOFF, fs * 0.499)
+    } else {
+        MIN_CUTOFF
+    }
+}
+
+/// Direct form I state.
+///
+/// Transposed DF II is a bit cheaper, but its state is scaled by the
+/// coefficients, so it can blow up when they are swept quickly.
+/// DF I only stores past inputs and outputs, which stay valid.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiquadState {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl BiquadState {
+    #[inline(always)]
+    pub fn tick(&mut self, c: &BiquadCoeffs, x: f32) -> f32 {
+        let y = c.b0 * x + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// A section in a cascade. None for the Q means a first order section.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    q: Option<f32>,
+    coeffs: BiquadCoeffs,
+}
+
+fn butterworth_qs(order: usize) -> Vec<Option<f32>> {
+    let order = order.max(1);
+    let mut qs: Vec<Option<f32>> = (0..order / 2)
+        .map(|k| {
+            let theta = PI * (2 * k + 1) as f32 / (2 * order) as f32;
+            Some(1.0 / (2.0 * theta.sin()))
+        })
+        .collect();
+    if order % 2 == 1 {
+        qs.push(None);
+    }
+    qs
+}
+
+/// RBJ cookbook biquad filters, with optional Butterworth and
+/// Linkwitz-Riley cascades.
+///
+/// The "cutoff", "q" and "gain" control inputs are added to the base
+/// parameters. Coefficients are recomputed at the control rate, and only when
+/// the parameters change, so sweeping the filter doesn't cost a
+/// cookbook design per sample.
+///
+/// Cascades bake their Q into each section, so the base Q is only used by
+/// single section filters. The "q" input is added to every section of a
+/// cascade, so it will detune Butterworth responses.
+pub struct Biquad<C>
+where
+    C: ArrayLength,
+{
+    filter_type: BiquadType,
+    cutoff: f32,
+    gain_db: f32,
+    sections: Vec<Section>,
+    // [chan][section]
+    state: GenericArray<Vec<BiquadState>, C>,
+    // The cutoff, Q offset and gain the coefficients were last designed with
+    current: Option<(f32, f32, f32)>,
+    ports: Ports<C, C, U3, U0>,
+}
+
+impl<C> Biquad<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(
+        filter_type: BiquadType,
+        cutoff: f32,
+        q: f32,
+        gain_db: f32,
+        cascade: BiquadCascade,
+    ) -> Self {
+        let qs = match cascade {
+            BiquadCascade::Single => vec![Some(q)],
+            BiquadCascade::Butterworth(order) => butterworth_qs(order),
+            BiquadCascade::LinkwitzRiley(order) => {
+                let half = butterworth_qs((order / 2).max(1));
+                half.iter().chain(half.iter()).copied().collect()
+            }
+        };
+        let sections: Vec<Section> = qs
+            .into_iter()
+            .map(|q| Section {
+                q,
+                coeffs: BiquadCoeffs::IDENTITY,
+            })
+            .collect();
+
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "cutoff",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "q",
+                    index: 1
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "gain",
+                    index: 2
+                },
+            },
+        ];
+
+        let num_sections = sections.len();
+
+        Self {
+            filter_type,
+            cutoff,
+            gain_db,
+            sections,
+            state: GenericArray::generate(|_| vec![BiquadState::default(); num_sections]),
+            current: None,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_inputs),
+                control_outputs: None,
+            },
+        }
+    }
+    fn update(&mut self, fs: f32, cutoff: f32, q: f32, gain_db: f32) {
+        if self.current == Some((cutoff, q, gain_db)) {
+            return;
+        }
+        self.current = Some((cutoff, q, gain_db));
+
+        for section in self.sections.iter_mut() {
+            section.coeffs = match section.q {
+                Some(section_q) => {
+                    BiquadCoeffs::design(self.filter_type, fs, cutoff, section_q + q, gain_db)
+                }
+                None => BiquadCoeffs::first_order(self.filter_type, fs, cutoff),
+            };
+        }
+    }
+    /// The magnitude response of the whole cascade, at the current parameters.
+    pub fn magnitude(&mut self, freq: f32, fs: f32) -> f32 {
+        self.update(fs, self.cutoff, 0.0, self.gain_db);
+        self.sections
+            .iter()
+            .map(|x| x.coeffs.magnitude(freq, fs))
+            .product()
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Biquad<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+        debug_assert_eq!(ci.len(), 3);
+
+        let fs = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        for (m, start) in (0..AF::USIZE).step_by(chunk).enumerate() {
+            let m = m.min(CF::USIZE - 1);
+            self.update(
+                fs,
+                self.cutoff + ci[0][m],
+                ci[1][m],
+                self.gain_db + ci[2][m],
+            );
+
+            let end = (start + chunk).min(AF::USIZE);
+
+            for ((input, output), state) in ai.iter().zip(ao.iter_mut()).zip(self.state.iter_mut())
+            {
+                for n in start..end {
+                    let mut y = input[n];
+                    for (section, s) in self.sections.iter().zip(state.iter_mut()) {
+                        y = s.tick(&section.coeffs, y);
+                    }
+                    output[n] = y;
+                }
+            }
+        }
+
+        // If anything blew up, i.e a NaN on the input, start over rather than output NaNs forever
+        for (output, state) in ao.iter_mut().zip(self.state.iter_mut()) {
+            if !output[AF::USIZE - 1].is_finite() {
+                state.fill(BiquadState::default());
+                output.fill(0.0);
+            }
+        }
+    }
+}
+
+impl<C> PortedErased for Biquad<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type BiquadMono = Biquad<Mono>;
+pub type BiquadStereo = Biquad<Stereo>;
+pub type BiquadMC<C> = Biquad<C>;
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    use super::{BiquadCascade, BiquadCoeffs, BiquadMono, BiquadType};
+
+    fn db(x: f32) -> f32 {
+        20.0 * x.log10()
+    }
+
+    #[test]
+    fn cookbook_responses() {
+        let lp = BiquadCoeffs::design(BiquadType::LowPass, FS, 1000.0, FRAC_1_SQRT_2, 0.0);
+        assert!(db(lp.magnitude(1000.0, FS)) + 3.01 < 0.05);
+        assert!(db(lp.magnitude(10.0, FS)).abs() < 0.05);
+
+        let peak = BiquadCoeffs::design(BiquadType::Peak, FS, 1000.0, 1.0, 6.0);
+        assert!((db(peak.magnitude(1000.0, FS)) - 6.0).abs() < 0.05);
+
+        let shelf = BiquadCoeffs::design(BiquadType::LowShelf, FS, 1000.0, FRAC_1_SQRT_2, -12.0);
+        assert!((db(shelf.magnitude(10.0, FS)) + 12.0).abs() < 0.1);
+        assert!(db(shelf.magnitude(20_000.0, FS)).abs() < 0.1);
+
+        let ap = BiquadCoeffs::design(BiquadType::AllPass, FS, 1000.0, 2.0, 0.0);
+        for freq in [100.0, 1000.0, 10_000.0] {
+            assert!((ap.magnitude(freq, FS) - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn butterworth_and_linkwitz_riley_cascades() {
+        for order in [3, 4] {
+            let mut bw = BiquadMono::new(
+                BiquadType::LowPass,
+                250.0,
+                0.0,
+                0.0,
+                BiquadCascade::Butterworth(order),
+            );
+            assert!((db(bw.magnitude(250.0, FS)) + 3.01).abs() < 0.05);
+            // Roughly 6dB per order, per octave, well above the cutoff
+            let slope = db(bw.magnitude(2000.0, FS)) - db(bw.magnitude(4000.0, FS));
+            assert!((slope - 6.0 * order as f32).abs() < 1.5, "{slope}");
+        }
+
+        let mut low = BiquadMono::new(
+            BiquadType::LowPass,
+            1000.0,
+            0.0,
+            0.0,
+            BiquadCascade::LinkwitzRiley(4),
+        );
+        let mut high = BiquadMono::new(
+            BiquadType::HighPass,
+            1000.0,
+            0.0,
+            0.0,
+            BiquadCascade::LinkwitzRiley(4),
+        );
+        assert!((db(low.magnitude(1000.0, FS)) + 6.02).abs() < 0.05);
+        assert!((db(high.magnitude(1000.0, FS)) + 6.02).abs() < 0.05);
+    }
+
+    #[test]
+    fn modulation_stays_stable() {
+        // A musical sweep, every control sample, should stay well behaved
+        let mut filter =
+            BiquadMono::new(BiquadType::LowPass, 20.0, 0.0, 0.0, BiquadCascade::Single);
+        let mut runner = NodeRunner::new(&filter);
+        for block in 0..500 {
+            for (n, x) in runner.ai[0].iter_mut().enumerate() {
+                *x = if (block * 64 + n) % 100 < 50 {
+                    1.0
+                } else {
+                    -1.0
+                };
+            }
+            for (m, x) in runner.ci[0].iter_mut().enumerate() {
+                let t = (block * 16 + m) as f32 * 0.01;
+                *x = 10_000.0 + 9_900.0 * t.sin();
+            }
+            runner.ci[1].fill(0.5 + 4.0 * (block as f32 * 0.05).cos().abs());
+            runner.process(&mut filter);
+            assert!(runner.ao[0].iter().all(|x| x.is_finite() && x.abs() < 20.0));
+        }
+
+        // Wild values, well outside the sane range, should at least never produce NaNs
+        let mut filter = BiquadMono::new(
+            BiquadType::LowPass,
+            1000.0,
+            10.0,
+            0.0,
+            BiquadCascade::Single,
+        );
+        let mut runner = NodeRunner::new(&filter);
+        runner.ai[0].fill(1.0);
+        for block in 0..500 {
+            let x = block as f32;
+            runner.ci[0].fill(((x * 0.37).sin() * 60_000.0).abs() - 1000.0);
+            runner.ci[1].fill((x * 0.11).cos() * 200.0);
+            runner.ci[2].fill(f32::NAN);
+            runner.process(&mut filter);
+            assert!(runner.ao[0].iter().all(|x| x.is_finite()));
+        }
+    }
+}
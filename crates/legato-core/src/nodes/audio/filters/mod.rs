@@ -1,3 +1,4 @@
+pub mod biquad;
 pub mod fir;
 pub mod svf;
-// TODO: Biquad, one pole, etc.
+pub mod utility;
@@ -1,325 +1,335 @@
-// use std::{f32::consts::PI, ops::Add};
-
-// use generic_array::{arr, ArrayLength, GenericArray};
-// use typenum::{Diff, Sum, Unsigned, U2};
-
-// use crate::{engine::{audio_context::AudioContext, buffer::Frame, node::Node, port::{Mono, Port, PortBehavior, Ported, PortedErased, Stereo}}, nodes::osc::OscillatorPorts};
-
-// #[derive(Copy, Clone)]
-// pub enum FilterType {
-//     LowPass,
-//     BandPass,
-//     HighPass,
-//     Notch,
-//     Peak,
-//     AllPass,
-//     Bell,
-//     LowShelf,
-//     HighShelf,
-// }
-// #[derive(Copy, Clone, Default)]
-// struct SvfState {
-//     ic1eq: f32,
-//     ic2eq: f32
-// }
-
-// #[derive(Copy, Clone, Default)]
-// struct SvfCoefficients {
-//     a1: f32,
-//     a2: f32,
-//     a3: f32,
-//     m0: f32,
-//     m1: f32,
-//     m2: f32,
-// }
-
-// pub struct SvfPorts<Ai, Ci, O>
-// where
-//     Ai: Unsigned + Add<Ci>,
-//     Ci: Unsigned,
-//     O: Unsigned + ArrayLength,
-//     Sum<Ai, Ci>: Unsigned + ArrayLength,
-// {
-//     pub inputs: GenericArray<Port, Sum<Ai, Ci>>,
-//     pub outputs: GenericArray<Port, O>,
-// }
-
-// pub struct Svf <Ai, Ci, O>
-// where
-//     Ai: Unsigned + Add<Ci> + ArrayLength,
-//     Ci: Unsigned,
-//     O: Unsigned + ArrayLength,
-//     Sum<Ai, Ci>: Unsigned + ArrayLength,
-// {
-//     filter_type: FilterType,
-//     sample_rate: f32,
-//     cutoff: f32,
-//     gain: f32,
-//     q: f32,
-//     // Filter state for each channel
-//     filter_state: Vec<SvfState>,
-//     // Filter Coefficeints
-//     coefficients: SvfCoefficients,
-//     // Ports for SVF
-//     ports: SvfPorts<Ai, Ci, O>
-
-// }
-
-// impl<Ai, Ci, O> Svf<Ai, Ci, O>
-// where
-//     Ai: Unsigned + Add<Ci> + ArrayLength,
-//     Ci: Unsigned,
-//     O: Unsigned + ArrayLength,
-//     Sum<Ai, Ci>: Unsigned + ArrayLength,
-// {
-//     pub fn new(ports: SvfPorts<Ai, Ci, O>, sample_rate: f32, filter_type: FilterType, cutoff: f32, gain: f32, q: f32) -> Self {
-//         let mut new_filter =
-//         Self {
-//             sample_rate,
-//             filter_type,
-//             cutoff,
-//             gain,
-//             q,
-//             filter_state: vec![SvfState::default(); Ai::USIZE],
-//             coefficients: SvfCoefficients::default(),
-//             ports
-//         };
-
-//         new_filter.set(filter_type, sample_rate, cutoff, q, gain);
-
-//         new_filter
-//     }
-//     #[inline(always)]
-//     pub fn set(&mut self, filter_type: FilterType, sample_rate: f32, cutoff: f32, q: f32, gain: f32){
-//         self.filter_type = filter_type;
-//         self.sample_rate = sample_rate;
-//         self.cutoff = cutoff;
-//         self.q = q;
-//         self.gain = gain;
-
-//         match filter_type {
-//             FilterType::LowPass => {
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-//                 let k = 1.0 / self.q;
-
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 0.0;
-//                 self.coefficients.m1 = 0.0;
-//                 self.coefficients.m2 = 1.0;
-//             },
-//             FilterType::BandPass => {
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-//                 let k = 1.0 / self.q;
-
-//                 self.coefficients.a1 = 1.0 / (1.0 + g*(g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g*self.coefficients.a2;
-//                 self.coefficients.m0 = 0.0;
-//                 self.coefficients.m1 = 1.0;
-//                 self.coefficients.m2 = 0.0;
-//             },
-//             FilterType::HighPass => {
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-//                 let k = 1.0 / self.q;
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 1.0;
-//                 self.coefficients.m1 = -k;
-//                 self.coefficients.m2 = -1.0;
-//             },
-//             FilterType::Notch => {
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-//                 let k = 1.0 / self.q;
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 1.0;
-//                 self.coefficients.m1 = -k;
-//                 self.coefficients.m2 = 0.0;
-//             },
-//             FilterType::Peak => {
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-
-//                 let k = 1.0 / self.q;
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 1.0;
-//                 self.coefficients.m1 = -k;
-//                 self.coefficients.m2 = -2.0;
-//             },
-//             FilterType::AllPass => {
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-//                 let k = 1.0 / self.q;
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 1.0;
-//                 self.coefficients.m1 = -2.0 * k;
-//                 self.coefficients.m2 = 0.0;
-//             },
-//             FilterType::Bell => {
-//                 let a = f32::powf(
-//                     10.0,
-//                     self.gain / 40.0,
-//                 );
-//                 let g = (PI * self.cutoff / self.sample_rate).tan();
-
-//                 let k = 1.0 / (self.q * a);
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 1.0;
-//                 self.coefficients.m1 = k * (a * a - 1.0);
-//                 self.coefficients.m2 = 0.0;
-//             },
-//             FilterType::LowShelf => {
-//                 let a = f32::powf(
-//                     10.0,
-//                     self.gain / 40.0,
-//                 );
-//                 let g = (PI * self.cutoff / self.sample_rate).tan() / f32::sqrt(a);
-//                 let k = 1.0 / self.q;
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = 1.0;
-//                 self.coefficients.m1 = k * (a - 1.0);
-//                 self.coefficients.m2 = a * a - 1.0;
-//             }
-//             FilterType::HighShelf => {
-//                 let a = f32::powf(
-//                     10.0,
-//                     self.gain / 40.0,
-//                 );
-//                 let g = (PI * self.cutoff / self.sample_rate).tan() * f32::sqrt(a);
-
-//                 let k = 1.0 / self.q;
-//                 self.coefficients.a1 = 1.0 / (1.0 + g * (g + k));
-//                 self.coefficients.a2 = g * self.coefficients.a1;
-//                 self.coefficients.a3 = g * self.coefficients.a2;
-//                 self.coefficients.m0 = a * a;
-//                 self.coefficients.m1 = k * (1.0 - a) * a;
-//                 self.coefficients.m2 = 1.0 - a * a;
-//             }
-//             _ => ()
-//         }
-//     }
-// }
-
-// type ControlIn = U2; // Cutoff, Q
-// pub type SvfMono = Svf<Mono, ControlIn, Mono>;
-// pub type SvfStereo = Svf<Stereo, ControlIn, Stereo>;
-
-// impl<Ai, Ci, O> PortedErased for Svf<Ai, Ci, O>
-// where
-//     Ai: Unsigned + Add<Ci> + ArrayLength,
-//     Ci: Unsigned,
-//     O: Unsigned + ArrayLength,
-//     Sum<Ai, Ci>: Unsigned + ArrayLength
-// {
-//     fn get_inputs(&self) -> &[Port] {
-//         &self.ports.inputs
-//     }
-//     fn get_outputs(&self) -> &[Port] {
-//         &self.ports.outputs
-//     }
-// }
-
-// impl SvfPorts<Mono, ControlIn, Mono>{
-//     fn new() -> Self {
-//         let inputs = arr![
-//             Port { name: "mono", index: 0, behavior: PortBehavior::Default},
-//             Port {name: "cutoff", index: 1, behavior: PortBehavior::Default },
-//             Port {name: "q", index: 2, behavior: PortBehavior::Default },
-//         ];
-//         let outputs = arr![
-//             Port {name: "mono", index: 1, behavior: PortBehavior::Default }
-//         ];
-//         Self {
-//             inputs,
-//             outputs
-//         }
-//     }
-// }
-
-// impl SvfPorts<Stereo, ControlIn, Stereo>{
-//     fn new() -> Self {
-//         let inputs = arr![
-//             Port { name: "l", index: 0, behavior: PortBehavior::Default},
-//             Port { name: "r", index: 1, behavior: PortBehavior::Default},
-//             Port {name: "cutoff", index: 2, behavior: PortBehavior::Default },
-//             Port {name: "q", index: 3, behavior: PortBehavior::Default },
-//         ];
-//         let outputs = arr![
-//             Port { name: "l", index: 0, behavior: PortBehavior::Default},
-//             Port { name: "r", index: 0, behavior: PortBehavior::Default},
-
-//         ];
-//         Self {
-//             inputs,
-//             outputs
-//         }
-//     }
-// }
-
-// const CUTOFF_EPSILON: f32 = 1e-3;
-
-// impl<N, Ai, Ci, O> Node<N> for Svf<Ai, Ci, O>
-// where
-//     Ai: Unsigned + Add<Ci> + ArrayLength,
-//     Ci: Unsigned,
-//     O: Unsigned + ArrayLength,
-//     Sum<Ai, Ci>: Unsigned + ArrayLength,
-// {
-//     fn process(&mut self, ctx: &mut AudioContext<AF>, inputs: &Frame<N>, output: &mut Frame<N>) {
-//         debug_assert_eq!(inputs.len(), <Sum<Ai, Ci>>::USIZE);
-//         debug_assert_eq!(output.len(), O::USIZE);
-//         let cutoff = inputs.get(Ai::USIZE); // Control starts at the end of the audio inputs
-
-//         for n in 0..N {
-//             if let Some(cutoff_frame) = cutoff {
-//                 let new_cutoff = cutoff_frame[n];
-//                 if (new_cutoff - self.cutoff).abs() > CUTOFF_EPSILON {
-//                     self.set(self.filter_type, self.sample_rate, new_cutoff, self.q, self.gain);
-//                 }
-//             }
-
-//             for c in 0..Ai::USIZE - 1 {
-//                 let input = inputs[c];
-
-//                 let filter_state = &mut self.filter_state[c];
-
-//                 let v0 = input[n];
-
-//                 let v3 = v0 - filter_state.ic2eq;
-
-//                 let v1 = self.coefficients.a1 * filter_state.ic1eq + self.coefficients.a2 * v3;
-
-//                 let v2 = filter_state.ic2eq + self.coefficients.a2 * filter_state.ic1eq + self.coefficients.a3 * v3;
-
-//                 filter_state.ic1eq = 2.0 * v1 - filter_state.ic1eq;
-//                 filter_state.ic2eq = 2.0 * v2 - filter_state.ic2eq;
-
-//                 output[c][n] = self.coefficients.m0 * v0 + self.coefficients.m1 * v1 + self.coefficients.m2 * v2;
-//             }
-//         }
-//     }
-// }
-
-// impl Default for SvfMono {
-//     fn default() -> Self {
-//         let ports = SvfPorts::<Mono, ControlIn, Mono>::new();
-//         Self::new(ports, 48_000.0, FilterType::LowPass, 5400.0 , 0.7, 0.3)
-//     }
-// }
-
-// impl Default for SvfStereo {
-//     fn default() -> Self {
-//         let ports = SvfPorts::<Stereo, ControlIn, Stereo>::new();
-//         Self::new(ports, 48_000.0, FilterType::LowPass, 5400.0 , 0.7, 0.3)
-//     }
-// }
+use std::{f32::consts::PI, ops::Add};
+
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{Sum, U0, U2, Unsigned};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::port_utils::{generate_audio_inputs, generate_audio_outputs},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterType {
+    LowPass,
+    BandPass,
+    HighPass,
+    Notch,
+    Peak,
+    AllPass,
+    Bell,
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Copy, Clone, Default)]
+struct SvfState {
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+#[derive(Copy, Clone, Default)]
+struct SvfCoefficients {
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    m0: f32,
+    m1: f32,
+    m2: f32,
+}
+
+impl SvfCoefficients {
+    /// Cytomic/Andy Simper style coefficients, for each of the filter modes
+    fn new(filter_type: FilterType, sample_rate: f32, cutoff: f32, q: f32, gain: f32) -> Self {
+        // Keep the cutoff under nyquist and the Q positive, so modulation can't blow it up
+        let cutoff = cutoff.clamp(1.0, sample_rate * 0.499);
+        let q = q.max(0.01);
+
+        let g = (PI * cutoff / sample_rate).tan();
+        let k = 1.0 / q;
+        let a = f32::powf(10.0, gain / 40.0);
+
+        let (g, k, m0, m1, m2) = match filter_type {
+            FilterType::LowPass => (g, k, 0.0, 0.0, 1.0),
+            FilterType::BandPass => (g, k, 0.0, 1.0, 0.0),
+            FilterType::HighPass => (g, k, 1.0, -k, -1.0),
+            FilterType::Notch => (g, k, 1.0, -k, 0.0),
+            FilterType::Peak => (g, k, 1.0, -k, -2.0),
+            FilterType::AllPass => (g, k, 1.0, -2.0 * k, 0.0),
+            FilterType::Bell => {
+                let k = 1.0 / (q * a);
+                (g, k, 1.0, k * (a * a - 1.0), 0.0)
+            }
+            FilterType::LowShelf => (g / a.sqrt(), k, 1.0, k * (a - 1.0), a * a - 1.0),
+            FilterType::HighShelf => (g * a.sqrt(), k, a * a, k * (1.0 - a) * a, 1.0 - a * a),
+        };
+
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        Self {
+            a1,
+            a2,
+            a3,
+            m0,
+            m1,
+            m2,
+        }
+    }
+}
+
+// Skip recomputing the coefficients for tiny changes
+const PARAM_EPSILON: f32 = 1e-3;
+
+/// A state variable filter, based on Andy Simper's trapezoidal SVF.
+///
+/// Unlike the biquad, the SVF holds up well when the cutoff and Q
+/// are modulated at audio rate, so it is the better choice for
+/// sweeps and envelopes.
+///
+/// The "cutoff" and "q" inputs exist at both audio and control rate,
+/// and are added to the base values. Control rate values are linearly
+/// ramped across the block to avoid zipper noise.
+pub struct Svf<C>
+where
+    C: ArrayLength + Add<U2>,
+    Sum<C, U2>: ArrayLength,
+{
+    filter_type: FilterType,
+    cutoff: f32,
+    q: f32,
+    gain: f32,
+    // Filter state for each channel
+    filter_state: GenericArray<SvfState, C>,
+    coefficients: SvfCoefficients,
+    // The cutoff and Q the coefficients were last computed with
+    current: (f32, f32),
+    // Last control values, to ramp from
+    last_control: (f32, f32),
+    ports: Ports<Sum<C, U2>, C, U2, U0>,
+}
+
+impl<C> Svf<C>
+where
+    C: ArrayLength + Add<U2>,
+    Sum<C, U2>: ArrayLength,
+{
+    pub fn new(filter_type: FilterType, cutoff: f32, q: f32, gain: f32) -> Self {
+        let channel_inputs = generate_audio_inputs::<C>();
+        let audio_inputs = GenericArray::generate(|i| AudioInputPort {
+            meta: if i < C::USIZE {
+                channel_inputs[i].meta
+            } else {
+                PortMeta {
+                    name: if i == C::USIZE { "cutoff" } else { "q" },
+                    index: i,
+                }
+            },
+        });
+
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "cutoff",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "q",
+                    index: 1
+                },
+            },
+        ];
+
+        Self {
+            filter_type,
+            cutoff,
+            q,
+            gain,
+            filter_state: GenericArray::generate(|_| SvfState::default()),
+            coefficients: SvfCoefficients::default(),
+            // NaN so the first sample always computes the coefficients
+            current: (f32::NAN, f32::NAN),
+            last_control: (0.0, 0.0),
+            ports: Ports {
+                audio_inputs: Some(audio_inputs),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_inputs),
+                control_outputs: None,
+            },
+        }
+    }
+    #[inline(always)]
+    fn set(&mut self, sample_rate: f32, cutoff: f32, q: f32) {
+        // A NaN would stick in the state forever, so keep the last good coefficients
+        if !cutoff.is_finite() || !q.is_finite() {
+            return;
+        }
+        let (current_cutoff, current_q) = self.current;
+        // NaN never compares greater, so check for the initial state explicitly
+        if (cutoff - current_cutoff).abs() > PARAM_EPSILON
+            || (q - current_q).abs() > PARAM_EPSILON
+            || current_cutoff.is_nan()
+        {
+            self.current = (cutoff, q);
+            self.coefficients =
+                SvfCoefficients::new(self.filter_type, sample_rate, cutoff, q, self.gain);
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Svf<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength + Add<U2>,
+    Sum<C, U2>: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), <Sum<C, U2>>::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+        debug_assert_eq!(ci.len(), 2);
+
+        let sample_rate = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        let cutoff_mod = &ai[C::USIZE];
+        let q_mod = &ai[C::USIZE + 1];
+
+        for n in 0..AF::USIZE {
+            // Ramp from the previous control value to the current one
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let t = ((n % chunk) + 1) as f32 / chunk as f32;
+            let (prev_cutoff, prev_q) = if m == 0 {
+                self.last_control
+            } else {
+                (ci[0][m - 1], ci[1][m - 1])
+            };
+            let control_cutoff = prev_cutoff + (ci[0][m] - prev_cutoff) * t;
+            let control_q = prev_q + (ci[1][m] - prev_q) * t;
+
+            self.set(
+                sample_rate,
+                self.cutoff + cutoff_mod[n] + control_cutoff,
+                self.q + q_mod[n] + control_q,
+            );
+
+            let c = self.coefficients;
+
+            for (chan, filter_state) in self.filter_state.iter_mut().enumerate() {
+                let v0 = ai[chan][n];
+                let v3 = v0 - filter_state.ic2eq;
+                let v1 = c.a1 * filter_state.ic1eq + c.a2 * v3;
+                let v2 = filter_state.ic2eq + c.a2 * filter_state.ic1eq + c.a3 * v3;
+
+                filter_state.ic1eq = 2.0 * v1 - filter_state.ic1eq;
+                filter_state.ic2eq = 2.0 * v2 - filter_state.ic2eq;
+
+                ao[chan][n] = c.m0 * v0 + c.m1 * v1 + c.m2 * v2;
+            }
+        }
+
+        self.last_control = (ci[0][CF::USIZE - 1], ci[1][CF::USIZE - 1]);
+    }
+}
+
+impl<C> PortedErased for Svf<C>
+where
+    C: ArrayLength + Add<U2>,
+    Sum<C, U2>: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type SvfMono = Svf<Mono>;
+pub type SvfStereo = Svf<Stereo>;
+
+#[cfg(test)]
+mod test {
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    use super::{FilterType, SvfMono};
+
+    /// Steady state gain of a sine through the filter
+    fn gain(filter_type: FilterType, freq: f32, gain_db: f32) -> f32 {
+        let mut svf = SvfMono::new(
+            filter_type,
+            1000.0,
+            std::f32::consts::FRAC_1_SQRT_2,
+            gain_db,
+        );
+        let mut runner = NodeRunner::new(&svf);
+
+        let mut peak = 0.0_f32;
+        for block in 0..1500 {
+            for (n, x) in runner.ai[0].iter_mut().enumerate() {
+                let t = (block * 64 + n) as f32 / FS;
+                *x = (std::f32::consts::TAU * freq * t).sin();
+            }
+            runner.process(&mut svf);
+            // Skip the transient
+            if block > 750 {
+                peak = runner.ao[0].iter().fold(peak, |acc, x| acc.max(x.abs()));
+            }
+        }
+        20.0 * peak.log10()
+    }
+
+    #[test]
+    fn non_finite_modulation_is_ignored() {
+        let mut svf = SvfMono::new(FilterType::LowPass, 1000.0, 0.7, 0.0);
+        let mut runner = NodeRunner::new(&svf);
+        runner.ai[0].fill(1.0);
+
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            // The audio rate cutoff, and the control rate Q
+            runner.ai[1].fill(bad);
+            runner.ci[1].fill(bad);
+            runner.process(&mut svf);
+            runner.ai[1].fill(0.0);
+            runner.ci[1].fill(0.0);
+            for _ in 0..100 {
+                runner.process(&mut svf);
+            }
+            // The low pass settles back to passing DC
+            let out = runner.ao[0][63];
+            assert!((out - 1.0).abs() < 1e-3, "{out} after {bad}");
+        }
+    }
+
+    #[test]
+    fn modes() {
+        assert!((gain(FilterType::LowPass, 1000.0, 0.0) + 3.0).abs() < 0.2);
+        assert!(gain(FilterType::LowPass, 8000.0, 0.0) < -30.0);
+        assert!(gain(FilterType::HighPass, 100.0, 0.0) < -30.0);
+        assert!(gain(FilterType::Notch, 1000.0, 0.0) < -30.0);
+        assert!(gain(FilterType::AllPass, 300.0, 0.0).abs() < 0.1);
+        assert!((gain(FilterType::Bell, 1000.0, 6.0) - 6.0).abs() < 0.2);
+        assert!((gain(FilterType::LowShelf, 50.0, -12.0) + 12.0).abs() < 0.3);
+        assert!((gain(FilterType::HighShelf, 15_000.0, 6.0) - 6.0).abs() < 0.3);
+    }
+}
@@ -0,0 +1,168 @@
+use generic_array::{ArrayLength, GenericArray, sequence::GenericSequence};
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::{
+        filters::{DcBlocker, LeakyIntegrator, OnePoleHp, OnePoleLp, SlewLimiter},
+        port_utils::{generate_audio_inputs, generate_audio_outputs},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UtilityFilterType {
+    /// One pole low pass, 6dB/oct
+    LowPass { cutoff: f32 },
+    /// One pole high pass, 6dB/oct
+    HighPass { cutoff: f32 },
+    /// Removes DC, i.e after adding an offset with ApplyOp
+    DcBlock { cutoff: f32 },
+    /// Max rise and fall, in units per second
+    Slew { rise: f32, fall: f32 },
+    /// The amount kept each sample, i.e 0.999
+    LeakyIntegrator { leak: f32 },
+}
+
+#[derive(Clone, Copy)]
+enum UtilityFilterState {
+    LowPass(OnePoleLp),
+    HighPass(OnePoleHp),
+    DcBlock(DcBlocker),
+    Slew(SlewLimiter),
+    LeakyIntegrator(LeakyIntegrator),
+}
+
+impl UtilityFilterState {
+    fn new(filter_type: UtilityFilterType, fs: f32) -> Self {
+        match filter_type {
+            UtilityFilterType::LowPass { cutoff } => Self::LowPass(OnePoleLp::new(cutoff, fs)),
+            UtilityFilterType::HighPass { cutoff } => Self::HighPass(OnePoleHp::new(cutoff, fs)),
+            UtilityFilterType::DcBlock { cutoff } => Self::DcBlock(DcBlocker::new(cutoff, fs)),
+            UtilityFilterType::Slew { rise, fall } => Self::Slew(SlewLimiter::new(rise, fall, fs)),
+            UtilityFilterType::LeakyIntegrator { leak } => {
+                Self::LeakyIntegrator(LeakyIntegrator::new(leak))
+            }
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, x: f32) -> f32 {
+        match self {
+            Self::LowPass(f) => f.tick(x),
+            Self::HighPass(f) => f.tick(x),
+            Self::DcBlock(f) => f.tick(x),
+            Self::Slew(f) => f.tick(x),
+            Self::LeakyIntegrator(f) => f.tick(x),
+        }
+    }
+}
+
+/// Small one pole style filters as graph nodes, for cleaning up DC,
+/// gentle tone shaping, and smoothing or integrating signals.
+pub struct UtilityFilter<C>
+where
+    C: ArrayLength,
+{
+    filter_type: UtilityFilterType,
+    state: GenericArray<UtilityFilterState, C>,
+    // The coefficients depend on the sample rate, so these are built on the first block
+    state_fs: f32,
+    ports: Ports<C, C, U0, U0>,
+}
+
+impl<C> UtilityFilter<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(filter_type: UtilityFilterType) -> Self {
+        Self {
+            filter_type,
+            state: GenericArray::generate(|_| UtilityFilterState::new(filter_type, 48_000.0)),
+            state_fs: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for UtilityFilter<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.state_fs {
+            let filter_type = self.filter_type;
+            self.state
+                .iter_mut()
+                .for_each(|state| *state = UtilityFilterState::new(filter_type, fs));
+            self.state_fs = fs;
+        }
+
+        for ((state, input), output) in self.state.iter_mut().zip(ai).zip(ao.iter_mut()) {
+            for (x, y) in input.iter().zip(output.iter_mut()) {
+                *y = state.tick(*x);
+            }
+        }
+    }
+}
+
+impl<C> PortedErased for UtilityFilter<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type UtilityFilterMono = UtilityFilter<Mono>;
+pub type UtilityFilterStereo = UtilityFilter<Stereo>;
+pub type UtilityFilterMC<C> = UtilityFilter<C>;
+
+#[cfg(test)]
+mod test {
+    use crate::nodes::utils::test_utils::NodeRunner;
+
+    use super::{UtilityFilterMono, UtilityFilterType};
+
+    #[test]
+    fn dc_block_node_removes_offset() {
+        let mut node = UtilityFilterMono::new(UtilityFilterType::DcBlock { cutoff: 10.0 });
+        let mut runner = NodeRunner::new(&node);
+        runner.ai[0].fill(1.0);
+
+        // A step of DC, after a second it should have decayed away
+        runner.render(&mut node, 750);
+        assert!(runner.ao[0].iter().all(|x| x.abs() < 1e-3));
+    }
+}
@@ -0,0 +1,396 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use assert_no_alloc::permit_alloc;
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{U0, U3};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+        resources::SampleKey,
+    },
+    nodes::{
+        audio::panner::{PanLaw, pan_gains},
+        utils::{
+            interp::{Interp, Interpolator},
+            port_utils::generate_audio_outputs,
+            rng::Rng,
+        },
+    },
+};
+
+/// The envelope over each grain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GrainWindow {
+    /// Smooth, the usual choice
+    #[default]
+    Hann,
+    /// Sharper in the middle, so dense clouds sound a bit more pointed
+    Triangle,
+    /// Flat in the middle, with cosine fades over the first and last quarter.
+    /// Keeps more of the source, i.e the transients of a drum loop.
+    Tukey,
+}
+
+impl GrainWindow {
+    /// The gain at x, from 0.0 to 1.0 through the grain
+    #[inline(always)]
+    fn gain(&self, x: f32) -> f32 {
+        match self {
+            GrainWindow::Hann => 0.5 - 0.5 * (TAU * x).cos(),
+            GrainWindow::Triangle => 1.0 - (2.0 * x - 1.0).abs(),
+            GrainWindow::Tukey => {
+                let edge = x.min(1.0 - x);
+                if edge >= 0.25 {
+                    1.0
+                } else {
+                    0.5 - 0.5 * (TAU * 2.0 * edge).cos()
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GranularParams {
+    /// How long each grain lasts
+    pub grain_size: Duration,
+    /// How many grains start per second
+    pub density: f32,
+    /// Where grains start in the sample, from 0.0 to 1.0
+    pub position: f32,
+    /// How far the start can wander either side of the position, as a fraction of the sample
+    pub position_jitter: f32,
+    /// The playback pitch of each grain, in semitones
+    pub pitch: f32,
+    /// How far the pitch can wander either side, in semitones
+    pub pitch_jitter: f32,
+    pub window: GrainWindow,
+    /// How far grains can be panned from the center, from 0.0 to 1.0. Only used for stereo.
+    pub spread: f32,
+    /// The most grains that can play at once. Grains past this are dropped.
+    pub voices: usize,
+    pub interp: Interp,
+    pub seed: u64,
+}
+
+impl Default for GranularParams {
+    fn default() -> Self {
+        Self {
+            grain_size: Duration::from_millis(100),
+            density: 20.0,
+            position: 0.0,
+            position_jitter: 0.0,
+            pitch: 0.0,
+            pitch_jitter: 0.0,
+            window: GrainWindow::Hann,
+            spread: 0.0,
+            voices: 32,
+            interp: Interp::Linear,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Grain<C>
+where
+    C: ArrayLength,
+{
+    active: bool,
+    // In samples of the source, fractional for pitched grains
+    pos: f64,
+    step: f64,
+    age: usize,
+    len: usize,
+    gains: GenericArray<f32, C>,
+    interpolators: GenericArray<Interpolator, C>,
+}
+
+/// A granular synth, playing short windowed grains from a sample.
+///
+/// All of the voices are made up front, so the audio thread never allocates.
+/// Grains wrap around the end of the sample, which suits loops.
+///
+/// The "position", "density" and "pitch" control inputs are added to the
+/// params, so the position can be scrubbed with an LFO or an envelope.
+/// Each output channel reads the matching channel of the sample, or the
+/// last one if the sample has fewer.
+pub struct Granular<C>
+where
+    C: ArrayLength,
+{
+    sample_key: SampleKey,
+    params: GranularParams,
+    grains: Vec<Grain<C>>,
+    // Samples until the next grain starts
+    until_next: f64,
+    rng: Rng,
+    ports: Ports<U0, C, U3, U0>,
+}
+
+impl<C> Granular<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(sample_key: SampleKey, params: GranularParams) -> Self {
+        let grain = Grain {
+            active: false,
+            pos: 0.0,
+            step: 1.0,
+            age: 0,
+            len: 0,
+            gains: GenericArray::generate(|_| 1.0),
+            interpolators: GenericArray::generate(|_| Interpolator::new(params.interp)),
+        };
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "position",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "density",
+                    index: 1
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "pitch",
+                    index: 2
+                },
+            },
+        ];
+        Self {
+            sample_key,
+            params,
+            grains: vec![grain; params.voices.max(1)],
+            until_next: 0.0,
+            rng: Rng::new(params.seed),
+            ports: Ports {
+                audio_inputs: None,
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_inputs),
+                control_outputs: None,
+            },
+        }
+    }
+    fn start_grain(&mut self, position: f32, pitch: f32, len: usize, sample_len: usize) {
+        let Some(grain) = self.grains.iter_mut().find(|g| !g.active) else {
+            return;
+        };
+        let position = position + self.params.position_jitter * self.rng.next_bipolar();
+        let pitch = pitch + self.params.pitch_jitter * self.rng.next_bipolar();
+        let pan = self.params.spread * self.rng.next_bipolar();
+
+        grain.active = true;
+        grain.pos = position.rem_euclid(1.0) as f64 * sample_len as f64;
+        grain.step = 2.0_f64.powf(pitch as f64 / 12.0);
+        grain.age = 0;
+        grain.len = len;
+        if C::USIZE == 2 {
+            let (l, r) = pan_gains(PanLaw::ConstantPower, pan);
+            // Scaled, so a grain in the middle is as loud as it is in mono
+            grain.gains[0] = l * std::f32::consts::SQRT_2;
+            grain.gains[1] = r * std::f32::consts::SQRT_2;
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Granular<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        _: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        for chan in ao.iter_mut() {
+            chan.fill(0.0);
+        }
+
+        let fs = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let grain_len = ((self.params.grain_size.as_secs_f32() * fs) as usize).max(1);
+
+        permit_alloc(|| {
+            let Some(inner) = ctx.get_sample(self.sample_key) else {
+                return;
+            };
+            let buf = inner.data();
+            let sample_len = buf.first().map_or(0, |chan| chan.len());
+            if sample_len == 0 {
+                return;
+            }
+
+            for n in 0..AF::USIZE {
+                let m = (n / chunk).min(CF::USIZE - 1);
+                let control = |i: usize| ci.get(i).map_or(0.0, |x| x[m]);
+
+                let density = self.params.density + control(1);
+                if density > 0.0 {
+                    // Don't let a long gap hold off a density that was just turned up
+                    let period = fs as f64 / density as f64;
+                    self.until_next = self.until_next.min(period);
+                    if self.until_next <= 0.0 {
+                        self.until_next += period;
+                        let position = self.params.position + control(0);
+                        let pitch = self.params.pitch + control(2);
+                        self.start_grain(position, pitch, grain_len, sample_len);
+                    }
+                    self.until_next -= 1.0;
+                }
+
+                for grain in self.grains.iter_mut().filter(|g| g.active) {
+                    let window = self.params.window.gain(grain.age as f32 / grain.len as f32);
+                    let index = grain.pos.floor();
+                    let t = (grain.pos - index) as f32;
+                    for (c, out) in ao.iter_mut().enumerate() {
+                        let chan = &buf[c.min(buf.len() - 1)];
+                        let x = grain.interpolators[c].read(index as isize, t, |i| {
+                            chan[i.rem_euclid(sample_len as isize) as usize]
+                        });
+                        out[n] += x * window * grain.gains[c];
+                    }
+
+                    grain.pos = (grain.pos + grain.step) % sample_len as f64;
+                    grain.age += 1;
+                    grain.active = grain.age < grain.len;
+                }
+            }
+        })
+    }
+}
+
+impl<C> PortedErased for Granular<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        None
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type GranularMono = Granular<Mono>;
+pub type GranularStereo = Granular<Stereo>;
+pub type GranularMC<C> = Granular<C>;
+
+#[cfg(test)]
+mod test {
+    use typenum::{U16, U64};
+
+    use super::*;
+    use crate::nodes::utils::test_utils::{FS, NodeRunner, add_sample, context};
+
+    fn run<C: ArrayLength>(
+        granular: &mut Granular<C>,
+        runner: &mut NodeRunner<U64, U16>,
+        blocks: usize,
+        position: f32,
+    ) -> Vec<Vec<f32>> {
+        runner.fit(granular);
+        runner.ci[0].fill(position);
+        runner.render(granular, blocks)
+    }
+
+    #[test]
+    fn grains_read_from_the_position() {
+        // A ramp, so the output tells us where each grain read from
+        let len = 48_000;
+        let ramp: Vec<f32> = (0..len).map(|n| n as f32 / len as f32).collect();
+        let mut ctx = context();
+        let key = add_sample(&mut ctx, vec![ramp]);
+
+        let params = GranularParams {
+            grain_size: Duration::from_millis(10),
+            density: 50.0,
+            position: 0.25,
+            window: GrainWindow::Tukey,
+            ..Default::default()
+        };
+        let mut granular = GranularMono::new(key, params);
+        let mut runner = NodeRunner::with_context(ctx, &granular);
+        let out = &run(&mut granular, &mut runner, 600, 0.0)[0];
+
+        // Grains start every 960 samples, and last 480
+        for start in (0..out.len() - 960).step_by(960) {
+            // The flat middle of each grain is the ramp, from the position
+            let mid = start + 240;
+            let expected = 0.25 + 240.0 / len as f32;
+            assert!((out[mid] - expected).abs() < 1e-3, "{} {}", mid, out[mid]);
+            // And nothing plays between grains
+            assert_eq!(out[start + 700], 0.0);
+        }
+
+        // The position control scrubs
+        let mut granular = GranularMono::new(key, params);
+        let out = &run(&mut granular, &mut runner, 4, 0.5)[0];
+        assert!((out[240] - (0.75 + 240.0 / len as f32)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn voices_are_capped_and_pitch_shifts() {
+        let len = 4800;
+        let freq = 100.0;
+        let sine: Vec<f32> = (0..len)
+            .map(|n| (TAU * freq * n as f32 / FS).sin())
+            .collect();
+        let mut ctx = context();
+        let key = add_sample(&mut ctx, vec![sine]);
+
+        // An octave up doubles the zero crossings
+        let params = GranularParams {
+            grain_size: Duration::from_millis(50),
+            density: 20.0,
+            pitch: 12.0,
+            window: GrainWindow::Tukey,
+            ..Default::default()
+        };
+        let mut granular = GranularMono::new(key, params);
+        // Offset a little, so no zero crossing lands exactly on a sample
+        let mut runner = NodeRunner::with_context(ctx, &granular);
+        let out = &run(&mut granular, &mut runner, 36, 0.01)[0];
+        let flat = &out[600..1800];
+        let crossings = flat
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        assert_eq!(crossings, 5);
+
+        // Overlapping grains past the voice count are dropped
+        let params = GranularParams {
+            grain_size: Duration::from_millis(100),
+            density: 1000.0,
+            voices: 4,
+            window: GrainWindow::Tukey,
+            spread: 1.0,
+            ..Default::default()
+        };
+        let mut granular = GranularStereo::new(key, params);
+        run(&mut granular, &mut runner, 10, 0.0);
+        assert_eq!(granular.grains.len(), 4);
+        assert!(granular.grains.iter().all(|g| g.active));
+    }
+}
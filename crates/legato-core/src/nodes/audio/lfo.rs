@@ -0,0 +1,244 @@
+use std::f32::consts::TAU;
+
+use generic_array::arr;
+use typenum::{U0, U1, U2};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::rng::Rng,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    /// Rising ramp
+    Saw,
+    Square,
+    /// A new random value every cycle
+    SampleAndHold,
+    /// Random values, with a cosine glide between them
+    SmoothRandom,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LfoRate {
+    /// Free running, in cycles per second
+    Hz(f32),
+    /// Synced to the context tempo, in beats per cycle. i.e 4.0 is a bar in 4/4,
+    /// 0.25 a sixteenth note
+    Sync(f32),
+}
+
+impl Default for LfoRate {
+    fn default() -> Self {
+        Self::Hz(1.0)
+    }
+}
+
+/// A low frequency oscillator, running at the control rate.
+///
+/// The "rate" control input is added to the rate in Hz, or in cycles per
+/// beat when synced. The "reset" control input restarts the cycle from the
+/// phase offset at a rising edge (> 0.5).
+///
+/// Bipolar output is -1.0 to 1.0, unipolar 0.0 to 1.0.
+pub struct Lfo {
+    shape: LfoShape,
+    rate: LfoRate,
+    phase_offset: f32,
+    bipolar: bool,
+    phase: f32,
+    last_reset: f32,
+    rng: Rng,
+    // The last and next random values, for the random shapes
+    random: (f32, f32),
+    ports: Ports<U0, U0, U2, U1>,
+}
+
+impl Lfo {
+    pub fn new(
+        shape: LfoShape,
+        rate: LfoRate,
+        phase_offset: f32,
+        bipolar: bool,
+        seed: u64,
+    ) -> Self {
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "rate",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "reset",
+                    index: 1
+                },
+            },
+        ];
+        let control_outputs = arr![ControlOutputPort {
+            meta: PortMeta {
+                name: "out",
+                index: 0
+            },
+        }];
+
+        let mut rng = Rng::new(seed);
+        let random = (rng.next_bipolar(), rng.next_bipolar());
+
+        Self {
+            shape,
+            rate,
+            phase_offset: phase_offset.rem_euclid(1.0),
+            bipolar,
+            phase: 0.0,
+            last_reset: 0.0,
+            rng,
+            random,
+            ports: Ports {
+                audio_inputs: None,
+                audio_outputs: None,
+                control_inputs: Some(control_inputs),
+                control_outputs: Some(control_outputs),
+            },
+        }
+    }
+    /// Bipolar value at the current phase
+    #[inline(always)]
+    fn tick(&self) -> f32 {
+        let t = (self.phase + self.phase_offset).fract();
+        match self.shape {
+            LfoShape::Sine => (TAU * t).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (t - 0.5).abs(),
+            LfoShape::Saw => 2.0 * t - 1.0,
+            LfoShape::Square => {
+                if t < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            LfoShape::SampleAndHold => self.random.0,
+            LfoShape::SmoothRandom => {
+                // The random values change on the raw phase, so the offset doesn't cause a jump
+                let x = 0.5 - 0.5 * (std::f32::consts::PI * self.phase).cos();
+                self.random.0 + (self.random.1 - self.random.0) * x
+            }
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for Lfo
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        _: &Frame<AF>,
+        _: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        co: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ci.len(), 2);
+        debug_assert_eq!(co.len(), 1);
+
+        let control_rate = ctx.get_control_rate();
+        let beats_per_second = ctx.get_bpm() / 60.0;
+
+        for n in 0..CF::USIZE {
+            let reset = ci[1][n];
+            if self.last_reset <= 0.5 && reset > 0.5 {
+                self.phase = 0.0;
+            }
+            self.last_reset = reset;
+
+            let value = self.tick();
+            co[0][n] = if self.bipolar {
+                value
+            } else {
+                0.5 * value + 0.5
+            };
+
+            let freq = match self.rate {
+                LfoRate::Hz(hz) => hz + ci[0][n],
+                LfoRate::Sync(beats) if beats > 0.0 => (1.0 / beats + ci[0][n]) * beats_per_second,
+                LfoRate::Sync(_) => 0.0,
+            };
+
+            self.phase += (freq / control_rate).max(0.0);
+            if self.phase >= 1.0 {
+                self.phase = self.phase.fract();
+                self.random = (self.random.1, self.rng.next_bipolar());
+            }
+        }
+    }
+}
+
+impl PortedErased for Lfo {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        None
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        None
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        self.ports.get_control_outputs()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use typenum::{U16, U64};
+
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    use super::{Lfo, LfoRate, LfoShape};
+
+    // The runner has 16 control samples per 64 sample block
+    const CONTROL_RATE: f32 = FS / 4.0;
+
+    fn render(lfo: &mut Lfo, runner: &mut NodeRunner<U64, U16>, samples: usize) -> Vec<f32> {
+        let mut out = Vec::new();
+        while out.len() < samples {
+            runner.process(lfo);
+            out.extend_from_slice(&runner.co[0]);
+        }
+        out
+    }
+
+    #[test]
+    fn unipolar_square_with_phase_offset() {
+        let mut lfo = Lfo::new(LfoShape::Square, LfoRate::Hz(1.0), 0.5, false, 0);
+        let mut runner = NodeRunner::new(&lfo);
+
+        let out = render(&mut lfo, &mut runner, CONTROL_RATE as usize);
+        // Half a cycle of offset starts the square low
+        assert_eq!(out[0], 0.0);
+        assert_eq!(out[CONTROL_RATE as usize / 2 + 1], 1.0);
+    }
+
+    #[test]
+    fn tempo_sync_follows_the_context() {
+        // One cycle per beat, at 120bpm is 2Hz, so 2 wraps in 1.25 seconds
+        let mut lfo = Lfo::new(LfoShape::Saw, LfoRate::Sync(1.0), 0.0, true, 0);
+        let mut runner = NodeRunner::new(&lfo);
+        runner.ctx.set_bpm(120.0);
+        let out = render(&mut lfo, &mut runner, (CONTROL_RATE * 1.25) as usize);
+
+        let wraps = out.windows(2).filter(|x| x[1] < x[0]).count();
+        assert_eq!(wraps, 2);
+    }
+}
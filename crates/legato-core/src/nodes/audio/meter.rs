@@ -0,0 +1,552 @@
+use std::{
+    f64::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use generic_array::{ArrayLength, GenericArray, sequence::GenericSequence};
+use portable_atomic::AtomicF32;
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::{
+        audio::filters::biquad::{BiquadCoeffs, BiquadState},
+        utils::{
+            filters::{Ballistics, OnePoleLp, TruePeak},
+            gain::gain_to_db,
+            port_utils::{generate_audio_inputs, generate_audio_outputs},
+        },
+    },
+};
+
+/// Loudness is gathered in 100ms steps, as in EBU R128
+const SUB_BLOCK: Duration = Duration::from_millis(100);
+/// 400ms of sub blocks for the momentary loudness
+const MOMENTARY_BLOCKS: usize = 4;
+/// 3s of sub blocks for the short term loudness
+const SHORT_TERM_BLOCKS: usize = 30;
+
+// The integrated loudness keeps a histogram of the 400ms blocks, in 0.1 LU steps
+const ABSOLUTE_GATE: f32 = -70.0;
+const RELATIVE_GATE: f32 = -10.0;
+const HISTOGRAM_MAX: f32 = 10.0;
+const HISTOGRAM_STEP: f32 = 0.1;
+const HISTOGRAM_BINS: usize = ((HISTOGRAM_MAX - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize;
+
+/// Mean squares below this read as silence, about -180dB
+const SILENT_POWER: f64 = 1e-18;
+
+/// How the meter readings move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterParams {
+    /// How long the peak holds stay up before falling
+    pub hold: Duration,
+    /// How fast the peaks and holds fall, in dB per second
+    pub decay: f32,
+    /// The averaging time for the RMS and correlation
+    pub rms_window: Duration,
+}
+
+impl Default for MeterParams {
+    fn default() -> Self {
+        Self {
+            hold: Duration::from_millis(1500),
+            decay: 20.0,
+            rms_window: Duration::from_millis(300),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChannelReadings {
+    peak: AtomicF32,
+    peak_hold: AtomicF32,
+    rms: AtomicF32,
+    true_peak: AtomicF32,
+    true_peak_hold: AtomicF32,
+}
+
+#[derive(Debug)]
+struct MeterReadings {
+    channels: Box<[ChannelReadings]>,
+    momentary: AtomicF32,
+    short_term: AtomicF32,
+    integrated: AtomicF32,
+    correlation: AtomicF32,
+    reset: AtomicBool,
+}
+
+/// The UI side of a meter.
+///
+/// Readings are written once per block by the audio thread, and can be
+/// read from any thread without locking. Levels are in dBFS, loudness
+/// is in LUFS, and anything silent reads as about -180.
+#[derive(Debug, Clone)]
+pub struct MeterHandle {
+    readings: Arc<MeterReadings>,
+}
+
+impl MeterHandle {
+    fn new(chans: usize) -> Self {
+        let silent = gain_to_db(0.0);
+        let channels = (0..chans)
+            .map(|_| ChannelReadings {
+                peak: AtomicF32::new(silent),
+                peak_hold: AtomicF32::new(silent),
+                rms: AtomicF32::new(silent),
+                true_peak: AtomicF32::new(silent),
+                true_peak_hold: AtomicF32::new(silent),
+            })
+            .collect();
+        let silent_lufs = power_to_lufs(0.0);
+        Self {
+            readings: Arc::new(MeterReadings {
+                channels,
+                momentary: AtomicF32::new(silent_lufs),
+                short_term: AtomicF32::new(silent_lufs),
+                integrated: AtomicF32::new(silent_lufs),
+                correlation: AtomicF32::new(0.0),
+                reset: AtomicBool::new(false),
+            }),
+        }
+    }
+    pub fn chans(&self) -> usize {
+        self.readings.channels.len()
+    }
+    /// The sample peak, falling at the decay rate
+    pub fn peak(&self, chan: usize) -> f32 {
+        self.readings.channels[chan].peak.load(Ordering::Relaxed)
+    }
+    pub fn peak_hold(&self, chan: usize) -> f32 {
+        self.readings.channels[chan]
+            .peak_hold
+            .load(Ordering::Relaxed)
+    }
+    pub fn rms(&self, chan: usize) -> f32 {
+        self.readings.channels[chan].rms.load(Ordering::Relaxed)
+    }
+    /// The 4x oversampled peak, falling at the decay rate
+    pub fn true_peak(&self, chan: usize) -> f32 {
+        self.readings.channels[chan]
+            .true_peak
+            .load(Ordering::Relaxed)
+    }
+    pub fn true_peak_hold(&self, chan: usize) -> f32 {
+        self.readings.channels[chan]
+            .true_peak_hold
+            .load(Ordering::Relaxed)
+    }
+    /// Loudness over the last 400ms
+    pub fn momentary(&self) -> f32 {
+        self.readings.momentary.load(Ordering::Relaxed)
+    }
+    /// Loudness over the last 3s
+    pub fn short_term(&self) -> f32 {
+        self.readings.short_term.load(Ordering::Relaxed)
+    }
+    /// Gated loudness since the meter started, or was last reset
+    pub fn integrated(&self) -> f32 {
+        self.readings.integrated.load(Ordering::Relaxed)
+    }
+    /// The correlation of the first two channels, from -1.0 to 1.0.
+    ///
+    /// Mono meters and silence read 0.0.
+    pub fn correlation(&self) -> f32 {
+        self.readings.correlation.load(Ordering::Relaxed)
+    }
+    /// Clears the holds and the integrated loudness on the next block
+    pub fn reset(&self) {
+        self.readings.reset.store(true, Ordering::Relaxed);
+    }
+}
+
+#[inline(always)]
+fn power_to_lufs(power: f64) -> f32 {
+    (-0.691 + 10.0 * power.max(SILENT_POWER).log10()) as f32
+}
+
+/// The two ITU-R BS.1770 K-weighting stages, a high shelf then a high pass.
+///
+/// The standard only lists coefficients for 48kHz, so these are the analog
+/// prototypes re-derived for any sample rate.
+fn k_weighting(fs: f32) -> [BiquadCoeffs; 2] {
+    let fs = fs as f64;
+
+    let f0 = 1681.974450955533;
+    let g = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / fs).tan();
+    let vh = 10.0_f64.powf(g / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = BiquadCoeffs {
+        b0: ((vh + vb * k / q + k * k) / a0) as f32,
+        b1: (2.0 * (k * k - vh) / a0) as f32,
+        b2: ((vh - vb * k / q + k * k) / a0) as f32,
+        a1: (2.0 * (k * k - 1.0) / a0) as f32,
+        a2: ((1.0 - k / q + k * k) / a0) as f32,
+    };
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = BiquadCoeffs {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: (2.0 * (k * k - 1.0) / a0) as f32,
+        a2: ((1.0 - k / q + k * k) / a0) as f32,
+    };
+
+    [shelf, high_pass]
+}
+
+/// A pass-through meter, publishing levels to a `MeterHandle`.
+///
+/// Per channel, it reads the sample peak, RMS and 4x oversampled true peak,
+/// with holds. The loudness follows EBU R128, with every channel weighted
+/// equally, and the correlation is taken between the first two channels.
+///
+/// Readings are published at the end of every block.
+pub struct Meter<C>
+where
+    C: ArrayLength,
+{
+    params: MeterParams,
+    handle: MeterHandle,
+    meter_fs: f32,
+    sub_block_len: usize,
+    k_coeffs: [BiquadCoeffs; 2],
+    // Per channel
+    k_state: GenericArray<[BiquadState; 2], C>,
+    mean_square: GenericArray<OnePoleLp, C>,
+    true_peaks: GenericArray<TruePeak, C>,
+    peaks: GenericArray<Ballistics, C>,
+    true_peak_levels: GenericArray<Ballistics, C>,
+    // Loudness
+    sub_block_sum: f64,
+    sub_block_count: usize,
+    sub_blocks: [f64; SHORT_TERM_BLOCKS],
+    sub_block_index: usize,
+    sub_blocks_seen: usize,
+    histogram_counts: Vec<u32>,
+    histogram_powers: Vec<f64>,
+    integrated: f32,
+    // Correlation of the first two channels
+    products: [OnePoleLp; 3],
+    ports: Ports<C, C, U0, U0>,
+}
+
+impl<C> Meter<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(params: MeterParams) -> (Self, MeterHandle) {
+        let handle = MeterHandle::new(C::USIZE);
+        let node = Self {
+            params,
+            handle: handle.clone(),
+            meter_fs: 0.0,
+            sub_block_len: 1,
+            k_coeffs: [BiquadCoeffs::IDENTITY; 2],
+            k_state: GenericArray::generate(|_| [BiquadState::default(); 2]),
+            mean_square: GenericArray::generate(|_| OnePoleLp::default()),
+            true_peaks: GenericArray::generate(|_| TruePeak::default()),
+            peaks: GenericArray::generate(|_| Ballistics::default()),
+            true_peak_levels: GenericArray::generate(|_| Ballistics::default()),
+            sub_block_sum: 0.0,
+            sub_block_count: 0,
+            sub_blocks: [0.0; SHORT_TERM_BLOCKS],
+            sub_block_index: 0,
+            sub_blocks_seen: 0,
+            histogram_counts: vec![0; HISTOGRAM_BINS],
+            histogram_powers: vec![0.0; HISTOGRAM_BINS],
+            integrated: power_to_lufs(0.0),
+            products: [OnePoleLp::default(); 3],
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        };
+        (node, handle)
+    }
+    fn prepare(&mut self, fs: f32) {
+        if fs == self.meter_fs {
+            return;
+        }
+        self.k_coeffs = k_weighting(fs);
+        self.mean_square
+            .iter_mut()
+            .chain(self.products.iter_mut())
+            .for_each(|x| x.set_time(self.params.rms_window, fs));
+        self.sub_block_len = ((SUB_BLOCK.as_secs_f32() * fs) as usize).max(1);
+        self.meter_fs = fs;
+    }
+    fn reset(&mut self) {
+        self.peaks.fill(Ballistics::default());
+        self.true_peak_levels.fill(Ballistics::default());
+        self.sub_blocks = [0.0; SHORT_TERM_BLOCKS];
+        self.sub_blocks_seen = 0;
+        self.histogram_counts.fill(0);
+        self.histogram_powers.fill(0.0);
+        self.integrated = power_to_lufs(0.0);
+    }
+    /// Closes a 100ms sub block, updating the loudness windows
+    fn end_sub_block(&mut self) {
+        self.sub_blocks[self.sub_block_index] = self.sub_block_sum / self.sub_block_count as f64;
+        self.sub_block_index = (self.sub_block_index + 1) % SHORT_TERM_BLOCKS;
+        self.sub_blocks_seen += 1;
+        self.sub_block_sum = 0.0;
+        self.sub_block_count = 0;
+
+        // 400ms gating blocks overlap by 75%, so one ends with every sub block
+        if self.sub_blocks_seen >= MOMENTARY_BLOCKS {
+            let power = self.momentary_power();
+            let lufs = power_to_lufs(power);
+            if lufs > ABSOLUTE_GATE {
+                let bin =
+                    (((lufs - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize).min(HISTOGRAM_BINS - 1);
+                self.histogram_counts[bin] += 1;
+                self.histogram_powers[bin] += power;
+                self.integrated = self.gated_loudness();
+            }
+        }
+    }
+    fn momentary_power(&self) -> f64 {
+        (1..=MOMENTARY_BLOCKS)
+            .map(|i| {
+                self.sub_blocks[(self.sub_block_index + SHORT_TERM_BLOCKS - i) % SHORT_TERM_BLOCKS]
+            })
+            .sum::<f64>()
+            / MOMENTARY_BLOCKS as f64
+    }
+    /// The mean of every block above the absolute gate, then again for
+    /// every block within 10 LU of that
+    fn gated_loudness(&self) -> f32 {
+        let mean_above = |first_bin: usize| {
+            let (count, power) = self.histogram_counts[first_bin..]
+                .iter()
+                .zip(&self.histogram_powers[first_bin..])
+                .fold((0, 0.0), |(n, p), (count, power)| (n + count, p + power));
+            if count > 0 { power / count as f64 } else { 0.0 }
+        };
+        let gate = power_to_lufs(mean_above(0)) + RELATIVE_GATE;
+        let first_bin = ((gate - ABSOLUTE_GATE).max(0.0) / HISTOGRAM_STEP) as usize;
+        power_to_lufs(mean_above(first_bin.min(HISTOGRAM_BINS - 1)))
+    }
+    fn publish(&self) {
+        let readings = &self.handle.readings;
+        for (c, channel) in readings.channels.iter().enumerate() {
+            channel.peak.store(self.peaks[c].level, Ordering::Relaxed);
+            channel
+                .peak_hold
+                .store(self.peaks[c].hold, Ordering::Relaxed);
+            channel.rms.store(
+                gain_to_db(self.mean_square[c].value().max(0.0).sqrt()),
+                Ordering::Relaxed,
+            );
+            channel
+                .true_peak
+                .store(self.true_peak_levels[c].level, Ordering::Relaxed);
+            channel
+                .true_peak_hold
+                .store(self.true_peak_levels[c].hold, Ordering::Relaxed);
+        }
+
+        let momentary = power_to_lufs(self.momentary_power());
+        let short_term =
+            power_to_lufs(self.sub_blocks.iter().sum::<f64>() / SHORT_TERM_BLOCKS as f64);
+        readings.momentary.store(momentary, Ordering::Relaxed);
+        readings.short_term.store(short_term, Ordering::Relaxed);
+        readings
+            .integrated
+            .store(self.integrated, Ordering::Relaxed);
+
+        let [lr, ll, rr] = self.products.map(|x| x.value());
+        let correlation = if ll * rr > 1e-18 {
+            (lr / (ll * rr).sqrt()).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        readings.correlation.store(correlation, Ordering::Relaxed);
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Meter<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        let fs = ctx.get_sample_rate();
+        self.prepare(fs);
+        if self.handle.readings.reset.swap(false, Ordering::Relaxed) {
+            self.reset();
+        }
+
+        let block_secs = AF::USIZE as f32 / fs;
+        for (c, (input, output)) in ai.iter().zip(ao.iter_mut()).enumerate() {
+            let mut peak = 0.0_f32;
+            let mut true_peak = 0.0_f32;
+            for &x in input.iter() {
+                peak = peak.max(x.abs());
+                true_peak = true_peak.max(self.true_peaks[c].tick(x));
+                self.mean_square[c].tick(x * x);
+            }
+            let MeterParams { hold, decay, .. } = self.params;
+            self.peaks[c].update(gain_to_db(peak), hold, decay, block_secs);
+            self.true_peak_levels[c].update(gain_to_db(true_peak), hold, decay, block_secs);
+            output.copy_from_slice(input);
+        }
+
+        if C::USIZE >= 2 {
+            let [lr, ll, rr] = &mut self.products;
+            for (l, r) in ai[0].iter().zip(ai[1].iter()) {
+                lr.tick(l * r);
+                ll.tick(l * l);
+                rr.tick(r * r);
+            }
+        }
+
+        for n in 0..AF::USIZE {
+            let mut power = 0.0;
+            for (input, [shelf, high_pass]) in ai.iter().zip(self.k_state.iter_mut()) {
+                let y = high_pass.tick(&self.k_coeffs[1], shelf.tick(&self.k_coeffs[0], input[n]));
+                power += (y * y) as f64;
+            }
+            self.sub_block_sum += power;
+            self.sub_block_count += 1;
+            if self.sub_block_count == self.sub_block_len {
+                self.end_sub_block();
+            }
+        }
+
+        self.publish();
+    }
+}
+
+impl<C> PortedErased for Meter<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type MeterMono = Meter<Mono>;
+pub type MeterStereo = Meter<Stereo>;
+pub type MeterMC<C> = Meter<C>;
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::TAU;
+
+    use typenum::{U64, Unsigned};
+
+    use super::*;
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    fn run<C: ArrayLength>(meter: &mut Meter<C>, secs: f32, signal: impl Fn(usize, usize) -> f32) {
+        let mut runner = NodeRunner::new(meter);
+        let blocks = (secs * FS) as usize / U64::USIZE;
+        for b in 0..blocks {
+            for (c, buf) in runner.ai.iter_mut().enumerate() {
+                for (n, x) in buf.iter_mut().enumerate() {
+                    *x = signal(c, b * U64::USIZE + n);
+                }
+            }
+            runner.process(meter);
+            assert_eq!(runner.ai, runner.ao);
+        }
+    }
+
+    #[test]
+    fn loudness_matches_the_reference_sine() {
+        // A full scale 1kHz sine in one channel is -3.01 LUFS
+        let (mut meter, handle) = MeterMono::new(MeterParams::default());
+        run(&mut meter, 4.0, |_, n| (TAU * 1000.0 * n as f32 / FS).sin());
+
+        assert!(
+            (handle.momentary() + 3.01).abs() < 0.05,
+            "{}",
+            handle.momentary()
+        );
+        assert!((handle.short_term() + 3.01).abs() < 0.05);
+        assert!((handle.integrated() + 3.01).abs() < 0.05);
+        assert!((handle.rms(0) + 3.01).abs() < 0.05);
+        assert!(handle.peak(0) <= 0.0 && handle.peak(0) > -0.01);
+
+        // The quiet tail is gated out of the integrated loudness. Only the 3 blocks
+        // straddling the change get through, pulling 37 full blocks down to -3.18
+        run(&mut meter, 4.0, |_, n| {
+            0.001 * (TAU * 1000.0 * n as f32 / FS).sin()
+        });
+        assert!(handle.short_term() < -60.0);
+        assert!((handle.integrated() + 3.18).abs() < 0.05);
+
+        handle.reset();
+        run(&mut meter, 0.1, |_, _| 0.0);
+        assert!(handle.integrated() < -170.0);
+    }
+
+    #[test]
+    fn peaks_hold_then_decay() {
+        let params = MeterParams {
+            hold: Duration::from_millis(500),
+            decay: 20.0,
+            ..Default::default()
+        };
+        let (mut meter, handle) = MeterStereo::new(params);
+
+        // Between two samples, so the true peak reads higher than the sample peak
+        let f = FS / 4.0;
+        run(&mut meter, 0.1, |c, n| {
+            let x = 0.5 * (TAU * f * n as f32 / FS + std::f32::consts::FRAC_PI_4).sin();
+            if c == 0 { x } else { -x }
+        });
+        assert!(handle.true_peak(0) > handle.peak(0) + 2.0);
+        assert!(handle.correlation() < -0.99);
+
+        // 400ms later, the peak has fallen by 8dB, and the hold has not
+        let held = handle.peak_hold(0);
+        run(&mut meter, 0.4, |_, _| 0.0);
+        assert_eq!(handle.peak_hold(0), held);
+        assert!((handle.peak(0) - (held - 8.0)).abs() < 0.5);
+
+        run(&mut meter, 0.6, |_, _| 0.0);
+        assert!(handle.peak_hold(0) < held - 1.0);
+    }
+}
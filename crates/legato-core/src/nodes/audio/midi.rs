@@ -0,0 +1,493 @@
+use std::time::Duration;
+
+use typenum::{U0, U1};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        midi::MidiMessage,
+        node::{FrameSize, Node},
+        port::{
+            AudioInputPort, AudioOutputPort, ControlInputPort, ControlOutputPort, PortedErased,
+            Ports,
+        },
+    },
+    nodes::utils::{filters::OnePoleLp, port_utils::generate_audio_outputs},
+};
+
+/// Converts incoming MIDI messages into a signal, one sample at a time.
+///
+/// Implement this to make your own MIDI -> signal nodes with MidiSignal.
+pub trait MidiConverter: Send {
+    fn handle(&mut self, message: &MidiMessage);
+    fn tick(&mut self, fs: f32) -> f32;
+}
+
+/// A node that reads the MIDI events for the current block from the
+/// audio context, and writes the converted signal to a single output.
+///
+/// Events are applied at their sample offset, so the output is sample accurate.
+/// The output is audio rate, so it can feed the fm input on Sine,
+/// an ApplyOp, or any other port expecting a signal.
+pub struct MidiSignal<T>
+where
+    T: MidiConverter,
+{
+    converter: T,
+    channel: Option<u8>, // None listens to all channels
+    ports: Ports<U0, U1, U0, U0>,
+}
+
+impl<T> MidiSignal<T>
+where
+    T: MidiConverter,
+{
+    pub fn with_converter(converter: T, channel: Option<u8>) -> Self {
+        Self {
+            converter,
+            channel,
+            ports: Ports {
+                audio_inputs: None,
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF, T> Node<AF, CF> for MidiSignal<T>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    T: MidiConverter,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        _: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        let fs = ctx.get_sample_rate();
+        let events = ctx.get_midi_events();
+
+        let mut cursor = 0;
+        for (n, out) in ao[0].iter_mut().enumerate() {
+            // Apply everything scheduled up to and including this sample
+            while cursor < events.len() && events[cursor].offset <= n {
+                let message = &events[cursor].message;
+                if self.channel.is_none_or(|c| c == message.channel()) {
+                    self.converter.handle(message);
+                }
+                cursor += 1;
+            }
+            *out = self.converter.tick(fs);
+        }
+    }
+}
+
+impl<T> PortedErased for MidiSignal<T>
+where
+    T: MidiConverter,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        None
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+// Max notes we keep track of for last note priority
+const NOTE_STACK_SIZE: usize = 16;
+
+/// Held notes, for last note priority.
+#[derive(Default)]
+struct NoteStack {
+    notes: heapless::Vec<u8, NOTE_STACK_SIZE>,
+}
+
+impl NoteStack {
+    fn press(&mut self, note: u8) {
+        self.release(note);
+        if self.notes.is_full() {
+            self.notes.remove(0);
+        }
+        let _ = self.notes.push(note);
+    }
+    fn release(&mut self, note: u8) {
+        if let Some(i) = self.notes.iter().position(|&x| x == note) {
+            self.notes.remove(i);
+        }
+    }
+    fn last(&self) -> Option<u8> {
+        self.notes.last().copied()
+    }
+    fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+}
+
+#[inline(always)]
+pub fn midi_to_freq(note: f32) -> f32 {
+    440.0 * ((note - 69.0) / 12.0).exp2()
+}
+
+/// Frequency in Hz of the last held note, with pitch bend applied.
+///
+/// The frequency is held after the note is released, so release stages still sound correct.
+pub struct NoteFreq {
+    stack: NoteStack,
+    note: f32,
+    bend: f32,
+    bend_range: f32, // In semitones
+    freq: f32,
+}
+
+impl NoteFreq {
+    pub fn new(bend_range: f32) -> Self {
+        Self {
+            stack: NoteStack::default(),
+            note: 69.0,
+            bend: 0.0,
+            bend_range,
+            freq: 440.0,
+        }
+    }
+    fn update_freq(&mut self) {
+        self.freq = midi_to_freq(self.note + self.bend * self.bend_range);
+    }
+}
+
+impl MidiConverter for NoteFreq {
+    fn handle(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn { note, .. } => {
+                self.stack.press(note);
+                self.note = note as f32;
+            }
+            MidiMessage::NoteOff { note, .. } => {
+                self.stack.release(note);
+                // Fall back to the previously held note
+                if let Some(last) = self.stack.last() {
+                    self.note = last as f32;
+                }
+            }
+            MidiMessage::PitchBend { value, .. } => self.bend = value,
+            _ => return,
+        }
+        self.update_freq();
+    }
+    #[inline(always)]
+    fn tick(&mut self, _: f32) -> f32 {
+        self.freq
+    }
+}
+
+/// 1.0 while any note is held, 0.0 otherwise.
+#[derive(Default)]
+pub struct NoteGate {
+    stack: NoteStack,
+}
+
+impl MidiConverter for NoteGate {
+    fn handle(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn { note, .. } => self.stack.press(note),
+            MidiMessage::NoteOff { note, .. } => self.stack.release(note),
+            _ => (),
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, _: f32) -> f32 {
+        if self.stack.is_empty() { 0.0 } else { 1.0 }
+    }
+}
+
+/// A single sample of 1.0 on every note on.
+#[derive(Default)]
+pub struct NoteTrigger {
+    pending: bool,
+}
+
+impl MidiConverter for NoteTrigger {
+    fn handle(&mut self, message: &MidiMessage) {
+        if let MidiMessage::NoteOn { .. } = message {
+            self.pending = true;
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, _: f32) -> f32 {
+        let out = if self.pending { 1.0 } else { 0.0 };
+        self.pending = false;
+        out
+    }
+}
+
+/// Velocity of the last note on, from 0.0 to 1.0.
+#[derive(Default)]
+pub struct NoteVelocity {
+    velocity: f32,
+}
+
+impl MidiConverter for NoteVelocity {
+    fn handle(&mut self, message: &MidiMessage) {
+        if let MidiMessage::NoteOn { velocity, .. } = *message {
+            self.velocity = velocity;
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, _: f32) -> f32 {
+        self.velocity
+    }
+}
+
+/// Channel or polyphonic pressure, from 0.0 to 1.0.
+#[derive(Default)]
+pub struct Aftertouch {
+    pressure: f32,
+}
+
+impl MidiConverter for Aftertouch {
+    fn handle(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::Aftertouch { pressure, .. }
+            | MidiMessage::PolyAftertouch { pressure, .. } => self.pressure = pressure,
+            _ => (),
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, _: f32) -> f32 {
+        self.pressure
+    }
+}
+
+/// A CC value from 0.0 to 1.0, smoothed with a one pole
+/// filter so that stepped 7 bit values don't zipper.
+pub struct ControlChange {
+    cc: u8,
+    smoothing: Duration,
+    target: f32,
+    smoother: OnePoleLp,
+    // Cached so we only recalculate the coefficient if the rate changes
+    coeff_fs: f32,
+}
+
+impl ControlChange {
+    pub fn new(cc: u8, smoothing: Duration) -> Self {
+        Self {
+            cc,
+            smoothing,
+            target: 0.0,
+            smoother: OnePoleLp::default(),
+            coeff_fs: 0.0,
+        }
+    }
+}
+
+impl MidiConverter for ControlChange {
+    fn handle(&mut self, message: &MidiMessage) {
+        if let MidiMessage::ControlChange { cc, value, .. } = *message
+            && cc == self.cc
+        {
+            self.target = value;
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, fs: f32) -> f32 {
+        if fs != self.coeff_fs {
+            self.smoother.set_time(self.smoothing, fs);
+            self.coeff_fs = fs;
+        }
+        self.smoother.tick(self.target)
+    }
+}
+
+pub type NoteToFreq = MidiSignal<NoteFreq>;
+pub type Gate = MidiSignal<NoteGate>;
+pub type Trigger = MidiSignal<NoteTrigger>;
+pub type Velocity = MidiSignal<NoteVelocity>;
+pub type AftertouchSignal = MidiSignal<Aftertouch>;
+pub type CcSignal = MidiSignal<ControlChange>;
+
+impl NoteToFreq {
+    pub fn new(bend_range: f32, channel: Option<u8>) -> Self {
+        Self::with_converter(NoteFreq::new(bend_range), channel)
+    }
+}
+
+impl CcSignal {
+    pub fn new(cc: u8, smoothing: Duration, channel: Option<u8>) -> Self {
+        Self::with_converter(ControlChange::new(cc, smoothing), channel)
+    }
+}
+
+impl<T> MidiSignal<T>
+where
+    T: MidiConverter + Default,
+{
+    pub fn new(channel: Option<u8>) -> Self {
+        Self::with_converter(T::default(), channel)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use typenum::{U16, U64};
+
+    use super::*;
+    use crate::{engine::midi::MidiEvent, nodes::utils::test_utils::NodeRunner};
+
+    fn note_on(channel: u8, note: u8, velocity: f32) -> MidiMessage {
+        MidiMessage::NoteOn {
+            channel,
+            note,
+            velocity,
+        }
+    }
+
+    fn note_off(channel: u8, note: u8) -> MidiMessage {
+        MidiMessage::NoteOff { channel, note }
+    }
+
+    /// Runs a block with events at the given offsets, returning the output
+    fn run<T: MidiConverter>(
+        node: &mut MidiSignal<T>,
+        events: &[(usize, MidiMessage)],
+    ) -> Vec<f32> {
+        let mut runner: NodeRunner<U64, U16> = NodeRunner::new(node);
+        for &(offset, message) in events {
+            runner.ctx.push_midi_event(MidiEvent { offset, message });
+        }
+        runner.process(node);
+        runner.ao[0].to_vec()
+    }
+
+    #[test]
+    fn note_to_freq_follows_the_last_note() {
+        let mut node = NoteToFreq::new(2.0, Some(0));
+        let out = run(
+            &mut node,
+            &[
+                (10, note_on(0, 57, 1.0)),
+                (20, note_on(0, 81, 1.0)),
+                (30, note_on(1, 60, 1.0)),
+                (40, note_off(0, 81)),
+                (
+                    50,
+                    MidiMessage::PitchBend {
+                        channel: 0,
+                        value: 1.0,
+                    },
+                ),
+            ],
+        );
+        assert!(out[..10].iter().all(|x| *x == 440.0));
+        assert!(out[10..20].iter().all(|x| *x == 220.0));
+        // The note on the other channel is ignored
+        assert!(out[20..40].iter().all(|x| *x == 880.0));
+        // Releasing falls back to the held note, then the bend adds 2 semitones
+        assert!(out[40..50].iter().all(|x| *x == 220.0));
+        assert!(out[50..].iter().all(|x| *x == midi_to_freq(59.0)));
+    }
+
+    #[test]
+    fn gates_and_triggers_land_on_their_offsets() {
+        // Any channel holds the gate open, until every note is released
+        let mut gate = Gate::new(None);
+        let out = run(
+            &mut gate,
+            &[
+                (8, note_on(0, 60, 1.0)),
+                (16, note_on(5, 62, 1.0)),
+                (24, note_off(0, 60)),
+                (32, note_off(5, 62)),
+            ],
+        );
+        let expected: Vec<f32> = (0..64)
+            .map(|n| if (8..32).contains(&n) { 1.0 } else { 0.0 })
+            .collect();
+        assert_eq!(out, expected);
+
+        let mut trigger = Trigger::new(Some(2));
+        let out = run(
+            &mut trigger,
+            &[
+                (5, note_on(2, 60, 1.0)),
+                (20, note_on(3, 60, 1.0)),
+                (40, note_on(2, 64, 1.0)),
+            ],
+        );
+        let expected: Vec<f32> = (0..64)
+            .map(|n| if n == 5 || n == 40 { 1.0 } else { 0.0 })
+            .collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn velocity_pressure_and_cc_values() {
+        let mut velocity = Velocity::new(Some(0));
+        let out = run(
+            &mut velocity,
+            &[(16, note_on(0, 60, 0.5)), (32, note_on(1, 60, 0.25))],
+        );
+        assert!(out[..16].iter().all(|x| *x == 0.0));
+        assert!(out[16..].iter().all(|x| *x == 0.5));
+
+        let mut aftertouch = AftertouchSignal::new(None);
+        let out = run(
+            &mut aftertouch,
+            &[
+                (
+                    10,
+                    MidiMessage::Aftertouch {
+                        channel: 0,
+                        pressure: 0.3,
+                    },
+                ),
+                (
+                    20,
+                    MidiMessage::PolyAftertouch {
+                        channel: 4,
+                        note: 60,
+                        pressure: 0.6,
+                    },
+                ),
+            ],
+        );
+        assert_eq!((out[9], out[10], out[20]), (0.0, 0.3, 0.6));
+
+        // Without smoothing, only the matching CC on the matching channel comes through
+        let mut cc = CcSignal::new(74, Duration::ZERO, Some(0));
+        let cc_message = |channel, cc, value| MidiMessage::ControlChange { channel, cc, value };
+        let out = run(
+            &mut cc,
+            &[
+                (12, cc_message(0, 74, 1.0)),
+                (20, cc_message(0, 1, 0.0)),
+                (30, cc_message(3, 74, 0.0)),
+            ],
+        );
+        assert!(out[..12].iter().all(|x| *x == 0.0));
+        assert!(out[12..].iter().all(|x| *x == 1.0));
+    }
+
+    #[test]
+    fn late_events_are_clamped_to_the_block() {
+        let mut trigger = Trigger::new(None);
+        let out = run(&mut trigger, &[(100, note_on(0, 60, 1.0))]);
+        assert_eq!(out[63], 1.0);
+        assert!(out[..63].iter().all(|x| *x == 0.0));
+    }
+}
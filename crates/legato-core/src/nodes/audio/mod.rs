@@ -1,10 +1,28 @@
+pub mod analysis;
 pub mod audio_ops;
+pub mod channel_mixer;
 pub mod delay;
+pub mod drum_rack;
+pub mod dynamics;
+pub mod envelope;
 pub mod filters;
+pub mod granular;
+pub mod lfo;
+pub mod meter;
+pub mod midi;
 pub mod mixer;
+pub mod modulation;
+pub mod noise;
+pub mod oscillator;
+pub mod panner;
 pub mod resample;
+pub mod reverb;
 pub mod sampler;
 pub mod sine;
+pub mod spectrum;
 pub mod stereo;
 pub mod subgraph;
 pub mod sweep;
+pub mod tap;
+pub mod waveshaper;
+pub mod wavetable;
@@ -0,0 +1,653 @@
+use std::{
+    f32::consts::{PI, TAU},
+    marker::PhantomData,
+};
+
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{U0, U3};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::{
+        audio::delay::DelayLine,
+        utils::{
+            interp::{Interp, Interpolator},
+            port_utils::{generate_audio_inputs, generate_audio_outputs},
+        },
+    },
+};
+
+/// The "rate", "depth" and "mix" control inputs, shared by the modulation effects
+fn modulation_control_inputs() -> GenericArray<ControlInputPort, U3> {
+    arr![
+        ControlInputPort {
+            meta: PortMeta {
+                name: "rate",
+                index: 0
+            },
+        },
+        ControlInputPort {
+            meta: PortMeta {
+                name: "depth",
+                index: 1
+            },
+        },
+        ControlInputPort {
+            meta: PortMeta {
+                name: "mix",
+                index: 2
+            },
+        },
+    ]
+}
+
+/// The shortest and longest delays, in samples, that can be read before writing
+/// the next sample, without the interpolation reaching past either end of the line
+fn delay_range(interp: Interp, capacity: usize) -> (f32, f32) {
+    let (before, after) = interp.reach();
+    (after as f32, capacity.saturating_sub(before + 2) as f32)
+}
+
+/// A sine LFO, with each channel offset across the cycle by the spread
+#[derive(Clone, Copy, Default)]
+struct ModLfo {
+    phase: f32,
+}
+
+impl ModLfo {
+    /// Unipolar 0.0 to 1.0 value for a channel
+    #[inline(always)]
+    fn value(&self, offset: f32) -> f32 {
+        0.5 - 0.5 * (TAU * (self.phase + offset)).cos()
+    }
+    #[inline(always)]
+    fn advance(&mut self, rate: f32, fs: f32) {
+        self.phase = (self.phase + rate.max(0.0) / fs).fract();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChorusParams {
+    /// LFO rate in Hz
+    pub rate: f32,
+    /// How far the delay swings, in milliseconds
+    pub depth: f32,
+    /// 0.0 is dry, 1.0 is fully wet
+    pub mix: f32,
+    /// The shortest delay, in milliseconds
+    pub delay: f32,
+    /// Number of voices, from 1 to 8
+    pub voices: usize,
+    /// 0.0 to 1.0, how far apart the channels are in the LFO cycle
+    pub spread: f32,
+}
+
+impl Default for ChorusParams {
+    fn default() -> Self {
+        Self {
+            rate: 0.8,
+            depth: 3.0,
+            mix: 0.5,
+            delay: 10.0,
+            voices: 3,
+            spread: 0.5,
+        }
+    }
+}
+
+// Upper limits, for sizing the delay lines
+const MAX_MOD_MS: f32 = 50.0;
+const MAX_VOICES: usize = 8;
+
+/// A multi voice chorus, with the voices spread evenly across the LFO
+/// cycle, and the channels spread apart for width.
+///
+/// The "rate", "depth" and "mix" control inputs are added to the base params.
+pub struct Chorus<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    params: ChorusParams,
+    line: DelayLine<AF, C>,
+    lfo: ModLfo,
+    interpolator: Interpolator,
+    ports: Ports<C, C, U3, U0>,
+    phantom: PhantomData<AF>,
+}
+
+impl<AF, C> Chorus<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    pub fn new(params: ChorusParams, sample_rate: f32) -> Self {
+        let capacity = (MAX_MOD_MS * 2.0 * sample_rate / 1000.0) as usize + 8;
+        Self {
+            params: ChorusParams {
+                voices: params.voices.clamp(1, MAX_VOICES),
+                ..params
+            },
+            line: DelayLine::new(capacity),
+            lfo: ModLfo::default(),
+            interpolator: Interpolator::new(Interp::Hermite),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(modulation_control_inputs()),
+                control_outputs: None,
+            },
+            phantom: PhantomData::<AF>,
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Chorus<AF, C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+        debug_assert_eq!(ci.len(), 3);
+
+        let fs = ctx.get_sample_rate();
+        let ms = fs / 1000.0;
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let (min_delay, max_delay) =
+            delay_range(self.interpolator.interp(), self.line.get_capacity());
+        let voices = self.params.voices;
+        let voice_gain = 1.0 / (voices as f32).sqrt();
+
+        for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let rate = self.params.rate + ci[0][m];
+            let depth = (self.params.depth + ci[1][m]).clamp(0.0, MAX_MOD_MS);
+            let mix = (self.params.mix + ci[2][m]).clamp(0.0, 1.0);
+
+            for c in 0..C::USIZE {
+                let x = ai[c][n];
+
+                let offset = self.params.spread * c as f32 / C::USIZE as f32;
+                let mut wet = 0.0;
+                for voice in 0..voices {
+                    let lfo = self.lfo.value(offset + voice as f32 / voices as f32);
+                    let delay =
+                        ((self.params.delay + depth * lfo) * ms).clamp(min_delay, max_delay);
+                    wet += self.line.get_delay_interp(c, delay, &mut self.interpolator);
+                }
+                self.line.write_sample(c, x);
+
+                ao[c][n] = x * (1.0 - mix) + wet * voice_gain * mix;
+            }
+            self.lfo.advance(rate, fs);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlangerParams {
+    /// LFO rate in Hz
+    pub rate: f32,
+    /// How far the delay swings, in milliseconds
+    pub depth: f32,
+    /// 0.0 is dry, 1.0 is fully wet. 0.5 gives the deepest notches.
+    pub mix: f32,
+    /// The shortest delay, in milliseconds
+    pub delay: f32,
+    /// -1.0 to 1.0, negative feedback hollows out the sound
+    pub feedback: f32,
+    /// 0.0 to 1.0, how far apart the channels are in the LFO cycle
+    pub spread: f32,
+    /// Delays the dry path to the middle of the sweep, so the wet
+    /// signal can pass through it, like flanging with two tape machines
+    pub through_zero: bool,
+}
+
+impl Default for FlangerParams {
+    fn default() -> Self {
+        Self {
+            rate: 0.2,
+            depth: 3.0,
+            mix: 0.5,
+            delay: 0.5,
+            feedback: 0.5,
+            spread: 0.25,
+            through_zero: false,
+        }
+    }
+}
+
+/// A flanger, with feedback and an optional through zero mode.
+///
+/// The "rate", "depth" and "mix" control inputs are added to the base params.
+pub struct Flanger<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    params: FlangerParams,
+    line: DelayLine<AF, C>,
+    // The dry signal without feedback, only used through zero
+    dry_line: DelayLine<AF, C>,
+    lfo: ModLfo,
+    interpolator: Interpolator,
+    ports: Ports<C, C, U3, U0>,
+    phantom: PhantomData<AF>,
+}
+
+impl<AF, C> Flanger<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    pub fn new(params: FlangerParams, sample_rate: f32) -> Self {
+        let capacity = (MAX_MOD_MS * 2.0 * sample_rate / 1000.0) as usize + 8;
+        Self {
+            params: FlangerParams {
+                // Keep a little headroom so the loop can't run away
+                feedback: params.feedback.clamp(-0.98, 0.98),
+                ..params
+            },
+            line: DelayLine::new(capacity),
+            dry_line: DelayLine::new(if params.through_zero { capacity } else { 1 }),
+            lfo: ModLfo::default(),
+            interpolator: Interpolator::new(Interp::Hermite),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(modulation_control_inputs()),
+                control_outputs: None,
+            },
+            phantom: PhantomData::<AF>,
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Flanger<AF, C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+        debug_assert_eq!(ci.len(), 3);
+
+        let fs = ctx.get_sample_rate();
+        let ms = fs / 1000.0;
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let (min_delay, max_delay) =
+            delay_range(self.interpolator.interp(), self.line.get_capacity());
+        let feedback = self.params.feedback;
+
+        for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let rate = self.params.rate + ci[0][m];
+            let depth = (self.params.depth + ci[1][m]).clamp(0.0, MAX_MOD_MS);
+            let mix = (self.params.mix + ci[2][m]).clamp(0.0, 1.0);
+
+            for c in 0..C::USIZE {
+                let x = ai[c][n];
+
+                let lfo = self
+                    .lfo
+                    .value(self.params.spread * c as f32 / C::USIZE as f32);
+                let delay = ((self.params.delay + depth * lfo) * ms).clamp(min_delay, max_delay);
+                let wet = self.line.get_delay_interp(c, delay, &mut self.interpolator);
+                self.line.write_sample(c, x + feedback * wet);
+
+                let dry = if self.params.through_zero {
+                    // The dry path sits in the middle of the sweep
+                    let center =
+                        ((self.params.delay + depth * 0.5) * ms).clamp(min_delay, max_delay);
+                    let dry = self
+                        .dry_line
+                        .get_delay_interp(c, center, &mut self.interpolator);
+                    self.dry_line.write_sample(c, x);
+                    dry
+                } else {
+                    x
+                };
+
+                ao[c][n] = dry * (1.0 - mix) + wet * mix;
+            }
+            self.lfo.advance(rate, fs);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhaserParams {
+    /// LFO rate in Hz
+    pub rate: f32,
+    /// 0.0 to 1.0, where 1.0 sweeps 3 octaves either side of the center
+    pub depth: f32,
+    /// 0.0 is dry, 1.0 is fully wet. 0.5 gives the deepest notches.
+    pub mix: f32,
+    /// Center of the sweep, in Hz
+    pub center: f32,
+    /// Number of first order allpass stages, from 2 to 12
+    pub stages: usize,
+    /// -1.0 to 1.0
+    pub feedback: f32,
+    /// 0.0 to 1.0, how far apart the channels are in the LFO cycle
+    pub spread: f32,
+}
+
+impl Default for PhaserParams {
+    fn default() -> Self {
+        Self {
+            rate: 0.5,
+            depth: 0.7,
+            mix: 0.5,
+            center: 800.0,
+            stages: 4,
+            feedback: 0.3,
+            spread: 0.25,
+        }
+    }
+}
+
+const MAX_STAGES: usize = 12;
+const PHASER_OCTAVES: f32 = 3.0;
+
+/// A phaser, sweeping a cascade of first order allpasses with feedback.
+///
+/// The "rate", "depth" and "mix" control inputs are added to the base params.
+pub struct Phaser<C>
+where
+    C: ArrayLength,
+{
+    params: PhaserParams,
+    lfo: ModLfo,
+    // Allpass state for each stage, and the last output for feedback
+    state: GenericArray<[f32; MAX_STAGES], C>,
+    last_wet: GenericArray<f32, C>,
+    ports: Ports<C, C, U3, U0>,
+}
+
+impl<C> Phaser<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(params: PhaserParams) -> Self {
+        Self {
+            params: PhaserParams {
+                stages: params.stages.clamp(2, MAX_STAGES),
+                feedback: params.feedback.clamp(-0.95, 0.95),
+                ..params
+            },
+            lfo: ModLfo::default(),
+            state: GenericArray::generate(|_| [0.0; MAX_STAGES]),
+            last_wet: GenericArray::generate(|_| 0.0),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(modulation_control_inputs()),
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Phaser<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+        debug_assert_eq!(ci.len(), 3);
+
+        let fs = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let stages = self.params.stages;
+        let feedback = self.params.feedback;
+
+        for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let rate = self.params.rate + ci[0][m];
+            let depth = (self.params.depth + ci[1][m]).clamp(0.0, 1.0);
+            let mix = (self.params.mix + ci[2][m]).clamp(0.0, 1.0);
+
+            for c in 0..C::USIZE {
+                let lfo = self
+                    .lfo
+                    .value(self.params.spread * c as f32 / C::USIZE as f32);
+                // Sweep exponentially, so it sounds even
+                let freq = (self.params.center
+                    * 2.0_f32.powf(depth * PHASER_OCTAVES * (2.0 * lfo - 1.0)))
+                .clamp(10.0, fs * 0.45);
+                let t = (PI * freq / fs).tan();
+                let a = (t - 1.0) / (t + 1.0);
+
+                let x = ai[c][n];
+                let mut y = x + feedback * self.last_wet[c];
+                for z in self.state[c].iter_mut().take(stages) {
+                    let out = a * y + *z;
+                    *z = y - a * out;
+                    y = out;
+                }
+                self.last_wet[c] = y;
+
+                ao[c][n] = x * (1.0 - mix) + y * mix;
+            }
+            self.lfo.advance(rate, fs);
+        }
+    }
+}
+
+impl<C> PortedErased for Phaser<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+impl<AF, C> PortedErased for Chorus<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+impl<AF, C> PortedErased for Flanger<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type ChorusMC<AF, C> = Chorus<AF, C>;
+pub type FlangerMC<AF, C> = Flanger<AF, C>;
+pub type PhaserMC<C> = Phaser<C>;
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::TAU;
+
+    use typenum::{U1, U16, U64, Unsigned};
+
+    use crate::{
+        engine::node::Node,
+        nodes::utils::test_utils::{FS, NodeRunner},
+    };
+
+    use super::{Chorus, ChorusParams, Flanger, FlangerParams, Phaser, PhaserParams};
+
+    /// Peak output level for a sine, after the effect settles
+    fn peak(node: &mut dyn Node<U64, U16>, freq: f32) -> f32 {
+        let mut runner = NodeRunner::new(node);
+
+        let mut peak = 0.0_f32;
+        for block in 0..1000 {
+            for (n, x) in runner.ai[0].iter_mut().enumerate() {
+                *x = (TAU * freq * (block * U64::USIZE + n) as f32 / FS).sin();
+            }
+            runner.process(node);
+            if block > 500 {
+                peak = runner.ao[0].iter().fold(peak, |acc, x| acc.max(x.abs()));
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn chorus_without_mix_is_dry() {
+        let params = ChorusParams {
+            mix: 0.0,
+            ..Default::default()
+        };
+        let mut chorus = Chorus::<U64, U1>::new(params, FS);
+        assert!((peak(&mut chorus, 440.0) - 1.0).abs() < 1e-3);
+    }
+
+    /// The first channel's response to an impulse at the start
+    fn impulse_response(node: &mut dyn Node<U64, U16>) -> Vec<f32> {
+        let mut runner = NodeRunner::new(node);
+        runner.ai[0][0] = 1.0;
+        runner.process(node);
+        let mut out = runner.ao[0].to_vec();
+        runner.ai[0][0] = 0.0;
+        runner.process(node);
+        out.extend_from_slice(&runner.ao[0]);
+        out
+    }
+
+    /// Checks that a response is the impulse, moved back by the delay
+    fn assert_delayed(response: &[f32], delay: usize) {
+        for (n, x) in response.iter().enumerate() {
+            let expected = if n == delay { 1.0 } else { 0.0 };
+            assert!((x - expected).abs() < 1e-6, "{} at {}", x, n);
+        }
+    }
+
+    #[test]
+    fn chorus_wet_is_a_delayed_copy() {
+        // 1.5 samples is too short for the interpolation, so it reads at 2
+        for (delay, samples) in [(1.0, 48), (1.5 / 48.0, 2)] {
+            let params = ChorusParams {
+                mix: 1.0,
+                depth: 0.0,
+                voices: 1,
+                delay,
+                ..Default::default()
+            };
+            let mut chorus = Chorus::<U64, U1>::new(params, FS);
+            assert_delayed(&impulse_response(&mut chorus), samples);
+        }
+    }
+
+    #[test]
+    fn flanger_wet_is_a_delayed_copy() {
+        for (delay, samples) in [(0.5, 24), (1.5 / 48.0, 2)] {
+            let params = FlangerParams {
+                mix: 1.0,
+                depth: 0.0,
+                feedback: 0.0,
+                delay,
+                ..Default::default()
+            };
+            let mut flanger = Flanger::<U64, U1>::new(params, FS);
+            assert_delayed(&impulse_response(&mut flanger), samples);
+        }
+    }
+
+    #[test]
+    fn phaser_notches_at_the_center() {
+        // Two stages are 180 degrees out at the center, so half wet cancels
+        let params = PhaserParams {
+            depth: 0.0,
+            feedback: 0.0,
+            stages: 2,
+            center: 1000.0,
+            mix: 0.5,
+            ..Default::default()
+        };
+        let mut phaser = Phaser::<U1>::new(params);
+        assert!(peak(&mut phaser, 1000.0) < 0.01);
+        assert!(peak(&mut phaser, 100.0) > 0.9);
+    }
+
+    #[test]
+    fn flanger_feedback_stays_stable() {
+        for through_zero in [false, true] {
+            let params = FlangerParams {
+                feedback: 1.5,
+                rate: 2.0,
+                through_zero,
+                ..Default::default()
+            };
+            let mut flanger = Flanger::<U64, U1>::new(params, FS);
+            let level = peak(&mut flanger, 440.0);
+            assert!(level.is_finite() && level < 50.0);
+        }
+    }
+}
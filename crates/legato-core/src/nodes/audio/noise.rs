@@ -0,0 +1,243 @@
+use generic_array::{ArrayLength, GenericArray, sequence::GenericSequence};
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::{filters::LeakyIntegrator, port_utils::generate_audio_outputs, rng::Rng},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseType {
+    White,
+    /// Voss-McCartney, -3dB per octave
+    Pink,
+    /// Leaky integrated white noise, -6dB per octave
+    Brown,
+    /// Sparse random +-1 impulses, with density in impulses per second
+    Velvet {
+        density: f32,
+    },
+}
+
+// Rows in the Voss-McCartney generator, giving a flat -3dB/oct slope for about 16 octaves
+const PINK_ROWS: usize = 16;
+
+struct NoiseState {
+    rng: Rng,
+    pink_rows: [f32; PINK_ROWS],
+    pink_sum: f32,
+    pink_counter: u32,
+    brown: LeakyIntegrator,
+    velvet_phase: f32,
+    velvet_at: f32,
+    velvet_fired: bool,
+}
+
+impl NoiseState {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            pink_rows: [0.0; PINK_ROWS],
+            pink_sum: 0.0,
+            pink_counter: 0,
+            brown: LeakyIntegrator::new(1.0 / 1.02),
+            velvet_phase: 0.0,
+            velvet_at: 0.0,
+            velvet_fired: false,
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, noise_type: NoiseType, fs: f32) -> f32 {
+        match noise_type {
+            NoiseType::White => self.rng.next_bipolar(),
+            NoiseType::Pink => {
+                // Every sample, one row is updated. Row k is updated every 2^k samples.
+                self.pink_counter = self.pink_counter.wrapping_add(1);
+                let row = self.pink_counter.trailing_zeros() as usize;
+                if row < PINK_ROWS {
+                    let white = self.rng.next_bipolar();
+                    self.pink_sum += white - self.pink_rows[row];
+                    self.pink_rows[row] = white;
+                }
+                let white = self.rng.next_bipolar();
+                // Half the RMS of the white noise. The rows add up, so peaks can pass 1.0.
+                (self.pink_sum + white) * 0.5 / ((PINK_ROWS + 1) as f32).sqrt()
+            }
+            NoiseType::Brown => self.brown.tick(self.rng.next_bipolar() * (0.02 / 1.02)) * 3.5,
+            NoiseType::Velvet { density } => {
+                // One impulse per period, at a random spot in the period
+                self.velvet_phase += (density / fs).clamp(0.0, 1.0);
+                if self.velvet_phase >= 1.0 {
+                    self.velvet_phase -= 1.0;
+                    self.velvet_at = self.rng.next_f32();
+                    self.velvet_fired = false;
+                }
+                if !self.velvet_fired && self.velvet_phase >= self.velvet_at {
+                    self.velvet_fired = true;
+                    if self.rng.next_u64() & 1 == 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// A noise source, seeded so that renders are reproducible.
+///
+/// Each output channel has its own generator, seeded from the
+/// seed and the channel index, so channels are decorrelated.
+pub struct Noise<Ao>
+where
+    Ao: ArrayLength,
+{
+    noise_type: NoiseType,
+    state: GenericArray<NoiseState, Ao>,
+    ports: Ports<U0, Ao, U0, U0>,
+}
+
+impl<Ao> Noise<Ao>
+where
+    Ao: ArrayLength,
+{
+    pub fn new(noise_type: NoiseType, seed: u64) -> Self {
+        // Offset channels by a large odd constant, so seed n + 1 doesn't replay channel 1 of seed n
+        let state = GenericArray::generate(|chan| {
+            NoiseState::new(seed.wrapping_add((chan as u64).wrapping_mul(0xD1B5_4A32_D192_ED03)))
+        });
+
+        Self {
+            noise_type,
+            state,
+            ports: Ports {
+                audio_inputs: None,
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF, Ao> Node<AF, CF> for Noise<Ao>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    Ao: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        _: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ao.len(), Ao::USIZE);
+
+        let fs = ctx.get_sample_rate();
+
+        for (chan, state) in ao.iter_mut().zip(self.state.iter_mut()) {
+            for sample in chan.iter_mut() {
+                *sample = state.tick(self.noise_type, fs);
+            }
+        }
+    }
+}
+
+impl<Ao> PortedErased for Noise<Ao>
+where
+    Ao: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        None
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type NoiseMono = Noise<Mono>;
+pub type NoiseStereo = Noise<Stereo>;
+pub type NoiseMC<C> = Noise<C>;
+
+#[cfg(test)]
+mod test {
+    use crate::nodes::utils::{fft::fft, test_utils::NodeRunner};
+
+    use super::{NoiseStereo, NoiseType};
+
+    const N: usize = 16_384;
+
+    fn render(noise_type: NoiseType, seed: u64, blocks: usize) -> Vec<Vec<f32>> {
+        let mut noise = NoiseStereo::new(noise_type, seed);
+        NodeRunner::new(&noise).render(&mut noise, blocks)
+    }
+
+    #[test]
+    fn seeded_output_is_reproducible() {
+        for noise_type in [
+            NoiseType::White,
+            NoiseType::Pink,
+            NoiseType::Brown,
+            NoiseType::Velvet { density: 2000.0 },
+        ] {
+            let a = render(noise_type, 7, 16);
+            let b = render(noise_type, 7, 16);
+            let c = render(noise_type, 8, 16);
+
+            assert_eq!(a, b, "{noise_type:?} differs with the same seed");
+            assert_ne!(a, c, "{noise_type:?} matches with a different seed");
+            assert_ne!(a[0], a[1], "{noise_type:?} channels are correlated");
+        }
+        // Only these are bounded, the filtered noise is kept to a similar RMS instead
+        for noise_type in [NoiseType::White, NoiseType::Velvet { density: 2000.0 }] {
+            let out = render(noise_type, 7, 16);
+            assert!(out[0].iter().all(|x| x.abs() <= 1.0));
+        }
+    }
+
+    #[test]
+    fn pink_falls_3db_per_octave() {
+        let mut re = render(NoiseType::Pink, 3, N / 64).remove(0);
+
+        let rms = (re.iter().map(|x| x * x).sum::<f32>() / N as f32).sqrt();
+        assert!((0.2..0.4).contains(&rms), "{rms}");
+
+        let mut im = vec![0.0; N];
+        fft(&mut re, &mut im, false);
+        // Average power across an octave, starting at a bin
+        let octave = |start: usize| {
+            (start..2 * start)
+                .map(|k| re[k] * re[k] + im[k] * im[k])
+                .sum::<f32>()
+                / start as f32
+        };
+        // Two octaves up should be 6dB down
+        let slope = 10.0 * (octave(1024) / octave(256)).log10();
+        assert!((slope + 6.0).abs() < 1.5, "{slope} dB");
+    }
+
+    #[test]
+    fn velvet_density() {
+        // One second, at 1000 impulses per second
+        let out = render(NoiseType::Velvet { density: 1000.0 }, 1, 750);
+        let impulses = out[0].iter().filter(|x| **x != 0.0).count();
+        assert!((999..=1001).contains(&impulses), "{impulses} impulses");
+    }
+}
@@ -0,0 +1,339 @@
+use generic_array::{ArrayLength, GenericArray, arr};
+use typenum::{U0, U4};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::port_utils::generate_audio_outputs,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Saw,
+    Square,
+    /// Variable width pulse, set with the pulse width and "pw" input
+    Pulse,
+    Triangle,
+}
+
+/// 2 point polynomial band-limited step residual.
+///
+/// t is the phase, and dt the phase increment per sample.
+#[inline(always)]
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// 2 point polynomial band-limited ramp residual, the integral of the BLEP.
+///
+/// Used for discontinuities in the first derivative, i.e the triangle's corners.
+#[inline(always)]
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -(x * x * x) / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        (x * x * x) / 3.0
+    } else {
+        0.0
+    }
+}
+
+/// Alias suppressed classic oscillator, using PolyBLEP for steps and PolyBLAMP for corners.
+///
+/// Inputs:
+/// - "fm": audio rate, added to the frequency like Sine. Below zero, the phase runs backwards.
+/// - "sync": hard sync, the phase restarts when this crosses zero going up.
+///   Note, the sync discontinuity itself is not band-limited at the moment.
+/// - "reset": restarts the phase at the rising edge of a trigger (> 0.5)
+/// - "pw": added to the pulse width, only used by the pulse waveform
+///
+/// Ao sets the output channel count, every channel gets the same signal.
+pub struct Oscillator<Ao>
+where
+    Ao: ArrayLength,
+{
+    waveform: Waveform,
+    freq: f32,
+    pulse_width: f32,
+    phase: f32,
+    initial_phase: f32,
+    last_sync: f32,
+    last_reset: f32,
+    ports: Ports<U4, Ao, U0, U0>,
+}
+
+impl<Ao> Oscillator<Ao>
+where
+    Ao: ArrayLength,
+{
+    pub fn new(waveform: Waveform, freq: f32, pulse_width: f32, phase: f32) -> Self {
+        let audio_inputs = arr![
+            AudioInputPort {
+                meta: PortMeta {
+                    name: "fm",
+                    index: 0
+                },
+            },
+            AudioInputPort {
+                meta: PortMeta {
+                    name: "sync",
+                    index: 1
+                },
+            },
+            AudioInputPort {
+                meta: PortMeta {
+                    name: "reset",
+                    index: 2
+                },
+            },
+            AudioInputPort {
+                meta: PortMeta {
+                    name: "pw",
+                    index: 3
+                },
+            },
+        ];
+
+        let audio_outputs: GenericArray<AudioOutputPort, Ao> = generate_audio_outputs::<Ao>();
+
+        let phase = phase.rem_euclid(1.0);
+
+        Self {
+            waveform,
+            freq,
+            pulse_width,
+            phase,
+            initial_phase: phase,
+            last_sync: 0.0,
+            last_reset: 0.0,
+            ports: Ports {
+                audio_inputs: Some(audio_inputs),
+                audio_outputs: Some(audio_outputs),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        }
+    }
+    #[inline(always)]
+    fn tick(&mut self, dt: f32, width: f32) -> f32 {
+        let t = self.phase;
+        match self.waveform {
+            Waveform::Saw => (2.0 * t - 1.0) - poly_blep(t, dt),
+            Waveform::Square => Self::pulse(t, dt, 0.5),
+            Waveform::Pulse => Self::pulse(t, dt, width),
+            Waveform::Triangle => {
+                let naive = if t < 0.5 {
+                    4.0 * t - 1.0
+                } else {
+                    3.0 - 4.0 * t
+                };
+                // The slope changes by 8 per cycle at each corner
+                let t2 = (t + 0.5).fract();
+                naive + 8.0 * dt * (poly_blamp(t, dt) - poly_blamp(t2, dt))
+            }
+        }
+    }
+    #[inline(always)]
+    fn pulse(t: f32, dt: f32, width: f32) -> f32 {
+        let naive = if t < width { 1.0 } else { -1.0 };
+        let t2 = (t - width).rem_euclid(1.0);
+        naive + poly_blep(t, dt) - poly_blep(t2, dt)
+    }
+}
+
+impl<AF, CF, Ao> Node<AF, CF> for Oscillator<Ao>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    Ao: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 4);
+        debug_assert_eq!(ao.len(), Ao::USIZE);
+
+        let fs = ctx.get_sample_rate();
+
+        for n in 0..AF::USIZE {
+            let freq = self.freq + ai[0][n];
+            // The residuals assume less than half a cycle per sample. They are symmetric
+            // around the edges, so negative frequencies just run the phase backwards.
+            let dt = (freq / fs).abs().min(0.5);
+            let increment = dt.copysign(freq);
+
+            let sync = ai[1][n];
+            if self.last_sync <= 0.0 && sync > 0.0 {
+                // Restart where the crossing happened, rather than snapping to the sample
+                let crossing = sync / (sync - self.last_sync);
+                self.phase = (crossing * increment).rem_euclid(1.0);
+            }
+            self.last_sync = sync;
+
+            let reset = ai[2][n];
+            if self.last_reset <= 0.5 && reset > 0.5 {
+                self.phase = self.initial_phase;
+            }
+            self.last_reset = reset;
+
+            // Keep the edges at least a sample apart
+            let width = (self.pulse_width + ai[3][n]).clamp(dt, 1.0 - dt);
+
+            let sample = self.tick(dt, width);
+
+            self.phase = (self.phase + increment).rem_euclid(1.0);
+
+            for chan in ao.iter_mut() {
+                chan[n] = sample;
+            }
+        }
+    }
+}
+
+impl<Ao> PortedErased for Oscillator<Ao>
+where
+    Ao: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type OscillatorMono = Oscillator<Mono>;
+pub type OscillatorStereo = Oscillator<Stereo>;
+pub type OscillatorMC<C> = Oscillator<C>;
+
+#[cfg(test)]
+mod test {
+    use crate::nodes::utils::{
+        fft::fft,
+        test_utils::{FS, NodeRunner},
+    };
+
+    use super::{OscillatorMono, Waveform};
+
+    const N: usize = 16_384;
+
+    fn render(waveform: Waveform, freq: f32) -> Vec<f32> {
+        let mut osc = OscillatorMono::new(waveform, freq, 0.3, 0.0);
+        let mut runner = NodeRunner::new(&osc);
+        runner.render(&mut osc, N / 64).remove(0)
+    }
+
+    /// Returns the loudest non-harmonic bin relative to the fundamental, in dB.
+    fn worst_alias_db(signal: &[f32], freq: f32) -> f32 {
+        // Blackman-Harris, so leakage doesn't get counted as aliasing
+        let mut re: Vec<f32> = signal
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let w = std::f32::consts::TAU * i as f32 / N as f32;
+                let window = 0.35875 - 0.48829 * w.cos() + 0.14128 * (2.0 * w).cos()
+                    - 0.01168 * (3.0 * w).cos();
+                x * window
+            })
+            .collect();
+        let mut im = vec![0.0; N];
+        fft(&mut re, &mut im, false);
+
+        let mags: Vec<f32> = (0..N / 2)
+            .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt())
+            .collect();
+
+        let bin_hz = FS / N as f32;
+        let fundamental = mags[(freq / bin_hz).round() as usize];
+
+        let mut worst = 0.0_f32;
+        for (k, &mag) in mags.iter().enumerate() {
+            let f = k as f32 * bin_hz;
+            let harmonic = (f / freq).round();
+            // Skip the main lobe of DC, and every real harmonic
+            if (f - harmonic * freq).abs() < 6.0 * bin_hz {
+                continue;
+            }
+            worst = worst.max(mag);
+        }
+
+        20.0 * (worst / fundamental).log10()
+    }
+
+    #[test]
+    fn negative_frequencies_run_backwards() {
+        // FM pulling the frequency below zero plays the saw in reverse, i.e upside down
+        let mut osc = OscillatorMono::new(Waveform::Saw, 100.0, 0.3, 0.0);
+        let mut runner = NodeRunner::new(&osc);
+        runner.ai[0].fill(-540.0);
+        let backwards = runner.render(&mut osc, 16).remove(0);
+
+        let forwards = &render(Waveform::Saw, 440.0)[..backwards.len()];
+        for (x, y) in backwards.iter().zip(forwards.iter()) {
+            assert!((x + y).abs() < 1e-3, "{} {}", x, y);
+        }
+    }
+
+    #[test]
+    fn saw_aliasing_below_threshold() {
+        let freq = 3_520.0;
+        let alias = worst_alias_db(&render(Waveform::Saw, freq), freq);
+        assert!(alias < -20.0, "saw alias at {alias} dB");
+
+        // And a good chunk better than the naive ramp
+        let naive: Vec<f32> = (0..N)
+            .map(|i| 2.0 * (i as f32 * freq / FS).fract() - 1.0)
+            .collect();
+        let naive_alias = worst_alias_db(&naive, freq);
+        assert!(
+            alias < naive_alias - 6.0,
+            "naive {naive_alias} dB, blep {alias} dB"
+        );
+    }
+
+    #[test]
+    fn square_aliasing_below_threshold() {
+        let freq = 3_520.0;
+        let alias = worst_alias_db(&render(Waveform::Square, freq), freq);
+        assert!(alias < -20.0, "square alias at {alias} dB");
+    }
+
+    #[test]
+    fn pulse_aliasing_below_threshold() {
+        let freq = 2_637.0;
+        let alias = worst_alias_db(&render(Waveform::Pulse, freq), freq);
+        assert!(alias < -25.0, "pulse alias at {alias} dB");
+    }
+
+    #[test]
+    fn triangle_aliasing_below_threshold() {
+        let freq = 3_520.0;
+        let alias = worst_alias_db(&render(Waveform::Triangle, freq), freq);
+        assert!(alias < -35.0, "triangle alias at {alias} dB");
+    }
+}
@@ -0,0 +1,450 @@
+use std::{
+    f32::consts::{FRAC_PI_2, SQRT_2},
+    time::Duration,
+};
+
+use generic_array::{GenericArray, arr};
+use typenum::{U0, U1, U2};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::{
+        filters::OnePoleLp,
+        port_utils::{generate_audio_inputs, generate_audio_outputs},
+    },
+};
+
+/// How long pan, balance and width changes take to glide
+const SMOOTHING: Duration = Duration::from_millis(10);
+
+/// How loud a centered signal is in each channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanLaw {
+    /// 0dB in the center, each side fades out linearly past it
+    Linear,
+    /// -3dB in the center, sine and cosine, so the power stays constant
+    #[default]
+    ConstantPower,
+    /// -4.5dB in the center, half way between constant power and -6dB
+    Compromise,
+    /// -6dB in the center, a linear crossfade, so mono sums stay constant
+    LinearTaper,
+}
+
+/// The left and right gains for a pan position, from -1.0 (left) to 1.0 (right)
+#[inline(always)]
+pub fn pan_gains(law: PanLaw, pan: f32) -> (f32, f32) {
+    let t = (pan.clamp(-1.0, 1.0) + 1.0) * 0.5;
+    match law {
+        PanLaw::Linear => ((2.0 - 2.0 * t).min(1.0), (2.0 * t).min(1.0)),
+        PanLaw::ConstantPower => ((t * FRAC_PI_2).cos(), (t * FRAC_PI_2).sin()),
+        PanLaw::Compromise => (
+            ((1.0 - t) * (t * FRAC_PI_2).cos()).sqrt(),
+            (t * (t * FRAC_PI_2).sin()).sqrt(),
+        ),
+        PanLaw::LinearTaper => (1.0 - t, t),
+    }
+}
+
+/// Sets up a smoother on the first block, or when the sample rate changes
+fn prepare_smoother(smoother: &mut OnePoleLp, smoother_fs: &mut f32, fs: f32, initial: f32) {
+    if fs != *smoother_fs {
+        smoother.set_time(SMOOTHING, fs);
+        if *smoother_fs == 0.0 {
+            smoother.reset(initial);
+        }
+        *smoother_fs = fs;
+    }
+}
+
+fn control_input(name: &'static str) -> GenericArray<ControlInputPort, U1> {
+    arr![ControlInputPort {
+        meta: PortMeta { name, index: 0 },
+    }]
+}
+
+fn channel_names(mid_side: bool) -> (&'static str, &'static str) {
+    if mid_side { ("m", "s") } else { ("l", "r") }
+}
+
+/// Pans a mono input across a stereo output.
+///
+/// The "pan" control input is added to the pan position, and smoothed.
+pub struct Panner {
+    law: PanLaw,
+    pan: f32,
+    smoother: OnePoleLp,
+    smoother_fs: f32,
+    ports: Ports<U1, U2, U1, U0>,
+}
+
+impl Panner {
+    pub fn new(law: PanLaw, pan: f32) -> Self {
+        Self {
+            law,
+            pan,
+            smoother: OnePoleLp::default(),
+            smoother_fs: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_input("pan")),
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for Panner
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 1);
+        debug_assert_eq!(ao.len(), 2);
+
+        let fs = ctx.get_sample_rate();
+        prepare_smoother(&mut self.smoother, &mut self.smoother_fs, fs, self.pan);
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let (left, right) = ao.split_at_mut(1);
+
+        for (n, ((x, l), r)) in ai[0]
+            .iter()
+            .zip(left[0].iter_mut())
+            .zip(right[0].iter_mut())
+            .enumerate()
+        {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let target = self.pan + ci.first().map_or(0.0, |x| x[m]);
+            let (gl, gr) = pan_gains(self.law, self.smoother.tick(target.clamp(-1.0, 1.0)));
+            *l = x * gl;
+            *r = x * gr;
+        }
+    }
+}
+
+impl PortedErased for Panner {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+/// Turns down one side of a stereo signal, without moving anything across.
+///
+/// -1.0 is only the left, 1.0 is only the right. The "balance" control
+/// input is added to this, and smoothed.
+pub struct Balance {
+    balance: f32,
+    smoother: OnePoleLp,
+    smoother_fs: f32,
+    ports: Ports<U2, U2, U1, U0>,
+}
+
+impl Balance {
+    pub fn new(balance: f32) -> Self {
+        Self {
+            balance,
+            smoother: OnePoleLp::default(),
+            smoother_fs: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_input("balance")),
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for Balance
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 2);
+        debug_assert_eq!(ao.len(), 2);
+
+        let fs = ctx.get_sample_rate();
+        prepare_smoother(&mut self.smoother, &mut self.smoother_fs, fs, self.balance);
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let target = self.balance + ci.first().map_or(0.0, |x| x[m]);
+            let balance = self.smoother.tick(target.clamp(-1.0, 1.0));
+            let (gl, gr) = pan_gains(PanLaw::Linear, balance);
+            ao[0][n] = ai[0][n] * gl;
+            ao[1][n] = ai[1][n] * gr;
+        }
+    }
+}
+
+impl PortedErased for Balance {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+/// Converts between left/right and mid/side.
+///
+/// Encoding and decoding both scale by 1/sqrt(2), so a round trip is
+/// unity gain, and each is its own inverse.
+pub struct MidSide {
+    ports: Ports<U2, U2, U0, U0>,
+}
+
+impl MidSide {
+    /// Takes "l" and "r", and outputs "m" and "s"
+    pub fn encode() -> Self {
+        Self::new(false)
+    }
+    /// Takes "m" and "s", and outputs "l" and "r"
+    pub fn decode() -> Self {
+        Self::new(true)
+    }
+    fn new(from_mid_side: bool) -> Self {
+        let (a_in, b_in) = channel_names(from_mid_side);
+        let (a_out, b_out) = channel_names(!from_mid_side);
+        Self {
+            ports: Ports {
+                audio_inputs: Some(arr![
+                    AudioInputPort {
+                        meta: PortMeta {
+                            name: a_in,
+                            index: 0
+                        },
+                    },
+                    AudioInputPort {
+                        meta: PortMeta {
+                            name: b_in,
+                            index: 1
+                        },
+                    },
+                ]),
+                audio_outputs: Some(arr![
+                    AudioOutputPort {
+                        meta: PortMeta {
+                            name: a_out,
+                            index: 0
+                        },
+                    },
+                    AudioOutputPort {
+                        meta: PortMeta {
+                            name: b_out,
+                            index: 1
+                        },
+                    },
+                ]),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for MidSide
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        _: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 2);
+        debug_assert_eq!(ao.len(), 2);
+
+        for n in 0..AF::USIZE {
+            let (a, b) = (ai[0][n], ai[1][n]);
+            ao[0][n] = (a + b) / SQRT_2;
+            ao[1][n] = (a - b) / SQRT_2;
+        }
+    }
+}
+
+impl PortedErased for MidSide {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+/// Scales the side of a stereo signal. 0.0 is mono, 1.0 is unchanged,
+/// and above that is wider.
+///
+/// The "width" control input is added to this, and smoothed.
+pub struct StereoWidth {
+    width: f32,
+    smoother: OnePoleLp,
+    smoother_fs: f32,
+    ports: Ports<U2, U2, U1, U0>,
+}
+
+impl StereoWidth {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            smoother: OnePoleLp::default(),
+            smoother_fs: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_input("width")),
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF> Node<AF, CF> for StereoWidth
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 2);
+        debug_assert_eq!(ao.len(), 2);
+
+        let fs = ctx.get_sample_rate();
+        prepare_smoother(&mut self.smoother, &mut self.smoother_fs, fs, self.width);
+
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
+        for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let target = self.width + ci.first().map_or(0.0, |x| x[m]);
+            let width = self.smoother.tick(target.max(0.0));
+
+            let mid = (ai[0][n] + ai[1][n]) * 0.5;
+            let side = (ai[0][n] - ai[1][n]) * 0.5 * width;
+            ao[0][n] = mid + side;
+            ao[1][n] = mid - side;
+        }
+    }
+}
+
+impl PortedErased for StereoWidth {
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::nodes::utils::{gain::gain_to_db, test_utils::NodeRunner};
+
+    use super::{MidSide, PanLaw, StereoWidth, pan_gains};
+
+    #[test]
+    fn pan_laws_hit_their_center_levels() {
+        for (law, db) in [
+            (PanLaw::Linear, 0.0),
+            (PanLaw::ConstantPower, -3.01),
+            (PanLaw::Compromise, -4.52),
+            (PanLaw::LinearTaper, -6.02),
+        ] {
+            let (l, r) = pan_gains(law, 0.0);
+            assert!((gain_to_db(l) - db).abs() < 0.01, "{:?}", law);
+            assert!((l - r).abs() < 1e-6);
+
+            // Hard left is only the left
+            let (l, r) = pan_gains(law, -1.0);
+            assert!((l - 1.0).abs() < 1e-6 && r.abs() < 1e-6, "{:?}", law);
+        }
+    }
+
+    #[test]
+    fn mid_side_round_trips_and_width_collapses() {
+        let mut encode = MidSide::encode();
+        let mut decode = MidSide::decode();
+        let mut width = StereoWidth::new(0.0);
+
+        let mut runner = NodeRunner::new(&encode);
+        runner.ai[0]
+            .iter_mut()
+            .enumerate()
+            .for_each(|(n, x)| *x = n as f32);
+        runner.ai[1].fill(0.25);
+        let lr = runner.ai.clone();
+
+        runner.process(&mut encode);
+        runner.ai = runner.ao.clone();
+        runner.process(&mut decode);
+        for (a, b) in lr.iter().zip(runner.ao.iter()) {
+            assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-4));
+        }
+
+        runner.ai = lr;
+        runner.process(&mut width);
+        assert_eq!(runner.ao[0], runner.ao[1]);
+    }
+}
@@ -0,0 +1,338 @@
+use std::{f32::consts::TAU, marker::PhantomData, time::Duration};
+
+use generic_array::{ArrayLength, GenericArray, sequence::GenericSequence};
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::{
+        audio::delay::DelayLine,
+        utils::{
+            interp::{Interp, Interpolator},
+            port_utils::{generate_audio_inputs, generate_audio_outputs},
+        },
+    },
+};
+
+/// The classic building blocks for Schroeder, Freeverb and Dattorro style reverbs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReverbBlockType {
+    /// Feedback comb
+    Comb { delay: Duration, feedback: f32 },
+    /// Feedback comb with a one pole low pass in the loop, like Freeverb
+    LowpassComb {
+        delay: Duration,
+        feedback: f32,
+        /// 0.0 to 1.0, higher is darker
+        damping: f32,
+    },
+    /// Schroeder allpass
+    Allpass { delay: Duration, gain: f32 },
+    /// An allpass with another allpass inside of its delay
+    NestedAllpass {
+        delay: Duration,
+        gain: f32,
+        inner_delay: Duration,
+        inner_gain: f32,
+    },
+    /// A series of allpasses sharing a gain, for smearing transients
+    Diffuser { delays: Vec<Duration>, gain: f32 },
+    /// An allpass with a sine modulated delay time, like in the Dattorro tank
+    ModulatedAllpass {
+        delay: Duration,
+        gain: f32,
+        /// In Hz
+        rate: f32,
+        depth: Duration,
+    },
+}
+
+impl ReverbBlockType {
+    /// The base delay time of each line
+    fn line_times(&self) -> Vec<Duration> {
+        match self {
+            Self::Comb { delay, .. }
+            | Self::LowpassComb { delay, .. }
+            | Self::Allpass { delay, .. }
+            | Self::ModulatedAllpass { delay, .. } => vec![*delay],
+            Self::NestedAllpass {
+                delay, inner_delay, ..
+            } => vec![*delay, *inner_delay],
+            Self::Diffuser { delays, .. } => delays.clone(),
+        }
+    }
+}
+
+/// A Schroeder allpass around one channel of a delay line
+#[inline(always)]
+fn allpass<AF, C>(
+    line: &mut DelayLine<AF, C>,
+    chan: usize,
+    delay: f32,
+    gain: f32,
+    x: f32,
+    interp: &mut Interpolator,
+) -> f32
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    let d = line.get_delay_interp(chan, delay, interp);
+    let v = x + gain * d;
+    line.write_sample(chan, v);
+    d - gain * v
+}
+
+/// A single reverb building block, with a channel count of C.
+///
+/// Each channel is processed independently. For the modulated allpass,
+/// the channels are spread across the modulation cycle for some width.
+pub struct ReverbBlock<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    block_type: ReverbBlockType,
+    lines: Vec<DelayLine<AF, C>>,
+    // The base delay time of each line, and in samples at the current rate
+    times: Vec<Duration>,
+    delays: Vec<f32>,
+    delays_fs: f32,
+    interpolator: Interpolator,
+    lowpass: GenericArray<f32, C>,
+    mod_phase: GenericArray<f32, C>,
+    ports: Ports<C, C, U0, U0>,
+    phantom: PhantomData<AF>,
+}
+
+impl<AF, C> ReverbBlock<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    pub fn new(block_type: ReverbBlockType, sample_rate: f32) -> Self {
+        let times = block_type.line_times();
+        let extra = match &block_type {
+            ReverbBlockType::ModulatedAllpass { depth, .. } => *depth,
+            _ => Duration::ZERO,
+        };
+        let lines: Vec<DelayLine<AF, C>> = times
+            .iter()
+            // A few samples of headroom for the modulation and interpolation
+            .map(|x| DelayLine::new(((*x + extra).as_secs_f32() * sample_rate) as usize + 4))
+            .collect();
+        let delays = vec![0.0; lines.len()];
+
+        Self {
+            block_type,
+            lines,
+            times,
+            delays,
+            delays_fs: 0.0,
+            interpolator: Interpolator::new(Interp::Linear),
+            lowpass: GenericArray::generate(|_| 0.0),
+            mod_phase: GenericArray::generate(|i| i as f32 / C::USIZE as f32),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+            phantom: PhantomData::<AF>,
+        }
+    }
+    fn set_delays(&mut self, fs: f32) {
+        // Keep at least a sample of delay, and stay inside the lines
+        for ((delay, time), line) in self
+            .delays
+            .iter_mut()
+            .zip(self.times.iter())
+            .zip(self.lines.iter())
+        {
+            let max = (line.get_capacity() as f32 - 3.0).max(1.0);
+            *delay = (time.as_secs_f32() * fs).clamp(1.0, max);
+        }
+        self.delays_fs = fs;
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for ReverbBlock<AF, C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.delays_fs {
+            self.set_delays(fs);
+        }
+
+        let interp = &mut self.interpolator;
+
+        for c in 0..C::USIZE {
+            for n in 0..AF::USIZE {
+                let x = ai[c][n];
+                ao[c][n] = match self.block_type {
+                    ReverbBlockType::Comb { feedback, .. } => {
+                        let line = &mut self.lines[0];
+                        let d = line.get_delay_interp(c, self.delays[0], interp);
+                        line.write_sample(c, x + feedback * d);
+                        d
+                    }
+                    ReverbBlockType::LowpassComb {
+                        feedback, damping, ..
+                    } => {
+                        let line = &mut self.lines[0];
+                        let d = line.get_delay_interp(c, self.delays[0], interp);
+                        let damping = damping.clamp(0.0, 1.0);
+                        self.lowpass[c] = d * (1.0 - damping) + self.lowpass[c] * damping;
+                        line.write_sample(c, x + feedback * self.lowpass[c]);
+                        d
+                    }
+                    ReverbBlockType::Allpass { gain, .. } => {
+                        allpass(&mut self.lines[0], c, self.delays[0], gain, x, interp)
+                    }
+                    ReverbBlockType::NestedAllpass {
+                        gain, inner_gain, ..
+                    } => {
+                        let (outer, inner) = self.lines.split_at_mut(1);
+                        let outer = &mut outer[0];
+                        let d = outer.get_delay_interp(c, self.delays[0], interp);
+                        let d = allpass(&mut inner[0], c, self.delays[1], inner_gain, d, interp);
+                        let v = x + gain * d;
+                        outer.write_sample(c, v);
+                        d - gain * v
+                    }
+                    ReverbBlockType::Diffuser { gain, .. } => self
+                        .lines
+                        .iter_mut()
+                        .zip(self.delays.iter())
+                        .fold(x, |acc, (line, delay)| {
+                            allpass(line, c, *delay, gain, acc, interp)
+                        }),
+                    ReverbBlockType::ModulatedAllpass {
+                        gain, rate, depth, ..
+                    } => {
+                        let depth = depth.as_secs_f32() * fs;
+                        let delay = self.delays[0] + depth * (TAU * self.mod_phase[c]).sin();
+                        self.mod_phase[c] = (self.mod_phase[c] + rate / fs).rem_euclid(1.0);
+                        let max = self.lines[0].get_capacity() as f32 - 3.0;
+                        allpass(
+                            &mut self.lines[0],
+                            c,
+                            delay.clamp(1.0, max),
+                            gain,
+                            x,
+                            interp,
+                        )
+                    }
+                };
+            }
+        }
+    }
+}
+
+impl<AF, C> PortedErased for ReverbBlock<AF, C>
+where
+    AF: FrameSize,
+    C: ArrayLength + Send + Sync + 'static,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type ReverbBlockMono<AF> = ReverbBlock<AF, Mono>;
+pub type ReverbBlockStereo<AF> = ReverbBlock<AF, Stereo>;
+pub type ReverbBlockMC<AF, C> = ReverbBlock<AF, C>;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use typenum::U64;
+
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    use super::{ReverbBlockMono, ReverbBlockType};
+
+    fn impulse_response(block_type: ReverbBlockType, samples: usize) -> Vec<f32> {
+        let mut block = ReverbBlockMono::<U64>::new(block_type, FS);
+        let mut runner = NodeRunner::new(&block);
+
+        let mut out = Vec::new();
+        while out.len() < samples {
+            runner.ai[0][0] = if out.is_empty() { 1.0 } else { 0.0 };
+            runner.process(&mut block);
+            out.extend_from_slice(&runner.ao[0]);
+        }
+        out
+    }
+
+    #[test]
+    fn comb_echoes_at_the_delay() {
+        // 100 samples of delay
+        let delay = Duration::from_secs_f32(100.0 / FS);
+        let out = impulse_response(
+            ReverbBlockType::Comb {
+                delay,
+                feedback: 0.5,
+            },
+            512,
+        );
+        assert!((out[100] - 1.0).abs() < 1e-4);
+        assert!((out[200] - 0.5).abs() < 1e-4);
+        assert!((out[300] - 0.25).abs() < 1e-4);
+        assert!(out[150].abs() < 1e-4);
+    }
+
+    #[test]
+    fn allpasses_keep_the_energy() {
+        let ms = Duration::from_millis;
+        for block_type in [
+            ReverbBlockType::Allpass {
+                delay: ms(7),
+                gain: 0.7,
+            },
+            ReverbBlockType::NestedAllpass {
+                delay: ms(11),
+                gain: 0.5,
+                inner_delay: ms(3),
+                inner_gain: 0.6,
+            },
+            ReverbBlockType::Diffuser {
+                delays: vec![ms(5), ms(3), ms(2), ms(1)],
+                gain: 0.7,
+            },
+        ] {
+            let out = impulse_response(block_type, FS as usize * 2);
+            let energy: f32 = out.iter().map(|x| x * x).sum();
+            assert!((energy - 1.0).abs() < 1e-3, "energy {}", energy);
+        }
+    }
+}
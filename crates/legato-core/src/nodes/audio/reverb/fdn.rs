@@ -0,0 +1,445 @@
+use std::{f32::consts::TAU, marker::PhantomData, time::Duration};
+
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{U0, U2, U4, U8, U16};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::{
+        audio::delay::DelayLine,
+        utils::{
+            filters::OnePoleLp,
+            interp::{Interp, Interpolator},
+            port_utils::{generate_audio_inputs, generate_audio_outputs},
+        },
+    },
+};
+
+/// The lossless matrix mixing the delay lines back into each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FdnMatrix {
+    /// Dense and cheap, but with a strong diagonal, so it builds echo density a bit slower
+    #[default]
+    Householder,
+    /// Every line feeds every other equally, for the fastest build up of density
+    Hadamard,
+}
+
+// Mutually prime-ish line lengths in milliseconds, at a size of 1.0
+const LINE_MS: [f32; 16] = [
+    29.7, 37.1, 41.1, 43.7, 47.3, 53.9, 59.3, 61.7, 67.1, 71.9, 73.3, 79.7, 83.1, 89.9, 97.1, 101.3,
+];
+
+// Early reflection taps off of the pre delay, in milliseconds and gain
+const EARLY_L: [(f32, f32); 6] = [
+    (7.1, 0.8),
+    (11.3, -0.7),
+    (17.9, 0.6),
+    (23.3, -0.5),
+    (29.9, 0.4),
+    (37.7, -0.3),
+];
+const EARLY_R: [(f32, f32); 6] = [
+    (8.3, -0.8),
+    (13.1, 0.7),
+    (19.7, -0.6),
+    (25.1, 0.5),
+    (31.3, -0.4),
+    (41.9, 0.3),
+];
+
+const MAX_SIZE: f32 = 2.0;
+const MIN_SIZE: f32 = 0.1;
+// Where the per line shelves split the lows from the highs
+const DAMPING_CROSSOVER: f32 = 3000.0;
+// Peak delay modulation, in samples at 48k
+const MOD_DEPTH: f32 = 6.0;
+
+/// The user facing settings. Everything but the pre delay can be modulated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FdnParams {
+    /// Scales the delay line lengths, from 0.1 to 2.0
+    pub size: f32,
+    /// Time in seconds for the low end to decay by 60dB
+    pub decay: f32,
+    /// 0.0 to 1.0, how much quicker the highs decay than the lows
+    pub damping: f32,
+    /// 0.0 is dry, 1.0 is fully wet
+    pub mix: f32,
+    /// Level of the early reflections
+    pub early: f32,
+    pub pre_delay: Duration,
+}
+
+impl Default for FdnParams {
+    fn default() -> Self {
+        Self {
+            size: 1.0,
+            decay: 2.0,
+            damping: 0.5,
+            mix: 0.3,
+            early: 0.5,
+            pre_delay: Duration::from_millis(10),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct LineState {
+    delay: f32,
+    low_gain: f32,
+    high_gain: f32,
+    shelf: OnePoleLp,
+    mod_phase: f32,
+    mod_rate: f32,
+}
+
+/// A feedback delay network reverb, with stereo in and out.
+///
+/// Each line is slowly modulated to smear out metallic resonances, and
+/// has a shelf in the feedback path so the highs decay faster than the
+/// lows. The input is pre delayed, with early reflections tapped off the
+/// pre delay line, before it feeds the network.
+///
+/// The "size", "decay", "damping" and "mix" control inputs are added
+/// to the base parameters.
+pub struct FdnReverb<AF, L>
+where
+    AF: FrameSize,
+    L: ArrayLength + Send + Sync + 'static,
+{
+    params: FdnParams,
+    matrix: FdnMatrix,
+    lines: DelayLine<AF, L>,
+    line_state: GenericArray<LineState, L>,
+    pre_delay: DelayLine<AF, U2>,
+    interpolator: Interpolator,
+    // The size, decay, damping, and sample rate the lines were last set up with
+    current: (f32, f32, f32, f32),
+    ports: Ports<U2, U2, U4, U0>,
+    phantom: PhantomData<AF>,
+}
+
+impl<AF, L> FdnReverb<AF, L>
+where
+    AF: FrameSize,
+    L: ArrayLength + Send + Sync + 'static,
+{
+    pub fn new(params: FdnParams, matrix: FdnMatrix, sample_rate: f32) -> Self {
+        debug_assert!(L::USIZE.is_power_of_two() && L::USIZE <= LINE_MS.len());
+
+        let max_line = LINE_MS[LINE_MS.len() - 1] * MAX_SIZE * sample_rate / 1000.0
+            + MOD_DEPTH * sample_rate / 48_000.0;
+        let max_early = EARLY_R[EARLY_R.len() - 1].0 * MAX_SIZE * sample_rate / 1000.0;
+        let pre_delay = params.pre_delay.as_secs_f32() * sample_rate;
+
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "size",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "decay",
+                    index: 1
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "damping",
+                    index: 2
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "mix",
+                    index: 3
+                },
+            },
+        ];
+
+        Self {
+            params,
+            matrix,
+            // A few samples of headroom for the interpolation
+            lines: DelayLine::new(max_line as usize + 8),
+            line_state: GenericArray::generate(|i| LineState {
+                // Spread the modulation so the lines don't move together
+                mod_phase: i as f32 / L::USIZE as f32,
+                mod_rate: 0.3 + 0.07 * i as f32,
+                ..Default::default()
+            }),
+            pre_delay: DelayLine::new((pre_delay + max_early) as usize + 8),
+            interpolator: Interpolator::new(Interp::Hermite),
+            current: (f32::NAN, f32::NAN, f32::NAN, f32::NAN),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(control_inputs),
+                control_outputs: None,
+            },
+            phantom: PhantomData::<AF>,
+        }
+    }
+    /// Recomputes the line lengths and shelf gains, if anything changed
+    fn set(&mut self, size: f32, decay: f32, damping: f32, fs: f32) {
+        let key = (size, decay, damping, fs);
+        if key == self.current {
+            return;
+        }
+        self.current = key;
+
+        let size = size.clamp(MIN_SIZE, MAX_SIZE);
+        let decay = decay.max(0.01);
+        let high_decay = decay * (1.0 - 0.95 * damping.clamp(0.0, 1.0));
+
+        // The lines were sized for the sample rate at construction, so stay inside them
+        let max_delay = self.lines.get_capacity() as f32 - MOD_DEPTH * fs / 48_000.0 - 4.0;
+
+        // With 8 lines, every other length keeps the spread
+        let stride = LINE_MS.len() / L::USIZE;
+        for (i, line) in self.line_state.iter_mut().enumerate() {
+            line.delay = (LINE_MS[i * stride] * size * fs / 1000.0).min(max_delay);
+            // The gain per pass for a line this long to hit -60dB at the decay time
+            let seconds = line.delay / fs;
+            line.low_gain = 10.0_f32.powf(-3.0 * seconds / decay);
+            line.high_gain = 10.0_f32.powf(-3.0 * seconds / high_decay);
+            line.shelf.set_cutoff(DAMPING_CROSSOVER, fs);
+        }
+    }
+    #[inline(always)]
+    fn mix_matrix(&self, x: &mut GenericArray<f32, L>) {
+        match self.matrix {
+            FdnMatrix::Householder => {
+                let sum: f32 = x.iter().sum();
+                let scaled = sum * 2.0 / L::USIZE as f32;
+                x.iter_mut().for_each(|v| *v -= scaled);
+            }
+            FdnMatrix::Hadamard => {
+                // Fast Walsh-Hadamard transform, normalized to stay lossless
+                let mut h = 1;
+                while h < L::USIZE {
+                    for i in (0..L::USIZE).step_by(h * 2) {
+                        for j in i..i + h {
+                            let (a, b) = (x[j], x[j + h]);
+                            x[j] = a + b;
+                            x[j + h] = a - b;
+                        }
+                    }
+                    h *= 2;
+                }
+                let norm = 1.0 / (L::USIZE as f32).sqrt();
+                x.iter_mut().for_each(|v| *v *= norm);
+            }
+        }
+    }
+}
+
+impl<AF, CF, L> Node<AF, CF> for FdnReverb<AF, L>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    L: ArrayLength + Send + Sync + 'static,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), 2);
+        debug_assert_eq!(ao.len(), 2);
+        debug_assert_eq!(ci.len(), 4);
+
+        let fs = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let ms = fs / 1000.0;
+        let mod_depth = MOD_DEPTH * fs / 48_000.0;
+        let pre_delay = (self.params.pre_delay.as_secs_f32() * fs).max(1.0);
+        let out_gain = 1.0 / (L::USIZE as f32 / 2.0).sqrt();
+
+        let mut feedback = GenericArray::<f32, L>::generate(|_| 0.0);
+
+        for n in 0..AF::USIZE {
+            let m = (n / chunk).min(CF::USIZE - 1);
+            let size = self.params.size + ci[0][m];
+            self.set(
+                size,
+                self.params.decay + ci[1][m],
+                self.params.damping + ci[2][m],
+                fs,
+            );
+            let mix = (self.params.mix + ci[3][m]).clamp(0.0, 1.0);
+            let size = size.clamp(MIN_SIZE, MAX_SIZE);
+
+            let (dry_l, dry_r) = (ai[0][n], ai[1][n]);
+            self.pre_delay.write_sample(0, dry_l);
+            self.pre_delay.write_sample(1, dry_r);
+
+            // Early reflections, tapped off of the pre delay
+            let mut early = [0.0; 2];
+            for (chan, taps) in [EARLY_L, EARLY_R].iter().enumerate() {
+                for (time, gain) in taps {
+                    let offset = pre_delay + time * size * ms;
+                    early[chan] += gain
+                        * self
+                            .pre_delay
+                            .get_delay_interp(chan, offset, &mut self.interpolator);
+                }
+            }
+            let input_l = self
+                .pre_delay
+                .get_delay_interp(0, pre_delay, &mut self.interpolator);
+            let input_r = self
+                .pre_delay
+                .get_delay_interp(1, pre_delay, &mut self.interpolator);
+
+            // Read the lines, through the shelves
+            let mut late = [0.0; 2];
+            for (i, (line, fb)) in self
+                .line_state
+                .iter_mut()
+                .zip(feedback.iter_mut())
+                .enumerate()
+            {
+                let modulation = (TAU * line.mod_phase).sin() * mod_depth;
+                line.mod_phase = (line.mod_phase + line.mod_rate / fs).fract();
+
+                let y =
+                    self.lines
+                        .get_delay_interp(i, line.delay + modulation, &mut self.interpolator);
+                let low = line.shelf.tick(y);
+                *fb = low * line.low_gain + (y - low) * line.high_gain;
+
+                // Even lines to the left, odd to the right, with alternating signs
+                let sign = if (i / 2) % 2 == 0 { 1.0 } else { -1.0 };
+                late[i % 2] += sign * y;
+            }
+
+            self.mix_matrix(&mut feedback);
+
+            for (i, fb) in feedback.iter().enumerate() {
+                let input = if i % 2 == 0 { input_l } else { input_r };
+                self.lines.write_sample(i, fb + input * out_gain);
+            }
+
+            let wet_l = early[0] * self.params.early + late[0] * out_gain;
+            let wet_r = early[1] * self.params.early + late[1] * out_gain;
+
+            ao[0][n] = dry_l * (1.0 - mix) + wet_l * mix;
+            ao[1][n] = dry_r * (1.0 - mix) + wet_r * mix;
+        }
+    }
+}
+
+impl<AF, L> PortedErased for FdnReverb<AF, L>
+where
+    AF: FrameSize,
+    L: ArrayLength + Send + Sync + 'static,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type FdnReverb8<AF> = FdnReverb<AF, U8>;
+pub type FdnReverb16<AF> = FdnReverb<AF, U16>;
+
+#[cfg(test)]
+mod test {
+    use generic_array::{GenericArray, sequence::GenericSequence};
+    use typenum::{U8, U16, U64};
+
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    use super::{FdnMatrix, FdnParams, FdnReverb};
+
+    #[test]
+    fn matrices_are_lossless() {
+        for matrix in [FdnMatrix::Householder, FdnMatrix::Hadamard] {
+            let reverb = FdnReverb::<U64, U16>::new(FdnParams::default(), matrix, FS);
+            let mut x = GenericArray::<f32, U16>::generate(|i| (i as f32 * 0.37).sin());
+            let before: f32 = x.iter().map(|v| v * v).sum();
+            reverb.mix_matrix(&mut x);
+            let after: f32 = x.iter().map(|v| v * v).sum();
+            assert!((before - after).abs() < 1e-4);
+        }
+    }
+
+    /// Energy of the impulse response in dB, in 50ms windows
+    fn tail_db(reverb: &mut FdnReverb<U64, U8>, seconds: f32) -> Vec<f32> {
+        let mut runner = NodeRunner::new(reverb);
+
+        let mut out = Vec::new();
+        for block in 0..(seconds * FS / 64.0) as usize {
+            runner.ai[0][0] = if block == 0 { 1.0 } else { 0.0 };
+            runner.ai[1][0] = runner.ai[0][0];
+            runner.process(reverb);
+            let ao = &runner.ao;
+            out.extend(ao[0].iter().zip(ao[1].iter()).map(|(l, r)| l * l + r * r));
+        }
+        // 50ms windows
+        out.chunks(2400)
+            .map(|x| 10.0 * (x.iter().sum::<f32>() / x.len() as f32).log10())
+            .collect()
+    }
+
+    #[test]
+    fn tail_decays_at_the_decay_time() {
+        let params = FdnParams {
+            decay: 0.5,
+            damping: 0.0,
+            mix: 1.0,
+            ..Default::default()
+        };
+        let mut reverb = FdnReverb::<U64, U8>::new(params, FdnMatrix::Hadamard, FS);
+        let windows = tail_db(&mut reverb, 1.0);
+
+        // Once the network is full at ~150ms, the tail should drop 60dB over the
+        // next 500ms, give or take, and never blow up
+        let drop = windows[3] - windows[13];
+        assert!(drop > 45.0 && drop < 75.0, "dropped {}dB", drop);
+        assert!(
+            windows
+                .iter()
+                .all(|x| x.is_finite() || *x == f32::NEG_INFINITY)
+        );
+    }
+
+    #[test]
+    fn damping_darkens_the_tail() {
+        let bright = FdnParams {
+            damping: 0.0,
+            mix: 1.0,
+            ..Default::default()
+        };
+        let dark = FdnParams {
+            damping: 0.9,
+            ..bright
+        };
+        let mut bright = FdnReverb::<U64, U8>::new(bright, FdnMatrix::Householder, FS);
+        let mut dark = FdnReverb::<U64, U8>::new(dark, FdnMatrix::Householder, FS);
+        let bright = tail_db(&mut bright, 1.0);
+        let dark = tail_db(&mut dark, 1.0);
+        // The damped tail loses its highs, so has less energy late on
+        assert!(dark[15] < bright[15] - 3.0);
+    }
+}
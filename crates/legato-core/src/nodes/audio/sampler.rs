@@ -1,6 +1,8 @@
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
 use assert_no_alloc::permit_alloc;
-use generic_array::ArrayLength;
-use typenum::U0;
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{U0, U4};
 
 use crate::{
     engine::{
@@ -10,36 +12,248 @@ use crate::{
         port::{Stereo, *},
         resources::SampleKey,
     },
-    nodes::utils::port_utils::generate_audio_outputs,
+    nodes::utils::{
+        interp::{Interp, Interpolator},
+        port_utils::generate_audio_outputs,
+    },
 };
 
+/// How long the fade out is when the gate closes, so the sample doesn't click off
+const RELEASE: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplerMode {
+    /// Plays from the start to the end once, then stops
+    OneShot,
+    /// Plays from the start, then repeats between the loop points
+    #[default]
+    Loop,
+    /// Plays from the start, then bounces back and forth between the loop points
+    PingPong,
+}
+
+/// Points are fractions of the sample, from 0.0 to 1.0, as the length
+/// isn't known until the sample is loaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerParams {
+    pub start: f32,
+    pub end: f32,
+    /// The loop points are kept inside of the start and end
+    pub loop_start: f32,
+    pub loop_end: f32,
+    pub mode: SamplerMode,
+    /// Plays backwards from the end
+    pub reverse: bool,
+    /// The playback rate, in semitones
+    pub pitch: f32,
+    /// How long the loop end fades into the loop start. This borrows the audio
+    /// just before the loop start (or after the loop end, in reverse), so it
+    /// is shortened when there isn't enough.
+    pub crossfade: Duration,
+    /// Linear gain, latched at each trigger
+    pub volume: f32,
+    /// Start playing right away, without waiting for a trigger
+    pub autoplay: bool,
+    pub interp: Interp,
+}
+
+impl Default for SamplerParams {
+    fn default() -> Self {
+        Self {
+            start: 0.0,
+            end: 1.0,
+            loop_start: 0.0,
+            loop_end: 1.0,
+            mode: SamplerMode::Loop,
+            reverse: false,
+            pitch: 0.0,
+            crossfade: Duration::ZERO,
+            volume: 1.0,
+            autoplay: true,
+            interp: Interp::Linear,
+        }
+    }
+}
+
+/// The params, in samples of the loaded sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    start: f64,
+    end: f64,
+    loop_start: f64,
+    loop_end: f64,
+    // The crossfades into the loop, going forwards and backwards
+    fade: f64,
+    fade_reverse: f64,
+}
+
+impl Region {
+    fn new(params: &SamplerParams, len: usize, fs: f32) -> Self {
+        let len = len as f64;
+        let at = |x: f32| (x.clamp(0.0, 1.0) as f64 * len).round();
+
+        let start = at(params.start.min(params.end));
+        let end = at(params.end.max(params.start)).max(start + 1.0).min(len);
+        let start = start.min(end - 1.0);
+
+        let mut loop_start = at(params.loop_start).clamp(start, end - 1.0);
+        let mut loop_end = at(params.loop_end).clamp(start, end);
+        if loop_end <= loop_start {
+            loop_start = start;
+            loop_end = end;
+        }
+
+        let fade = (params.crossfade.as_secs_f32() * fs).floor() as f64;
+        let half = (loop_end - loop_start) / 2.0;
+        Self {
+            start,
+            end,
+            loop_start,
+            loop_end,
+            fade: fade.min(loop_start).min(half),
+            fade_reverse: fade.min(len - loop_end).min(half),
+        }
+    }
+    fn loop_len(&self) -> f64 {
+        self.loop_end - self.loop_start
+    }
+}
+
+/// Plays a sample, with trigger and gate control inputs.
+///
+/// A rising "trig" (> 0.5) restarts the sample. A rising "gate" does the same,
+/// and a falling gate fades it out. The "volume" control input is added to the
+/// volume when triggered, so velocity can be patched straight in with a volume
+/// of 0.0. The "pitch" control input is added to the pitch, in semitones.
+///
+/// Positions are fractional, and read with interpolation. Looping is worked out
+/// every sample, so loops shorter than a block, or faster than a sample per
+/// sample, still wrap correctly.
 pub struct Sampler<Ao>
 where
     Ao: ArrayLength,
 {
     sample_key: SampleKey,
-    read_pos: usize,
-    is_looping: bool,
-    ports: Ports<U0, Ao, U0, U0>,
+    params: SamplerParams,
+    playing: bool,
+    // Fractional, so the sample can be read between samples with interpolation
+    read_pos: f64,
+    // 1.0 forwards, -1.0 backwards
+    direction: f64,
+    volume: f32,
+    // The gain of the release fade, and how much it drops each sample
+    release: f32,
+    release_step: f32,
+    last_trig: f32,
+    last_gate: f32,
+    interpolators: GenericArray<Interpolator, Ao>,
+    ports: Ports<U0, Ao, U4, U0>,
 }
 
 impl<Ao> Sampler<Ao>
 where
     Ao: ArrayLength,
 {
-    pub fn new(sample_key: SampleKey) -> Self {
+    pub fn new(sample_key: SampleKey, params: SamplerParams) -> Self {
+        let control_inputs = arr![
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "trig",
+                    index: 0
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "gate",
+                    index: 1
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "volume",
+                    index: 2
+                },
+            },
+            ControlInputPort {
+                meta: PortMeta {
+                    name: "pitch",
+                    index: 3
+                },
+            },
+        ];
         Self {
             sample_key,
-            read_pos: 0,
-            is_looping: true,
+            params,
+            // Waits for the sample to load, then starts from the right end
+            playing: false,
+            read_pos: f64::NAN,
+            direction: 1.0,
+            volume: params.volume,
+            release: 1.0,
+            release_step: 0.0,
+            last_trig: 0.0,
+            last_gate: 0.0,
+            interpolators: GenericArray::generate(|_| Interpolator::new(params.interp)),
             ports: Ports {
                 audio_inputs: None,
                 audio_outputs: Some(generate_audio_outputs()),
-                control_inputs: None, // TODO, Trig, Volume, etc.
+                control_inputs: Some(control_inputs),
                 control_outputs: None,
             },
         }
     }
+    fn trigger(&mut self, region: &Region, volume: f32) {
+        self.playing = true;
+        self.volume = volume.max(0.0);
+        self.release = 1.0;
+        self.release_step = 0.0;
+        if self.params.reverse {
+            self.direction = -1.0;
+            self.read_pos = region.end - 1.0;
+        } else {
+            self.direction = 1.0;
+            self.read_pos = region.start;
+        }
+    }
+    /// Moves the read position on by step, wrapping or stopping at the edges
+    fn advance(&mut self, region: &Region, step: f64) {
+        self.read_pos += step * self.direction;
+        let pos = self.read_pos;
+        match self.params.mode {
+            SamplerMode::OneShot => {
+                if pos >= region.end || pos < region.start {
+                    self.playing = false;
+                }
+            }
+            SamplerMode::Loop => {
+                // Before the loop start going forwards, or after the loop end going
+                // backwards, is the lead in to the loop, so it plays through
+                if (self.direction > 0.0 && pos >= region.loop_end)
+                    || (self.direction < 0.0 && pos < region.loop_start)
+                {
+                    self.read_pos =
+                        region.loop_start + (pos - region.loop_start).rem_euclid(region.loop_len());
+                }
+            }
+            SamplerMode::PingPong => {
+                // Bounce off the first and last samples, so they aren't played twice
+                let span = region.loop_len() - 1.0;
+                if span <= 0.0 {
+                    self.read_pos = region.loop_start;
+                } else if (self.direction > 0.0 && pos > region.loop_end - 1.0)
+                    || (self.direction < 0.0 && pos < region.loop_start)
+                {
+                    // Unfold the bounces into a triangle, and fold the position back in
+                    let mut x = (pos - region.loop_start).rem_euclid(2.0 * span);
+                    if x > span {
+                        x = 2.0 * span - x;
+                        self.direction = -self.direction;
+                    }
+                    self.read_pos = region.loop_start + x;
+                }
+            }
+        }
+    }
 }
 
 impl<AF, CF, Ao> Node<AF, CF> for Sampler<Ao>
@@ -53,31 +267,131 @@ where
         ctx: &mut AudioContext<AF>,
         _: &Frame<AF>,
         ao: &mut Frame<AF>,
-        _: &Frame<CF>,
+        ci: &Frame<CF>,
         _: &mut Frame<CF>,
     ) {
+        for chan in ao.iter_mut() {
+            chan.fill(0.0);
+        }
+
+        let fs = ctx.get_sample_rate();
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+
         permit_alloc(|| {
             // 128 bytes allocated in the load_full. Can we do better?
-            if let Some(inner) = ctx.get_sample(self.sample_key) {
-                let buf = inner.data();
-                let len = buf[0].len();
-                for n in 0..AF::USIZE {
-                    let i = self.read_pos + n;
-                    for c in 0..Ao::USIZE {
-                        ao[c][n] = if i < len {
-                            buf[c][i]
-                        } else if self.is_looping {
-                            buf[c][i % len]
-                        } else {
-                            0.0
-                        };
+            let Some(inner) = ctx.get_sample(self.sample_key) else {
+                return;
+            };
+            let buf = inner.data();
+            let len = buf.first().map_or(0, |chan| chan.len());
+            if len == 0 {
+                return;
+            }
+            let region = Region::new(&self.params, len, fs);
+
+            if self.read_pos.is_nan() {
+                if self.params.autoplay {
+                    self.trigger(&region, self.params.volume);
+                } else {
+                    self.read_pos = region.start;
+                }
+            }
+
+            let looping = self.params.mode == SamplerMode::Loop;
+            for n in 0..AF::USIZE {
+                let m = (n / chunk).min(CF::USIZE - 1);
+                let control = |i: usize| ci.get(i).map_or(0.0, |x| x[m]);
+
+                let (trig, gate) = (control(0), control(1));
+                if (self.last_trig <= 0.5 && trig > 0.5) || (self.last_gate <= 0.5 && gate > 0.5) {
+                    self.trigger(&region, self.params.volume + control(2));
+                } else if self.last_gate > 0.5 && gate <= 0.5 && self.playing {
+                    self.release_step = 1.0 / (RELEASE.as_secs_f32() * fs).max(1.0);
+                }
+                self.last_trig = trig;
+                self.last_gate = gate;
+
+                if !self.playing {
+                    continue;
+                }
+
+                let pos = self.read_pos;
+                let forwards = self.direction > 0.0;
+                // Past the loop, the interpolation taps wrap back into it
+                let wrap = |i: isize| -> isize {
+                    let (start, end) = (region.loop_start as isize, region.loop_end as isize);
+                    if looping && ((forwards && i >= end) || (!forwards && i < start)) {
+                        start + (i - start).rem_euclid(end - start)
+                    } else {
+                        i
+                    }
+                };
+                let fetch = |chan: &[f32], i: isize| {
+                    if i >= 0 && (i as usize) < len {
+                        chan[i as usize]
+                    } else {
+                        0.0
+                    }
+                };
+
+                // How far through a crossfade we are, and where the other side reads from
+                let crossfade = if !looping {
+                    None
+                } else if forwards
+                    && region.fade > 0.0
+                    && pos >= region.loop_start
+                    && pos >= region.loop_end - region.fade
+                {
+                    let t = (pos - (region.loop_end - region.fade)) / region.fade;
+                    Some((t as f32, pos - region.loop_len()))
+                } else if !forwards
+                    && region.fade_reverse > 0.0
+                    && pos < region.loop_end
+                    && pos < region.loop_start + region.fade_reverse
+                {
+                    let t = (region.loop_start + region.fade_reverse - pos) / region.fade_reverse;
+                    Some((t as f32, pos + region.loop_len()))
+                } else {
+                    None
+                };
+
+                let gain = self.volume * self.release;
+                for (c, out) in ao.iter_mut().enumerate() {
+                    let chan = &buf[c.min(buf.len() - 1)];
+                    let interpolator = &mut self.interpolators[c];
+
+                    let index = pos.floor();
+                    let t = (pos - index) as f32;
+                    let x = interpolator.read(index as isize, t, |i| fetch(chan, wrap(i)));
+
+                    out[n] = match crossfade {
+                        Some((fade, other)) => {
+                            let index = other.floor();
+                            let t = (other - index) as f32;
+                            let y = interpolator.read(index as isize, t, |i| fetch(chan, i));
+                            // Equal power, as the two sides usually aren't correlated
+                            let (g_out, g_in) =
+                                ((fade * FRAC_PI_2).cos(), (fade * FRAC_PI_2).sin());
+                            x * g_out + y * g_in
+                        }
+                        None => x,
+                    } * gain;
+                }
+
+                if self.release_step > 0.0 {
+                    self.release -= self.release_step;
+                    if self.release <= 0.0 {
+                        self.playing = false;
                     }
                 }
-                self.read_pos = if self.is_looping {
-                    (self.read_pos + AF::USIZE) % len // If we're looping, wrap around
+
+                let pitch = self.params.pitch + control(3);
+                let step = if pitch == 0.0 {
+                    1.0
                 } else {
-                    (self.read_pos + AF::USIZE).min(len) // If we're not looping, cap at the end
+                    2.0_f64.powf(pitch as f64 / 12.0)
                 };
+                self.advance(&region, step);
             }
         })
     }
@@ -100,5 +414,171 @@ where
     }
 }
 
-pub type SamplerMono<const AF: usize> = Sampler<Mono>;
-pub type SamplerStereo<const AF: usize> = Sampler<Stereo>;
+pub type SamplerMono = Sampler<Mono>;
+pub type SamplerStereo = Sampler<Stereo>;
+
+#[cfg(test)]
+mod test {
+    use typenum::{U16, U64};
+
+    use super::*;
+    use crate::nodes::utils::test_utils::{NodeRunner, add_sample, context};
+
+    /// A runner with a mono sample loaded
+    fn with_sample(data: Vec<f32>) -> (NodeRunner<U64, U16>, SampleKey) {
+        let mut ctx = context();
+        let key = add_sample(&mut ctx, vec![data]);
+        let runner = NodeRunner::with_context(ctx, &SamplerMono::new(key, Default::default()));
+        (runner, key)
+    }
+
+    /// Runs a block at a time, with control inputs for each block
+    fn run(
+        sampler: &mut SamplerMono,
+        runner: &mut NodeRunner<U64, U16>,
+        controls: &[[f32; 4]],
+    ) -> Vec<f32> {
+        let mut out = vec![];
+        for block in controls {
+            for (input, x) in runner.ci.iter_mut().zip(block) {
+                input.fill(*x);
+            }
+            runner.process(sampler);
+            out.extend_from_slice(&runner.ao[0]);
+        }
+        out
+    }
+
+    fn ramp(len: usize) -> Vec<f32> {
+        (0..len).map(|n| n as f32).collect()
+    }
+
+    #[test]
+    fn one_shots_wait_for_a_trigger() {
+        let (mut runner, key) = with_sample(ramp(100));
+        let params = SamplerParams {
+            mode: SamplerMode::OneShot,
+            autoplay: false,
+            start: 0.1,
+            end: 0.5,
+            volume: 0.0,
+            ..Default::default()
+        };
+        let mut sampler = SamplerMono::new(key, params);
+        // Nothing until the trigger, which carries the volume
+        let out = run(&mut sampler, &mut runner, &[[0.0; 4], [1.0, 0.0, 0.5, 0.0]]);
+        assert!(out[..64].iter().all(|x| *x == 0.0));
+        let expected: Vec<f32> = (10..50).map(|n| 0.5 * n as f32).collect();
+        assert_eq!(out[64..104], expected);
+        assert!(out[104..].iter().all(|x| *x == 0.0));
+
+        // Backwards, an octave up
+        let params = SamplerParams {
+            reverse: true,
+            pitch: 12.0,
+            ..params
+        };
+        let mut sampler = SamplerMono::new(key, params);
+        let out = run(&mut sampler, &mut runner, &[[1.0, 0.0, 1.0, 0.0]]);
+        let expected: Vec<f32> = (0..20).map(|n| 49.0 - 2.0 * n as f32).collect();
+        assert_eq!(out[..20], expected);
+        assert!(out[20..].iter().all(|x| *x == 0.0));
+
+        // The gate fades out when it closes
+        let mut sampler = SamplerMono::new(
+            key,
+            SamplerParams {
+                mode: SamplerMode::Loop,
+                autoplay: false,
+                ..Default::default()
+            },
+        );
+        let out = run(
+            &mut sampler,
+            &mut runner,
+            &[[0.0, 1.0, 0.0, 0.0], [0.0; 4], [0.0; 4], [0.0; 4], [0.0; 4]],
+        );
+        assert_eq!(out[..64], ramp(100)[..64]);
+        // 5ms is 240 samples, so it is half way down after 120
+        assert!((out[64 + 120] - 0.5 * 84.0).abs() < 0.1);
+        assert!(out[64 + 240..].iter().all(|x| x.abs() < 1e-3));
+    }
+
+    #[test]
+    fn loops_shorter_than_a_block_wrap() {
+        let (mut runner, key) = with_sample(ramp(10));
+        let params = SamplerParams {
+            loop_start: 0.2,
+            loop_end: 0.7,
+            interp: Interp::Truncate,
+            ..Default::default()
+        };
+
+        // A lead in, then 2, 3, 4, 5, 6 over and over
+        let mut sampler = SamplerMono::new(key, params);
+        let out = run(&mut sampler, &mut runner, &[[0.0; 4]; 2]);
+        let expected: Vec<f32> = (0..128)
+            .map(|n| if n < 2 { n } else { 2 + (n - 2) % 5 } as f32)
+            .collect();
+        assert_eq!(out, expected);
+
+        // Faster than the loop is long still lands in it
+        let mut sampler = SamplerMono::new(
+            key,
+            SamplerParams {
+                pitch: 12.0 * 7.0_f32.log2(),
+                ..params
+            },
+        );
+        let out = run(&mut sampler, &mut runner, &[[0.0; 4]]);
+        assert!(out[1..].iter().all(|x| (2.0..=6.0).contains(x)));
+
+        // Ping pong bounces without repeating the ends
+        let mut sampler = SamplerMono::new(
+            key,
+            SamplerParams {
+                mode: SamplerMode::PingPong,
+                ..params
+            },
+        );
+        let out = run(&mut sampler, &mut runner, &[[0.0; 4]]);
+        assert_eq!(
+            out[..14],
+            [
+                0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 5.0, 4.0, 3.0, 2.0, 3.0, 4.0, 5.0
+            ]
+        );
+    }
+
+    #[test]
+    fn loop_crossfades_are_smooth() {
+        // A ramp up to the loop end, so a plain loop jumps from 1.0 back down to 0.5
+        let len = 4800;
+        let data: Vec<f32> = (0..len).map(|n| n as f32 / len as f32).collect();
+        let (mut runner, key) = with_sample(data);
+        let params = SamplerParams {
+            loop_start: 0.5,
+            crossfade: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let mut sampler = SamplerMono::new(key, params);
+        let out = run(&mut sampler, &mut runner, &[[0.0; 4]; 150]);
+
+        let biggest_jump = |out: &[f32]| {
+            out.windows(2)
+                .map(|w| (w[1] - w[0]).abs())
+                .fold(0.0, f32::max)
+        };
+        assert!(biggest_jump(&out) < 0.01, "{}", biggest_jump(&out));
+
+        let mut sampler = SamplerMono::new(
+            key,
+            SamplerParams {
+                crossfade: Duration::ZERO,
+                ..params
+            },
+        );
+        let out = run(&mut sampler, &mut runner, &[[0.0; 4]; 150]);
+        assert!(biggest_jump(&out) > 0.49);
+    }
+}
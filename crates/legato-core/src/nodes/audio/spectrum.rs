@@ -0,0 +1,417 @@
+use std::{
+    f32::consts::TAU,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use generic_array::ArrayLength;
+use portable_atomic::AtomicF32;
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::{
+        fft::fft,
+        filters::Ballistics,
+        gain::gain_to_db,
+        port_utils::{generate_audio_inputs, generate_audio_outputs},
+    },
+};
+
+/// How many times a reader tries again when it catches a frame being written
+const READ_ATTEMPTS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpectrumWindow {
+    /// -31dB sidelobes, with a narrow main lobe
+    #[default]
+    Hann,
+    /// 4 term, -92dB sidelobes, for a wide dynamic range
+    BlackmanHarris,
+}
+
+impl SpectrumWindow {
+    fn generate(&self, size: usize) -> Vec<f32> {
+        (0..size)
+            .map(|n| {
+                let x = TAU * n as f32 / size as f32;
+                match self {
+                    SpectrumWindow::Hann => 0.5 - 0.5 * x.cos(),
+                    SpectrumWindow::BlackmanHarris => {
+                        0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos()
+                            - 0.01168 * (3.0 * x).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumParams {
+    /// The FFT size, a power of two
+    pub size: usize,
+    /// How much each FFT overlaps the last, from 0.0 up to 0.95
+    pub overlap: f32,
+    pub window: SpectrumWindow,
+    /// Averages each bin over this fraction of an octave, i.e 1/3. 0.0 turns it off
+    pub smoothing: f32,
+    /// How long the peaks stay up before falling
+    pub hold: Duration,
+    /// How fast the peaks fall, in dB per second
+    pub decay: f32,
+}
+
+impl Default for SpectrumParams {
+    fn default() -> Self {
+        Self {
+            size: 2048,
+            overlap: 0.75,
+            window: SpectrumWindow::Hann,
+            smoothing: 1.0 / 6.0,
+            hold: Duration::from_millis(1000),
+            decay: 20.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SpectrumFrame {
+    magnitudes: Box<[AtomicF32]>,
+    peaks: Box<[AtomicF32]>,
+    // Odd while a frame is being written
+    sequence: AtomicU64,
+    sample_rate: AtomicF32,
+    reset: AtomicBool,
+}
+
+/// The UI side of a spectrum analyzer.
+///
+/// Frames are read without locking. If the analyzer is part way through
+/// writing a frame, the read is tried again, and gives up after a few tries
+/// rather than waiting on the audio thread.
+#[derive(Debug, Clone)]
+pub struct SpectrumHandle {
+    frame: Arc<SpectrumFrame>,
+}
+
+impl SpectrumHandle {
+    /// The number of bins, half the FFT size plus one
+    pub fn bins(&self) -> usize {
+        self.frame.magnitudes.len()
+    }
+    /// The rate of the graph the analyzer is in, or 0.0 until it first runs
+    pub fn sample_rate(&self) -> f32 {
+        self.frame.sample_rate.load(Ordering::Relaxed)
+    }
+    /// The center frequency of a bin
+    pub fn bin_freq(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate() / (2 * (self.bins() - 1)) as f32
+    }
+    /// How many frames have been published
+    pub fn frames(&self) -> u64 {
+        self.frame.sequence.load(Ordering::Acquire) / 2
+    }
+    /// Copies the latest frame, in dBFS, returning its number.
+    ///
+    /// Either slice can be shorter than the number of bins, or empty.
+    pub fn read(&self, magnitudes: &mut [f32], peaks: &mut [f32]) -> Option<u64> {
+        for _ in 0..READ_ATTEMPTS {
+            let before = self.frame.sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            for (x, bin) in magnitudes.iter_mut().zip(self.frame.magnitudes.iter()) {
+                *x = bin.load(Ordering::Relaxed);
+            }
+            for (x, bin) in peaks.iter_mut().zip(self.frame.peaks.iter()) {
+                *x = bin.load(Ordering::Relaxed);
+            }
+            std::sync::atomic::fence(Ordering::Acquire);
+            if self.frame.sequence.load(Ordering::Relaxed) == before {
+                return Some(before / 2);
+            }
+        }
+        None
+    }
+    /// Drops the peaks back to the current magnitudes on the next frame
+    pub fn reset_peaks(&self) {
+        self.frame.reset.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A pass-through spectrum analyzer, publishing to a `SpectrumHandle`.
+///
+/// Channels are averaged before the analysis. A full scale sine reads 0dB.
+/// The FFT runs on the audio thread every hop, so large sizes with a lot of
+/// overlap will make some blocks much more expensive than others.
+pub struct Spectrum<C>
+where
+    C: ArrayLength,
+{
+    params: SpectrumParams,
+    handle: SpectrumHandle,
+    window: Vec<f32>,
+    // Sums the window, so a full scale sine reads 0dB
+    scale: f32,
+    hop: usize,
+    since_hop: usize,
+    history: Vec<f32>,
+    write_index: usize,
+    re: Vec<f32>,
+    im: Vec<f32>,
+    power: Vec<f32>,
+    // Running sum of the power, for the smoothing
+    power_sum: Vec<f64>,
+    smoothing_ranges: Vec<(usize, usize)>,
+    peaks: Vec<Ballistics>,
+    ports: Ports<C, C, U0, U0>,
+}
+
+impl<C> Spectrum<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(params: SpectrumParams) -> (Self, SpectrumHandle) {
+        let size = params.size.max(4).next_power_of_two();
+        let bins = size / 2 + 1;
+        let params = SpectrumParams {
+            size,
+            overlap: params.overlap.clamp(0.0, 0.95),
+            smoothing: params.smoothing.max(0.0),
+            ..params
+        };
+        let window = params.window.generate(size);
+        let silent = gain_to_db(0.0);
+        let handle = SpectrumHandle {
+            frame: Arc::new(SpectrumFrame {
+                magnitudes: (0..bins).map(|_| AtomicF32::new(silent)).collect(),
+                peaks: (0..bins).map(|_| AtomicF32::new(silent)).collect(),
+                sequence: AtomicU64::new(0),
+                sample_rate: AtomicF32::new(0.0),
+                reset: AtomicBool::new(false),
+            }),
+        };
+
+        // The ranges are in bins, so they don't depend on the sample rate
+        let half_width = 2.0_f32.powf(params.smoothing / 2.0);
+        let smoothing_ranges = (0..bins)
+            .map(|k| {
+                let lo = ((k as f32 / half_width).floor() as usize).min(k);
+                let hi = ((k as f32 * half_width).ceil() as usize).clamp(k, bins - 1);
+                (lo, hi)
+            })
+            .collect();
+
+        let node = Self {
+            scale: window.iter().sum::<f32>(),
+            window,
+            hop: ((size as f32 * (1.0 - params.overlap)) as usize).max(1),
+            since_hop: 0,
+            history: vec![0.0; size],
+            write_index: 0,
+            re: vec![0.0; size],
+            im: vec![0.0; size],
+            power: vec![0.0; bins],
+            power_sum: vec![0.0; bins + 1],
+            smoothing_ranges,
+            peaks: vec![Ballistics::default(); bins],
+            params,
+            handle: handle.clone(),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        };
+        (node, handle)
+    }
+    fn analyze(&mut self, fs: f32) {
+        let size = self.params.size;
+        let bins = self.power.len();
+
+        // Unroll the history, oldest first
+        for (n, (re, w)) in self.re.iter_mut().zip(self.window.iter()).enumerate() {
+            *re = self.history[(self.write_index + n) % size] * w;
+        }
+        self.im.fill(0.0);
+        fft(&mut self.re, &mut self.im, false);
+
+        for (k, power) in self.power.iter_mut().enumerate() {
+            // Everything but DC and Nyquist is split between positive and negative bins
+            let gain = if k == 0 || k == bins - 1 { 1.0 } else { 2.0 } / self.scale;
+            *power = (self.re[k] * self.re[k] + self.im[k] * self.im[k]) * gain * gain;
+        }
+        for k in 0..bins {
+            self.power_sum[k + 1] = self.power_sum[k] + self.power[k] as f64;
+        }
+
+        let frame = &self.handle.frame;
+        if frame.reset.swap(false, Ordering::Relaxed) {
+            self.peaks.fill(Ballistics::default());
+        }
+        let secs = self.hop as f32 / fs;
+
+        frame.sequence.fetch_add(1, Ordering::Relaxed);
+        std::sync::atomic::fence(Ordering::Release);
+        for k in 0..bins {
+            let power = if self.params.smoothing > 0.0 {
+                let (lo, hi) = self.smoothing_ranges[k];
+                ((self.power_sum[hi + 1] - self.power_sum[lo]) / (hi + 1 - lo) as f64) as f32
+            } else {
+                self.power[k]
+            };
+            let db = gain_to_db(power.sqrt());
+            let peak = &mut self.peaks[k];
+            peak.update(db, self.params.hold, self.params.decay, secs);
+
+            frame.magnitudes[k].store(db, Ordering::Relaxed);
+            frame.peaks[k].store(peak.hold, Ordering::Relaxed);
+        }
+        frame.sequence.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Spectrum<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        let fs = ctx.get_sample_rate();
+        self.handle.frame.sample_rate.store(fs, Ordering::Relaxed);
+
+        for (input, output) in ai.iter().zip(ao.iter_mut()) {
+            output.copy_from_slice(input);
+        }
+
+        let norm = 1.0 / C::USIZE as f32;
+        for n in 0..AF::USIZE {
+            self.history[self.write_index] = ai.iter().map(|x| x[n]).sum::<f32>() * norm;
+            self.write_index = (self.write_index + 1) % self.params.size;
+
+            self.since_hop += 1;
+            if self.since_hop >= self.hop {
+                self.since_hop = 0;
+                self.analyze(fs);
+            }
+        }
+    }
+}
+
+impl<C> PortedErased for Spectrum<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type SpectrumMono = Spectrum<Mono>;
+pub type SpectrumStereo = Spectrum<Stereo>;
+pub type SpectrumMC<C> = Spectrum<C>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    fn run(node: &mut SpectrumMono, blocks: usize, signal: impl Fn(usize) -> f32) {
+        let mut runner = NodeRunner::new(node);
+        for b in 0..blocks {
+            for (n, x) in runner.ai[0].iter_mut().enumerate() {
+                *x = signal(b * 64 + n);
+            }
+            runner.process(node);
+        }
+    }
+
+    fn read(handle: &SpectrumHandle) -> (Vec<f32>, Vec<f32>) {
+        let mut magnitudes = vec![0.0; handle.bins()];
+        let mut peaks = vec![0.0; handle.bins()];
+        handle.read(&mut magnitudes, &mut peaks).unwrap();
+        (magnitudes, peaks)
+    }
+
+    #[test]
+    fn full_scale_sine_reads_zero_db() {
+        for window in [SpectrumWindow::Hann, SpectrumWindow::BlackmanHarris] {
+            let params = SpectrumParams {
+                size: 1024,
+                window,
+                smoothing: 0.0,
+                ..Default::default()
+            };
+            let (mut node, handle) = SpectrumMono::new(params);
+            // On bin 64
+            let freq = 64.0 * FS / 1024.0;
+            run(&mut node, 64, |n| (TAU * freq * n as f32 / FS).sin());
+
+            let (magnitudes, _) = read(&handle);
+            assert_eq!(handle.bin_freq(64), freq);
+            assert!(magnitudes[64].abs() < 0.01, "{}", magnitudes[64]);
+            assert!(magnitudes[256] < -100.0);
+            assert!(handle.frames() > 0);
+        }
+    }
+
+    #[test]
+    fn smoothing_spreads_and_peaks_hold() {
+        let params = SpectrumParams {
+            size: 1024,
+            smoothing: 1.0,
+            ..Default::default()
+        };
+        let (mut node, handle) = SpectrumMono::new(params);
+        let freq = 64.0 * FS / 1024.0;
+        run(&mut node, 64, |n| (TAU * freq * n as f32 / FS).sin());
+
+        // An octave wide average leaks the sine into bins half an octave away
+        let (magnitudes, _) = read(&handle);
+        assert!(magnitudes[64] < -10.0);
+        assert!(magnitudes[80] > -30.0);
+        assert!(magnitudes[100] < -100.0);
+
+        // The peaks stay after the sine stops
+        run(&mut node, 16, |_| 0.0);
+        let (magnitudes, peaks) = read(&handle);
+        assert!(magnitudes[64] < -100.0);
+        assert!(peaks[64] > -20.0);
+
+        handle.reset_peaks();
+        run(&mut node, 16, |_| 0.0);
+        let (_, peaks) = read(&handle);
+        assert!(peaks[64] < -100.0);
+    }
+}
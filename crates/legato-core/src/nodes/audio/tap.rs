@@ -0,0 +1,401 @@
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use generic_array::{ArrayLength, GenericArray};
+use portable_atomic::AtomicF32;
+use typenum::U0;
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::{
+        port_utils::{generate_audio_inputs, generate_audio_outputs},
+        spsc::{Consumer, Producer, spsc_queue},
+    },
+};
+
+#[derive(Debug, Default)]
+struct TapInfo {
+    sample_rate: AtomicF32,
+    dropped: AtomicUsize,
+}
+
+/// The reading side of a tap.
+///
+/// Samples come out interleaved, one frame of every channel at a time.
+/// There can only be one reader, so this is moved rather than cloned.
+pub struct TapHandle {
+    consumer: Consumer,
+    info: Arc<TapInfo>,
+    chans: usize,
+}
+
+impl fmt::Debug for TapHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TapHandle")
+            .field("chans", &self.chans)
+            .field("available", &self.available())
+            .field("info", &self.info)
+            .finish()
+    }
+}
+
+impl TapHandle {
+    pub fn chans(&self) -> usize {
+        self.chans
+    }
+    /// The rate of the graph the tap is in, or 0.0 until it first runs
+    pub fn sample_rate(&self) -> f32 {
+        self.info.sample_rate.load(Ordering::Relaxed)
+    }
+    /// How many frames are waiting
+    pub fn available(&self) -> usize {
+        self.consumer.len() / self.chans
+    }
+    /// How many blocks were thrown away because the queue was full
+    pub fn dropped(&self) -> usize {
+        self.info.dropped.load(Ordering::Relaxed)
+    }
+    /// Reads as many whole frames as fit, returning the number of frames read
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let len = out.len() / self.chans * self.chans;
+        self.consumer.pop_slice(&mut out[..len]) / self.chans
+    }
+    /// Throws away everything waiting, i.e to catch up after a pause
+    pub fn clear(&mut self) {
+        self.consumer.clear();
+    }
+}
+
+/// Copies its input into a queue for another thread, passing it through untouched.
+///
+/// Blocks are only ever written whole. If the reader falls behind and the
+/// queue fills up, the block is dropped and counted instead.
+pub struct Tap<C>
+where
+    C: ArrayLength,
+{
+    producer: Producer,
+    info: Arc<TapInfo>,
+    ports: Ports<C, C, U0, U0>,
+}
+
+impl<C> Tap<C>
+where
+    C: ArrayLength,
+{
+    /// The queue holds at least capacity frames
+    pub fn new(capacity: usize) -> (Self, TapHandle) {
+        let (producer, consumer) = spsc_queue(capacity.max(1) * C::USIZE);
+        let info = Arc::new(TapInfo::default());
+        let node = Self {
+            producer,
+            info: info.clone(),
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: None,
+                control_outputs: None,
+            },
+        };
+        let handle = TapHandle {
+            consumer,
+            info,
+            chans: C::USIZE,
+        };
+        (node, handle)
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Tap<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        _: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        self.info
+            .sample_rate
+            .store(ctx.get_sample_rate(), Ordering::Relaxed);
+
+        for (input, output) in ai.iter().zip(ao.iter_mut()) {
+            output.copy_from_slice(input);
+        }
+
+        if self.producer.free() < AF::USIZE * C::USIZE {
+            self.info.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        for n in 0..AF::USIZE {
+            let frame: GenericArray<f32, C> = GenericArray::from_iter(ai.iter().map(|x| x[n]));
+            self.producer.push_slice(&frame);
+        }
+    }
+}
+
+impl<C> PortedErased for Tap<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        None
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type TapMono = Tap<Mono>;
+pub type TapStereo = Tap<Stereo>;
+pub type TapMC<C> = Tap<C>;
+
+/// How a scope looks for frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScopeParams {
+    /// The length of a frame, in samples
+    pub len: usize,
+    /// The level a rising edge has to cross
+    pub level: f32,
+    /// How far below the level the signal has to go before the next edge
+    /// counts, so noise doesn't retrigger
+    pub hysteresis: f32,
+    /// How long to wait after a frame before looking for the next edge
+    pub holdoff: Duration,
+    /// The channel to trigger on
+    pub channel: usize,
+    /// Free run when there is no edge for a whole frame, so silence still draws
+    pub auto: bool,
+}
+
+impl Default for ScopeParams {
+    fn default() -> Self {
+        Self {
+            len: 1024,
+            level: 0.0,
+            hysteresis: 0.01,
+            holdoff: Duration::ZERO,
+            channel: 0,
+            auto: true,
+        }
+    }
+}
+
+/// Captures triggered frames from a tap, like an oscilloscope.
+///
+/// This runs on the reading thread, so it can allocate. Frames
+/// start at the sample that crossed the level.
+pub struct Scope {
+    params: ScopeParams,
+    chans: usize,
+    capture: Vec<Vec<f32>>,
+    frame: Vec<Vec<f32>>,
+    captured: usize,
+    capturing: bool,
+    armed: bool,
+    holdoff_left: usize,
+    waited: usize,
+    scratch: Vec<f32>,
+}
+
+impl Scope {
+    pub fn new(params: ScopeParams, chans: usize) -> Self {
+        assert!(chans > 0, "A scope needs at least one channel");
+        let len = params.len.max(1);
+        Self {
+            params: ScopeParams { len, ..params },
+            chans,
+            capture: vec![vec![0.0; len]; chans],
+            frame: vec![vec![0.0; len]; chans],
+            captured: 0,
+            capturing: false,
+            armed: false,
+            holdoff_left: 0,
+            waited: 0,
+            scratch: vec![0.0; len * chans],
+        }
+    }
+    /// Reads everything waiting in the tap, returning true if there is a new frame
+    pub fn poll(&mut self, tap: &mut TapHandle) -> bool {
+        let fs = tap.sample_rate();
+        let mut scratch = std::mem::take(&mut self.scratch);
+        let mut new_frame = false;
+        loop {
+            let frames = tap.read(&mut scratch);
+            if frames == 0 {
+                break;
+            }
+            new_frame |= self.push(&scratch[..frames * tap.chans()], fs);
+        }
+        self.scratch = scratch;
+        new_frame
+    }
+    /// Feeds interleaved samples from any source, returning true if there is a new frame
+    pub fn push(&mut self, samples: &[f32], sample_rate: f32) -> bool {
+        let holdoff = (self.params.holdoff.as_secs_f32() * sample_rate) as usize;
+        let trigger_chan = self.params.channel.min(self.chans - 1);
+        let mut new_frame = false;
+
+        for samples in samples.chunks_exact(self.chans) {
+            let x = samples[trigger_chan];
+
+            if !self.capturing {
+                if self.holdoff_left > 0 {
+                    self.holdoff_left -= 1;
+                    continue;
+                }
+                if (self.armed && x >= self.params.level)
+                    || (self.params.auto && self.waited >= self.params.len)
+                {
+                    self.capturing = true;
+                    self.captured = 0;
+                } else if x < self.params.level - self.params.hysteresis {
+                    self.armed = true;
+                }
+                self.waited += 1;
+            }
+
+            if self.capturing {
+                for (chan, sample) in self.capture.iter_mut().zip(samples) {
+                    chan[self.captured] = *sample;
+                }
+                self.captured += 1;
+                if self.captured == self.params.len {
+                    std::mem::swap(&mut self.capture, &mut self.frame);
+                    self.capturing = false;
+                    self.armed = false;
+                    self.holdoff_left = holdoff;
+                    self.waited = 0;
+                    new_frame = true;
+                }
+            }
+        }
+        new_frame
+    }
+    /// The last captured frame of a channel
+    pub fn frame(&self, chan: usize) -> &[f32] {
+        &self.frame[chan]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::TAU;
+
+    use typenum::{U64, Unsigned};
+
+    use super::*;
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    fn run(tap: &mut TapStereo, blocks: usize, signal: impl Fn(usize) -> f32) {
+        let mut runner = NodeRunner::new(tap);
+        for b in 0..blocks {
+            let (left, right) = runner.ai.split_at_mut(1);
+            for (n, (l, r)) in left[0].iter_mut().zip(right[0].iter_mut()).enumerate() {
+                let x = signal(b * U64::USIZE + n);
+                *l = x;
+                *r = -x;
+            }
+            runner.process(tap);
+            assert_eq!(runner.ai, runner.ao);
+        }
+    }
+
+    #[test]
+    fn tap_drops_whole_blocks_when_full() {
+        let (mut tap, mut handle) = TapStereo::new(256);
+        run(&mut tap, 6, |n| n as f32);
+
+        // 4 blocks fit, the last 2 are dropped
+        assert_eq!(handle.available(), 256);
+        assert_eq!(handle.dropped(), 2);
+        assert_eq!(handle.sample_rate(), FS);
+
+        let mut out = vec![0.0; 2 * 256];
+        assert_eq!(handle.read(&mut out), 256);
+        let expected: Vec<f32> = (0..256).flat_map(|n| [n as f32, -(n as f32)]).collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn scope_triggers_on_rising_edges() {
+        let (mut tap, mut handle) = TapStereo::new(4096);
+        // 750Hz is a period of 64 samples
+        let freq = 750.0;
+        run(&mut tap, 32, |n| (TAU * freq * n as f32 / FS + 1.0).sin());
+
+        let params = ScopeParams {
+            len: 100,
+            holdoff: Duration::from_millis(2),
+            ..Default::default()
+        };
+        let mut scope = Scope::new(params, 2);
+        assert!(scope.poll(&mut handle));
+
+        // Every frame starts at an upward zero crossing
+        let frame = scope.frame(0);
+        assert!(frame[0] >= 0.0 && frame[0] < 0.1);
+        assert!(frame[1] > frame[0]);
+        assert_eq!(scope.frame(1)[0], -frame[0]);
+
+        // Frames are 100 samples, so each one waits for the edge after next,
+        // and the holdoff pushes that back another cycle
+        let count_frames = |holdoff: u64| {
+            let params = ScopeParams {
+                len: 100,
+                holdoff: Duration::from_millis(holdoff),
+                ..Default::default()
+            };
+            let mut scope = Scope::new(params, 1);
+            (0..64 * 60)
+                .filter(|n| scope.push(&[(TAU * *n as f32 / 64.0).sin()], FS))
+                .count()
+        };
+        assert_eq!(count_frames(0), 29);
+        assert_eq!(count_frames(2), 15);
+
+        // Silence only draws when free running
+        let silence = [0.0; 256];
+        let params = ScopeParams {
+            len: 100,
+            auto: false,
+            ..Default::default()
+        };
+        assert!(!Scope::new(params, 1).push(&silence, FS));
+        assert!(
+            Scope::new(
+                ScopeParams {
+                    auto: true,
+                    ..params
+                },
+                1
+            )
+            .push(&silence, FS)
+        );
+    }
+}
@@ -0,0 +1,443 @@
+use generic_array::{ArrayLength, GenericArray, arr, sequence::GenericSequence};
+use typenum::{U0, U2};
+
+use crate::{
+    engine::{
+        audio_context::AudioContext,
+        buffer::Frame,
+        node::{FrameSize, Node},
+        port::*,
+    },
+    nodes::utils::{
+        filters::DcBlocker,
+        port_utils::{generate_audio_inputs, generate_audio_outputs},
+    },
+};
+
+/// Under this, the ADAA divisions are ill conditioned, so we fall back to the midpoint
+const ADAA_EPSILON: f64 = 1e-5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapeCurve {
+    Tanh,
+    /// Clips at +-1
+    HardClip,
+    /// Cubic soft clip, reaching +-1 at +-1
+    SoftClip,
+    /// Folds back at +-1, like a triangle. Folds up to +-63 after the drive.
+    Foldback,
+    /// Asymmetric, the negative half saturates twice as early, adding even harmonics
+    Tube,
+    /// A user curve, spread evenly over -1.0 to 1.0, with linear interpolation
+    Table(Vec<f32>),
+}
+
+/// Antiderivative anti-aliasing. Each order adds half a sample of delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Adaa {
+    Off,
+    #[default]
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaveshaperParams {
+    pub curve: ShapeCurve,
+    pub adaa: Adaa,
+    /// Gain before the curve
+    pub drive: f32,
+    /// Offset before the curve, for asymmetric clipping
+    pub bias: f32,
+    /// Removes the DC the bias or an asymmetric curve leaves behind
+    pub dc_block: bool,
+}
+
+impl Default for WaveshaperParams {
+    fn default() -> Self {
+        Self {
+            curve: ShapeCurve::Tanh,
+            adaa: Adaa::First,
+            drive: 1.0,
+            bias: 0.0,
+            dc_block: true,
+        }
+    }
+}
+
+/// A curve as a piecewise linear table, with its first and second antiderivatives.
+///
+/// The antiderivatives are exact for the piecewise linear curve, so the ADAA
+/// never mixes up a curve with an approximation of its integral. Past the
+/// ends, the curve holds its last value.
+struct ShapeTable {
+    start: f64,
+    step: f64,
+    f0: Vec<f64>,
+    f1: Vec<f64>,
+    f2: Vec<f64>,
+}
+
+impl ShapeTable {
+    fn from_points(start: f64, step: f64, f0: Vec<f64>) -> Self {
+        let mut f1 = vec![0.0; f0.len()];
+        let mut f2 = vec![0.0; f0.len()];
+        for i in 1..f0.len() {
+            f1[i] = f1[i - 1] + step * (f0[i - 1] + f0[i]) / 2.0;
+            f2[i] = f2[i - 1] + step * f1[i - 1] + step * step * (2.0 * f0[i - 1] + f0[i]) / 6.0;
+        }
+        // Center the integration constants on 0.0, to keep the precision where the signal is
+        let mut table = Self {
+            start,
+            step,
+            f0,
+            f1,
+            f2,
+        };
+        let (_, c1, c2) = table.eval(0.0);
+        table.f1.iter_mut().for_each(|x| *x -= c1);
+        for (i, x) in table.f2.iter_mut().enumerate() {
+            *x -= c2 + c1 * (start + i as f64 * step);
+        }
+        table
+    }
+    fn sampled(range: f64, points_per_unit: usize, curve: impl Fn(f64) -> f64) -> Self {
+        let len = 2 * (range as usize) * points_per_unit + 1;
+        let step = 1.0 / points_per_unit as f64;
+        let points = (0..len).map(|i| curve(-range + i as f64 * step)).collect();
+        Self::from_points(-range, step, points)
+    }
+    fn new(curve: &ShapeCurve) -> Self {
+        match curve {
+            ShapeCurve::Tanh => Self::sampled(8.0, 256, f64::tanh),
+            ShapeCurve::HardClip => Self::from_points(-1.0, 2.0, vec![-1.0, 1.0]),
+            ShapeCurve::SoftClip => Self::sampled(1.0, 512, |x| 1.5 * x - 0.5 * x * x * x),
+            // The corners are on the odd numbers, so this is exact
+            ShapeCurve::Foldback => Self::from_points(
+                -63.0,
+                2.0,
+                (0..64)
+                    .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+                    .collect(),
+            ),
+            ShapeCurve::Tube => Self::sampled(8.0, 256, |x| {
+                if x >= 0.0 {
+                    x.tanh()
+                } else {
+                    0.5 * (2.0 * x).tanh()
+                }
+            }),
+            ShapeCurve::Table(points) => match points.len() {
+                0 => Self::from_points(-1.0, 2.0, vec![-1.0, 1.0]),
+                1 => Self::from_points(-1.0, 2.0, vec![points[0] as f64; 2]),
+                len => Self::from_points(
+                    -1.0,
+                    2.0 / (len - 1) as f64,
+                    points.iter().map(|x| *x as f64).collect(),
+                ),
+            },
+        }
+    }
+    /// The curve, and its first and second antiderivatives at x
+    #[inline(always)]
+    fn eval(&self, x: f64) -> (f64, f64, f64) {
+        let last = self.f0.len() - 1;
+        let pos = (x - self.start) / self.step;
+        let (i, slope) = if pos < 0.0 {
+            (0, 0.0)
+        } else if pos >= last as f64 {
+            (last, 0.0)
+        } else {
+            let i = pos as usize;
+            (i, (self.f0[i + 1] - self.f0[i]) / self.step)
+        };
+        let t = x - (self.start + i as f64 * self.step);
+
+        let f0 = self.f0[i] + slope * t;
+        let f1 = self.f1[i] + self.f0[i] * t + slope * t * t / 2.0;
+        let f2 = self.f2[i] + self.f1[i] * t + self.f0[i] * t * t / 2.0 + slope * t * t * t / 6.0;
+        (f0, f1, f2)
+    }
+    #[inline(always)]
+    fn f0(&self, x: f64) -> f64 {
+        self.eval(x).0
+    }
+    #[inline(always)]
+    fn f1(&self, x: f64) -> f64 {
+        self.eval(x).1
+    }
+    #[inline(always)]
+    fn f2(&self, x: f64) -> f64 {
+        self.eval(x).2
+    }
+}
+
+/// The first divided difference of the second antiderivative
+#[inline(always)]
+fn divided_difference(table: &ShapeTable, a: f64, b: f64) -> f64 {
+    let dx = a - b;
+    if dx.abs() < ADAA_EPSILON {
+        table.f1((a + b) / 2.0)
+    } else {
+        (table.f2(a) - table.f2(b)) / dx
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct AdaaState {
+    x1: f64,
+    x2: f64,
+}
+
+impl AdaaState {
+    #[inline(always)]
+    fn tick(&mut self, table: &ShapeTable, adaa: Adaa, x: f64) -> f64 {
+        let y = match adaa {
+            Adaa::Off => table.f0(x),
+            Adaa::First => {
+                let dx = x - self.x1;
+                if dx.abs() < ADAA_EPSILON {
+                    table.f0((x + self.x1) / 2.0)
+                } else {
+                    (table.f1(x) - table.f1(self.x1)) / dx
+                }
+            }
+            Adaa::Second => {
+                let dx = x - self.x2;
+                if dx.abs() < ADAA_EPSILON {
+                    // Bilbao et al, the midpoint form for when x is close to x2
+                    let mid = (x + self.x2) / 2.0;
+                    let dx = mid - self.x1;
+                    if dx.abs() < ADAA_EPSILON {
+                        table.f0((mid + self.x1) / 2.0)
+                    } else {
+                        2.0 / dx * (table.f1(mid) + (table.f2(self.x1) - table.f2(mid)) / dx)
+                    }
+                } else {
+                    2.0 / dx
+                        * (divided_difference(table, x, self.x1)
+                            - divided_difference(table, self.x1, self.x2))
+                }
+            }
+        };
+        self.x2 = self.x1;
+        self.x1 = x;
+        y
+    }
+}
+
+/// A waveshaper for saturation and distortion, with a channel count of C.
+///
+/// The "drive" and "bias" control inputs are added to the params. Everything
+/// follows the context's sample rate, so it can sit inside an `Oversample2X`
+/// subgraph for even less aliasing.
+pub struct Waveshaper<C>
+where
+    C: ArrayLength,
+{
+    params: WaveshaperParams,
+    table: ShapeTable,
+    adaa: GenericArray<AdaaState, C>,
+    dc_blockers: GenericArray<DcBlocker, C>,
+    dc_fs: f32,
+    ports: Ports<C, C, U2, U0>,
+}
+
+impl<C> Waveshaper<C>
+where
+    C: ArrayLength,
+{
+    pub fn new(params: WaveshaperParams) -> Self {
+        Self {
+            table: ShapeTable::new(&params.curve),
+            params,
+            adaa: GenericArray::generate(|_| AdaaState::default()),
+            dc_blockers: GenericArray::generate(|_| DcBlocker::default()),
+            dc_fs: 0.0,
+            ports: Ports {
+                audio_inputs: Some(generate_audio_inputs()),
+                audio_outputs: Some(generate_audio_outputs()),
+                control_inputs: Some(arr![
+                    ControlInputPort {
+                        meta: PortMeta {
+                            name: "drive",
+                            index: 0
+                        },
+                    },
+                    ControlInputPort {
+                        meta: PortMeta {
+                            name: "bias",
+                            index: 1
+                        },
+                    },
+                ]),
+                control_outputs: None,
+            },
+        }
+    }
+}
+
+impl<AF, CF, C> Node<AF, CF> for Waveshaper<C>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+    C: ArrayLength,
+{
+    fn process(
+        &mut self,
+        ctx: &mut AudioContext<AF>,
+        ai: &Frame<AF>,
+        ao: &mut Frame<AF>,
+        ci: &Frame<CF>,
+        _: &mut Frame<CF>,
+    ) {
+        debug_assert_eq!(ai.len(), C::USIZE);
+        debug_assert_eq!(ao.len(), C::USIZE);
+
+        let fs = ctx.get_sample_rate();
+        if fs != self.dc_fs {
+            self.dc_blockers
+                .iter_mut()
+                .for_each(|x| *x = DcBlocker::new(10.0, fs));
+            self.dc_fs = fs;
+        }
+
+        // The control rate does not change when oversampled, so the chunks just get longer
+        let chunk = (AF::USIZE / CF::USIZE).max(1);
+        let adaa = self.params.adaa;
+
+        for c in 0..C::USIZE {
+            let state = &mut self.adaa[c];
+            let dc_blocker = &mut self.dc_blockers[c];
+            for n in 0..AF::USIZE {
+                let m = (n / chunk).min(CF::USIZE - 1);
+                let drive = self.params.drive + ci.first().map_or(0.0, |x| x[m]);
+                let bias = self.params.bias + ci.get(1).map_or(0.0, |x| x[m]);
+
+                let x = (ai[c][n] * drive + bias) as f64;
+                let y = state.tick(&self.table, adaa, x) as f32;
+                ao[c][n] = if self.params.dc_block {
+                    dc_blocker.tick(y)
+                } else {
+                    y
+                };
+            }
+        }
+    }
+}
+
+impl<C> PortedErased for Waveshaper<C>
+where
+    C: ArrayLength,
+{
+    fn get_audio_inputs(&self) -> Option<&[AudioInputPort]> {
+        self.ports.get_audio_inputs()
+    }
+    fn get_audio_outputs(&self) -> Option<&[AudioOutputPort]> {
+        self.ports.get_audio_outputs()
+    }
+    fn get_control_inputs(&self) -> Option<&[ControlInputPort]> {
+        self.ports.get_control_inputs()
+    }
+    fn get_control_outputs(&self) -> Option<&[ControlOutputPort]> {
+        None
+    }
+}
+
+pub type WaveshaperMono = Waveshaper<Mono>;
+pub type WaveshaperStereo = Waveshaper<Stereo>;
+pub type WaveshaperMC<C> = Waveshaper<C>;
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::TAU;
+
+    use typenum::{U64, Unsigned};
+
+    use crate::nodes::utils::test_utils::{FS, NodeRunner};
+
+    use super::{Adaa, ShapeCurve, ShapeTable, WaveshaperMono, WaveshaperParams};
+
+    #[test]
+    fn tables_match_the_closed_forms() {
+        let tanh = ShapeTable::new(&ShapeCurve::Tanh);
+        let hard = ShapeTable::new(&ShapeCurve::HardClip);
+        for x in [-12.0, -3.0, -0.7, 0.0, 0.25, 1.5, 9.0] {
+            let (f0, f1, _) = tanh.eval(x);
+            assert!((f0 - x.tanh()).abs() < 1e-5);
+            assert!((f1 - x.cosh().ln()).abs() < 1e-5);
+
+            // Hard clip is piecewise linear, so these are exact
+            let (f0, f1, f2) = hard.eval(x);
+            assert!((f0 - x.clamp(-1.0, 1.0)).abs() < 1e-12);
+            let (e1, e2) = if x.abs() <= 1.0 {
+                (x * x / 2.0, x * x * x / 6.0)
+            } else {
+                (
+                    x.abs() - 0.5,
+                    x.signum() * (x * x / 2.0 - x.abs() / 2.0 + 1.0 / 6.0),
+                )
+            };
+            assert!((f1 - e1).abs() < 1e-12 && (f2 - e2).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn foldback_passes_then_folds() {
+        let fold = ShapeTable::new(&ShapeCurve::Foldback);
+        for (x, y) in [
+            (0.5, 0.5),
+            (-0.5, -0.5),
+            (1.0, 1.0),
+            (1.5, 0.5),
+            (-1.5, -0.5),
+            (3.5, -0.5),
+        ] {
+            assert!((fold.f0(x) - y).abs() < 1e-12, "f({}) = {}", x, fold.f0(x));
+        }
+    }
+
+    /// Level at a frequency with a single DFT bin
+    fn level_at(signal: &[f32], freq: f32) -> f32 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, x)| {
+                let phase = TAU * freq * n as f32 / FS;
+                (re + x * phase.cos(), im - x * phase.sin())
+            });
+        (re * re + im * im).sqrt() / signal.len() as f32
+    }
+
+    #[test]
+    fn adaa_reduces_aliasing() {
+        let alias = |adaa: Adaa| {
+            let params = WaveshaperParams {
+                curve: ShapeCurve::HardClip,
+                adaa,
+                drive: 4.0,
+                dc_block: false,
+                ..Default::default()
+            };
+            let mut shaper = WaveshaperMono::new(params);
+            let mut runner = NodeRunner::new(&shaper);
+
+            let mut out = Vec::new();
+            for block in 0..75 {
+                for (n, x) in runner.ai[0].iter_mut().enumerate() {
+                    *x = (TAU * 4500.0 * (block * U64::USIZE + n) as f32 / FS).sin();
+                }
+                runner.process(&mut shaper);
+                out.extend_from_slice(&runner.ao[0]);
+            }
+            // The 9th harmonic at 40.5k folds back down to 7.5k
+            level_at(&out, 7500.0)
+        };
+
+        let off = alias(Adaa::Off);
+        let first = alias(Adaa::First);
+        let second = alias(Adaa::Second);
+        assert!(first < off / 4.0, "{} {}", first, off);
+        assert!(second < first, "{} {}", second, first);
+    }
+}
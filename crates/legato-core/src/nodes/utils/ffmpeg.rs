@@ -7,21 +7,28 @@ use crate::engine::resources::audio_sample::AudioSample;
 
 // For the time being, we're just using FFMPEG for loading samples.
 // We can do something better in the future if required, i.e streaming with channel.
-pub fn decode_with_ffmpeg(path: &str, chans: usize, sr: u32) -> std::io::Result<AudioSample> {
+//
+// Without a sample rate, the file is left at its own rate, i.e for wavetables,
+// where resampling would smear the frames into each other.
+pub fn decode_with_ffmpeg(
+    path: &str,
+    chans: usize,
+    sr: Option<u32>,
+) -> std::io::Result<AudioSample> {
+    let chans_arg = chans.to_string();
+    let mut args = vec![
+        "-i", path, // input
+        "-f", "f32le", // correct format for f32
+        "-ac", &chans_arg, // number of channels
+    ];
+    let sr_arg = sr.map(|x| x.to_string());
+    if let Some(sr) = sr_arg.as_deref() {
+        args.extend(["-ar", sr]); // sample rate
+    }
+    args.extend(["-acodec", "pcm_f32le", "pipe:1"]);
+
     let mut child = Command::new("ffmpeg")
-        .args([
-            "-i",
-            path, // input
-            "-f",
-            "f32le", // correct format for f32
-            "-ac",
-            &chans.to_string(), // number of channels
-            "-ar",              // sample rate
-            &sr.to_string(),
-            "-acodec",
-            "pcm_f32le",
-            "pipe:1",
-        ])
+        .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::null()) // silence ffmpeg logging
         .spawn()?;
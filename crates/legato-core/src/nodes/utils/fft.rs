@@ -0,0 +1,99 @@
+use std::f32::consts::TAU;
+
+/// A small in-place radix-2 FFT.
+///
+/// This is not trying to compete with something like RustFFT, it's
+/// just enough for building band-limited tables and analysis,
+/// without pulling in another dependency.
+///
+/// The length of re and im must be the same power of two.
+/// The inverse transform is scaled by 1/N.
+pub fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+    debug_assert_eq!(n, im.len());
+    debug_assert!(n.is_power_of_two(), "FFT size must be a power of two");
+
+    if n <= 1 {
+        return;
+    }
+
+    // Bit reversal permutation
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        let step = sign * TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                let (sin, cos) = (step * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + half;
+
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        let scale = 1.0 / n as f32;
+        re.iter_mut().for_each(|x| *x *= scale);
+        im.iter_mut().for_each(|x| *x *= scale);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fft;
+
+    #[test]
+    fn round_trip() {
+        let input: Vec<f32> = (0..64).map(|i| ((i * 7) % 13) as f32 - 6.0).collect();
+        let mut re = input.clone();
+        let mut im = vec![0.0; 64];
+
+        fft(&mut re, &mut im, false);
+        fft(&mut re, &mut im, true);
+
+        for (a, b) in re.iter().zip(input.iter()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        assert!(im.iter().all(|x| x.abs() < 1e-4));
+    }
+
+    #[test]
+    fn single_bin() {
+        let n = 32;
+        let mut re: Vec<f32> = (0..n)
+            .map(|i| (std::f32::consts::TAU * 3.0 * i as f32 / n as f32).cos())
+            .collect();
+        let mut im = vec![0.0; n];
+
+        fft(&mut re, &mut im, false);
+
+        for k in 0..n {
+            let mag = (re[k] * re[k] + im[k] * im[k]).sqrt();
+            if k == 3 || k == n - 3 {
+                assert!((mag - n as f32 / 2.0).abs() < 1e-3);
+            } else {
+                assert!(mag < 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Tiny recursive filters, for use inside of other nodes.
+//!
+//! These are all single channel and per sample, so nodes
+//! keep one per channel or per parameter. For graph nodes
+//! wrapping these, see nodes::audio::filters::utility.
+
+use std::{f32::consts::TAU, time::Duration};
+
+use crate::nodes::utils::{
+    gain::gain_to_db,
+    interp::{SINC_TAPS, windowed_sinc},
+};
+
+/// The pole for a one pole smoother with a time constant, for
+/// y = x + coeff * (y - x). Anything under a sample is instant.
+pub fn time_coeff(time: Duration, fs: f32) -> f32 {
+    let samples = time.as_secs_f32() * fs;
+    if samples < 1.0 {
+        0.0
+    } else {
+        (-1.0 / samples).exp()
+    }
+}
+
+/// A one pole low pass, also handy as a parameter smoother.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnePoleLp {
+    coeff: f32,
+    z: f32,
+}
+
+impl Default for OnePoleLp {
+    /// Passes everything through until a cutoff or time is set
+    fn default() -> Self {
+        Self { coeff: 1.0, z: 0.0 }
+    }
+}
+
+impl OnePoleLp {
+    pub fn new(cutoff: f32, fs: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_cutoff(cutoff, fs);
+        filter
+    }
+    /// A smoother, reaching ~63% of a step after the given time
+    pub fn with_time(time: Duration, fs: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_time(time, fs);
+        filter
+    }
+    pub fn set_cutoff(&mut self, cutoff: f32, fs: f32) {
+        self.coeff = 1.0 - (-TAU * cutoff.clamp(0.0, fs * 0.5) / fs).exp();
+    }
+    pub fn set_time(&mut self, time: Duration, fs: f32) {
+        self.coeff = 1.0 - time_coeff(time, fs);
+    }
+    /// Jump straight to a value, i.e to avoid smoothing from zero on the first block
+    pub fn reset(&mut self, value: f32) {
+        self.z = value;
+    }
+    #[inline(always)]
+    pub fn value(&self) -> f32 {
+        self.z
+    }
+    #[inline(always)]
+    pub fn tick(&mut self, x: f32) -> f32 {
+        self.z += (x - self.z) * self.coeff;
+        self.z
+    }
+}
+
+/// A one pole high pass, the input minus a one pole low pass.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OnePoleHp {
+    lp: OnePoleLp,
+}
+
+impl OnePoleHp {
+    pub fn new(cutoff: f32, fs: f32) -> Self {
+        Self {
+            lp: OnePoleLp::new(cutoff, fs),
+        }
+    }
+    pub fn set_cutoff(&mut self, cutoff: f32, fs: f32) {
+        self.lp.set_cutoff(cutoff, fs);
+    }
+    #[inline(always)]
+    pub fn tick(&mut self, x: f32) -> f32 {
+        x - self.lp.tick(x)
+    }
+}
+
+/// The classic DC blocker, a zero at DC and a pole just inside the unit circle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DcBlocker {
+    r: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Default for DcBlocker {
+    fn default() -> Self {
+        Self {
+            r: 0.995,
+            x1: 0.0,
+            y1: 0.0,
+        }
+    }
+}
+
+impl DcBlocker {
+    /// A cutoff around 5-20Hz is usually what you want
+    pub fn new(cutoff: f32, fs: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_cutoff(cutoff, fs);
+        filter
+    }
+    pub fn set_cutoff(&mut self, cutoff: f32, fs: f32) {
+        self.r = (1.0 - TAU * cutoff / fs).clamp(0.0, 0.9999);
+    }
+    #[inline(always)]
+    pub fn tick(&mut self, x: f32) -> f32 {
+        let y = x - self.x1 + self.r * self.y1;
+        self.x1 = x;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Limits how fast a signal can rise and fall, in units per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlewLimiter {
+    rise: f32,
+    fall: f32,
+    value: f32,
+}
+
+impl Default for SlewLimiter {
+    /// No limiting until the rates are set
+    fn default() -> Self {
+        Self {
+            rise: f32::INFINITY,
+            fall: f32::INFINITY,
+            value: 0.0,
+        }
+    }
+}
+
+impl SlewLimiter {
+    pub fn new(rise: f32, fall: f32, fs: f32) -> Self {
+        let mut filter = Self::default();
+        filter.set_rates(rise, fall, fs);
+        filter
+    }
+    pub fn set_rates(&mut self, rise: f32, fall: f32, fs: f32) {
+        self.rise = rise.abs() / fs;
+        self.fall = fall.abs() / fs;
+    }
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+    }
+    #[inline(always)]
+    pub fn tick(&mut self, x: f32) -> f32 {
+        self.value += (x - self.value).clamp(-self.fall, self.rise);
+        self.value
+    }
+}
+
+/// Sums the input, leaking a little every sample so it doesn't run away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeakyIntegrator {
+    leak: f32,
+    value: f32,
+}
+
+impl LeakyIntegrator {
+    /// The leak is the amount kept per sample, i.e 0.999. 1.0 is a pure integrator.
+    pub fn new(leak: f32) -> Self {
+        Self {
+            leak: leak.clamp(0.0, 1.0),
+            value: 0.0,
+        }
+    }
+    #[inline(always)]
+    pub fn tick(&mut self, x: f32) -> f32 {
+        self.value = self.value * self.leak + x;
+        self.value
+    }
+}
+
+/// A 4x oversampled peak detector, catching the peaks between samples.
+///
+/// The in-between points are windowed sinc interpolated, so the
+/// reading is delayed by LATENCY samples.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TruePeak {
+    taps: [f32; SINC_TAPS],
+}
+
+impl TruePeak {
+    pub const LATENCY: usize = SINC_TAPS / 2;
+
+    pub fn reset(&mut self) {
+        self.taps = [0.0; SINC_TAPS];
+    }
+    /// Pushes a sample, returning the absolute peak around the center of the taps
+    #[inline(always)]
+    pub fn tick(&mut self, x: f32) -> f32 {
+        self.taps.copy_within(1.., 0);
+        self.taps[SINC_TAPS - 1] = x;
+
+        let mid = SINC_TAPS / 2;
+        [0.25, 0.5, 0.75]
+            .iter()
+            .map(|t| windowed_sinc(&self.taps, *t).abs())
+            .fold(self.taps[mid - 1].abs().max(self.taps[mid].abs()), f32::max)
+    }
+}
+
+/// A falling level with a hold, in dB, for meters and analyzers.
+///
+/// The level falls at the decay rate, and the hold stays at the highest
+/// level for the hold time before falling too.
+#[derive(Debug, Clone, Copy)]
+pub struct Ballistics {
+    pub level: f32,
+    pub hold: f32,
+    hold_left: f32,
+}
+
+impl Default for Ballistics {
+    fn default() -> Self {
+        Self {
+            level: gain_to_db(0.0),
+            hold: gain_to_db(0.0),
+            hold_left: 0.0,
+        }
+    }
+}
+
+impl Ballistics {
+    /// Takes a new reading, secs after the last one
+    pub fn update(&mut self, db: f32, hold: Duration, decay: f32, secs: f32) {
+        let fall = decay * secs;
+        self.level = db.max(self.level - fall);
+
+        if db >= self.hold {
+            self.hold = db;
+            self.hold_left = hold.as_secs_f32();
+        } else if self.hold_left > 0.0 {
+            self.hold_left -= secs;
+        } else {
+            self.hold = (self.hold - fall).max(self.level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DcBlocker, OnePoleLp, SlewLimiter};
+    use crate::nodes::utils::test_utils::FS;
+
+    #[test]
+    fn dc_blocker_removes_offset() {
+        let mut dc = DcBlocker::new(10.0, FS);
+        let mut y = 0.0;
+        for n in 0..FS as usize {
+            let x = 0.5 + 0.1 * (n as f32 * 0.1).sin();
+            y = dc.tick(x);
+        }
+        assert!(y.abs() < 0.11);
+
+        let mut mean = 0.0;
+        for n in 0..4800 {
+            mean += dc.tick(0.5 + 0.1 * (n as f32 * 0.1).sin());
+        }
+        assert!((mean / 4800.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn smoother_and_slew() {
+        let mut lp = OnePoleLp::with_time(std::time::Duration::from_millis(10), FS);
+        // One time constant in, we should be ~63% of the way there
+        let mut y = 0.0;
+        for _ in 0..480 {
+            y = lp.tick(1.0);
+        }
+        assert!((y - 0.632).abs() < 0.01);
+
+        // Rising at 1 per second takes half a second to reach 0.5
+        let mut slew = SlewLimiter::new(1.0, 10.0, FS);
+        for _ in 0..24_000 {
+            y = slew.tick(1.0);
+        }
+        assert!((y - 0.5).abs() < 1e-3);
+        // And falls 10x quicker
+        for _ in 0..2_400 {
+            y = slew.tick(0.0);
+        }
+        assert!(y.abs() < 1e-3);
+    }
+}
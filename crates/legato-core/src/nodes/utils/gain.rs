@@ -0,0 +1,14 @@
+//! Conversions between decibels and linear gain
+
+/// Anything quieter than this is treated as silence, about -180dB
+const SILENCE: f32 = 1e-9;
+
+#[inline(always)]
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+#[inline(always)]
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.abs().max(SILENCE).log10()
+}
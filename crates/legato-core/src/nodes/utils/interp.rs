@@ -0,0 +1,265 @@
+//! Interpolation for reading between samples, shared by the delay
+//! lines, samplers and wavetables.
+//!
+//! The kernels work on fixed size arrays of taps, so the compiler can
+//! unroll and vectorize them. The buffer access is left to the caller,
+//! as delay lines wrap around, and samples may or may not loop.
+
+use std::f32::consts::PI;
+
+/// The number of taps used by the windowed sinc
+pub const SINC_TAPS: usize = 8;
+
+#[inline(always)]
+pub fn lerp(v0: f32, v1: f32, t: f32) -> f32 {
+    (1.0 - t) * v0 + t * v1
+}
+
+/// 4 point, 3rd order Hermite, between x0 and x1
+#[inline(always)]
+pub fn hermite(xm1: f32, x0: f32, x1: f32, x2: f32, t: f32) -> f32 {
+    let c1 = 0.5 * (x1 - xm1);
+    let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+    let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+
+    ((c3 * t + c2) * t + c1) * t + x0
+}
+
+/// N point Lagrange, where taps[N / 2 - 1] is the sample just before the read position
+#[inline(always)]
+pub fn lagrange<const N: usize>(taps: &[f32; N], t: f32) -> f32 {
+    let center = (N / 2 - 1) as f32;
+    let mut out = 0.0;
+    for (k, tap) in taps.iter().enumerate() {
+        let mut weight = 1.0;
+        for m in 0..N {
+            if m != k {
+                weight *= (t + center - m as f32) / (k as f32 - m as f32);
+            }
+        }
+        out += weight * tap;
+    }
+    out
+}
+
+/// Blackman windowed sinc, where taps[SINC_TAPS / 2 - 1] is the sample just before the read position
+#[inline(always)]
+pub fn windowed_sinc(taps: &[f32; SINC_TAPS], t: f32) -> f32 {
+    let half = (SINC_TAPS / 2) as f32;
+    // sin(pi * (t - j)) is just +-sin(pi * t) for whole j, so we only need the one sin
+    let s = (PI * t).sin();
+
+    let mut out = 0.0;
+    let mut total = 0.0;
+    for (k, tap) in taps.iter().enumerate() {
+        let j = k as i32 - (SINC_TAPS / 2 - 1) as i32;
+        let x = t - j as f32;
+        let sinc = if x.abs() < 1e-6 {
+            1.0
+        } else if j % 2 == 0 {
+            s / (PI * x)
+        } else {
+            -s / (PI * x)
+        };
+        let window = 0.42 + 0.5 * (PI * x / half).cos() + 0.08 * (2.0 * PI * x / half).cos();
+        let weight = sinc * window;
+        out += weight * tap;
+        total += weight;
+    }
+    // Normalize, so DC passes at unity for every fractional position
+    out / total
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interp {
+    /// No interpolation, just truncates the position. Cheap, but noisy.
+    Truncate,
+    #[default]
+    Linear,
+    /// 4 point, 3rd order Hermite
+    Hermite,
+    /// 4 point, 3rd order Lagrange
+    Lagrange4,
+    /// 6 point, 5th order Lagrange
+    Lagrange6,
+    /// 1st order Thiran allpass. Flat magnitude, but it has state, so it is
+    /// only a good fit when reading forward one sample at a time, i.e a
+    /// delay line with a fixed or slowly changing time.
+    Allpass,
+    /// 8 point Blackman windowed sinc. The most expensive, and the cleanest.
+    Sinc,
+}
+
+impl Interp {
+    /// How many samples before and after the integer index the interpolation reads
+    pub fn reach(&self) -> (usize, usize) {
+        match self {
+            Interp::Truncate => (0, 0),
+            Interp::Linear => (0, 1),
+            Interp::Hermite | Interp::Lagrange4 | Interp::Allpass => (1, 2),
+            Interp::Lagrange6 => (2, 3),
+            Interp::Sinc => (SINC_TAPS / 2 - 1, SINC_TAPS / 2),
+        }
+    }
+}
+
+/// Reads between samples with the given interpolation.
+///
+/// Stateless modes can share one of these, but the allpass needs one
+/// per channel, as it keeps the last output.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Interpolator {
+    interp: Interp,
+    allpass_y1: f32,
+}
+
+impl Interpolator {
+    pub fn new(interp: Interp) -> Self {
+        Self {
+            interp,
+            allpass_y1: 0.0,
+        }
+    }
+    pub fn interp(&self) -> Interp {
+        self.interp
+    }
+    /// Reads at index + t, where fetch returns the sample at an index.
+    /// The fetch decides what happens past the edges, i.e wrapping or silence.
+    #[inline(always)]
+    pub fn read(&mut self, index: isize, t: f32, fetch: impl Fn(isize) -> f32) -> f32 {
+        match self.interp {
+            Interp::Truncate => fetch(index),
+            Interp::Linear => lerp(fetch(index), fetch(index + 1), t),
+            Interp::Hermite => hermite(
+                fetch(index - 1),
+                fetch(index),
+                fetch(index + 1),
+                fetch(index + 2),
+                t,
+            ),
+            Interp::Lagrange4 => {
+                let taps: [f32; 4] = std::array::from_fn(|k| fetch(index - 1 + k as isize));
+                lagrange(&taps, t)
+            }
+            Interp::Lagrange6 => {
+                let taps: [f32; 6] = std::array::from_fn(|k| fetch(index - 2 + k as isize));
+                lagrange(&taps, t)
+            }
+            Interp::Allpass => {
+                // Keep the fractional delay between 0.5 and 1.5, where the allpass behaves
+                let (newest, delay) = if t < 0.5 {
+                    (index + 1, 1.0 - t)
+                } else {
+                    (index + 2, 2.0 - t)
+                };
+                let eta = (1.0 - delay) / (1.0 + delay);
+                let y = eta * fetch(newest) + fetch(newest - 1) - eta * self.allpass_y1;
+                self.allpass_y1 = y;
+                y
+            }
+            Interp::Sinc => {
+                let first = index - (SINC_TAPS / 2 - 1) as isize;
+                let taps: [f32; SINC_TAPS] = std::array::from_fn(|k| fetch(first + k as isize));
+                windowed_sinc(&taps, t)
+            }
+        }
+    }
+    /// Reads a circular buffer, like a delay line or a single cycle
+    #[inline(always)]
+    pub fn read_wrapped(&mut self, buf: &[f32], pos: f32) -> f32 {
+        let len = buf.len() as isize;
+        let pos = pos.rem_euclid(len as f32);
+        let index = pos.floor();
+        self.read(index as isize, pos - index, |i| {
+            buf[i.rem_euclid(len) as usize]
+        })
+    }
+    /// Reads a one shot buffer, where anything past the edges is silent
+    #[inline(always)]
+    pub fn read_clamped(&mut self, buf: &[f32], pos: f32) -> f32 {
+        let index = pos.floor();
+        self.read(index as isize, pos - index, |i| {
+            if i >= 0 && (i as usize) < buf.len() {
+                buf[i as usize]
+            } else {
+                0.0
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::TAU;
+
+    use super::{Interp, Interpolator};
+
+    const MODES: [Interp; 7] = [
+        Interp::Truncate,
+        Interp::Linear,
+        Interp::Hermite,
+        Interp::Lagrange4,
+        Interp::Lagrange6,
+        Interp::Allpass,
+        Interp::Sinc,
+    ];
+
+    /// Worst error reading a sine back at a fractional offset, one sample at a time
+    fn max_error(interp: Interp, freq: f32, frac: f32) -> f32 {
+        let len = 4096;
+        let buf: Vec<f32> = (0..len)
+            .map(|n| (TAU * freq * n as f32 / len as f32).sin())
+            .collect();
+
+        let mut reader = Interpolator::new(interp);
+        let mut error = 0.0_f32;
+        for n in 0..len {
+            let pos = n as f32 + frac;
+            let expected = (TAU * freq * pos / len as f32).sin();
+            let y = reader.read_wrapped(&buf, pos);
+            // Give the allpass a moment to settle
+            if n > 64 {
+                error = error.max((y - expected).abs());
+            }
+        }
+        error
+    }
+
+    #[test]
+    fn whole_positions_are_exact() {
+        let buf = [0.1, -0.5, 0.9, 0.3, -0.2, 0.7, 0.0, -0.8];
+        for interp in MODES.into_iter().filter(|x| *x != Interp::Allpass) {
+            let mut reader = Interpolator::new(interp);
+            for (i, x) in buf.iter().enumerate() {
+                assert!((reader.read_wrapped(&buf, i as f32) - x).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn higher_orders_are_more_accurate() {
+        // A sine at 1/16th of the sample rate, read half way between samples
+        let linear = max_error(Interp::Linear, 256.0, 0.5);
+        let hermite = max_error(Interp::Hermite, 256.0, 0.5);
+        let lagrange4 = max_error(Interp::Lagrange4, 256.0, 0.5);
+        let lagrange6 = max_error(Interp::Lagrange6, 256.0, 0.5);
+        let sinc = max_error(Interp::Sinc, 256.0, 0.5);
+
+        assert!(linear < 0.02);
+        assert!(hermite < linear);
+        assert!(lagrange4 < linear);
+        assert!(lagrange6 < lagrange4);
+        assert!(sinc < linear);
+
+        // The allpass is all about phase, so it is best at lower frequencies
+        assert!(max_error(Interp::Allpass, 32.0, 0.25) < 1e-3);
+    }
+
+    #[test]
+    fn clamped_reads_are_silent_past_the_edges() {
+        let buf = [1.0; 16];
+        let mut reader = Interpolator::new(Interp::Sinc);
+        assert_eq!(reader.read_clamped(&buf, 100.0), 0.0);
+        assert!((reader.read_clamped(&buf, 8.5) - 1.0).abs() < 1e-6);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod ffmpeg;
+pub mod fft;
+pub mod filters;
+pub mod gain;
+pub mod interp;
 pub mod port_utils;
 pub mod ring;
+pub mod rng;
+pub mod spsc;
+#[cfg(test)]
+pub mod test_utils;
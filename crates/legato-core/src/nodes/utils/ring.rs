@@ -1,7 +1,7 @@
 /// Ringbuffer utility. Note, this is a
 /// ring buffer in the traditional sense, not some
-/// sort of spsc queue implementation. For that, I would
-/// suggest something like heapless, crossbeam, etc.
+/// sort of spsc queue implementation. For sending
+/// audio between threads, see utils::spsc.
 pub struct RingBuffer {
     data: Vec<f32>,
     write_index: usize,
@@ -0,0 +1,70 @@
+/// A small, seedable xorshift64* generator.
+///
+/// Not cryptographically anything, but it's fast, allocation free,
+/// and the same seed always gives the same stream, which keeps
+/// offline renders reproducible.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed with splitmix64, so that nearby seeds give unrelated streams,
+        // and a zero seed doesn't leave the generator stuck at zero.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Self {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+    #[inline(always)]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    /// Uniform in 0.0..1.0
+    #[inline(always)]
+    pub fn next_f32(&mut self) -> f32 {
+        // Top 24 bits, as that's all the mantissa can hold
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    /// Uniform in -1.0..1.0
+    #[inline(always)]
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Rng;
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        let a: Vec<u64> = (0..64).map(|_| a.next_u64()).collect();
+        let b: Vec<u64> = (0..64).map(|_| b.next_u64()).collect();
+        let c: Vec<u64> = (0..64).map(|_| c.next_u64()).collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn floats_stay_in_range() {
+        let mut rng = Rng::new(0);
+        for _ in 0..10_000 {
+            let x = rng.next_bipolar();
+            assert!((-1.0..1.0).contains(&x));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! A wait-free single producer, single consumer queue of samples.
+//!
+//! This is for getting audio off of the audio thread, i.e to a
+//! visualizer or a recorder. Neither side ever blocks or allocates,
+//! the producer just gets told when the queue is full.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use portable_atomic::AtomicF32;
+
+struct Shared {
+    data: Box<[AtomicF32]>,
+    mask: usize,
+    // Both only ever count up, wrapping, so full and empty can be told apart
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+/// Makes a queue holding at least capacity samples, rounded up to a power of two
+pub fn spsc_queue(capacity: usize) -> (Producer, Consumer) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let shared = Arc::new(Shared {
+        data: (0..capacity).map(|_| AtomicF32::new(0.0)).collect(),
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+        },
+        Consumer { shared },
+    )
+}
+
+/// The writing side, usually on the audio thread
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+impl Producer {
+    pub fn capacity(&self) -> usize {
+        self.shared.data.len()
+    }
+    /// How many samples can be pushed right now
+    pub fn free(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        self.capacity() - head.wrapping_sub(tail)
+    }
+    /// Returns false if the queue is full
+    pub fn push(&mut self, x: f32) -> bool {
+        self.push_slice(&[x]) == 1
+    }
+    /// Pushes as much of the slice as fits, returning how many were pushed
+    pub fn push_slice(&mut self, xs: &[f32]) -> usize {
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let count = xs.len().min(self.free());
+        for (i, x) in xs[..count].iter().enumerate() {
+            self.shared.data[head.wrapping_add(i) & self.shared.mask].store(*x, Ordering::Relaxed);
+        }
+        self.shared
+            .head
+            .store(head.wrapping_add(count), Ordering::Release);
+        count
+    }
+}
+
+/// The reading side
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+impl Consumer {
+    pub fn capacity(&self) -> usize {
+        self.shared.data.len()
+    }
+    /// How many samples are waiting
+    pub fn len(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Acquire);
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        head.wrapping_sub(tail)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn pop(&mut self) -> Option<f32> {
+        let mut x = [0.0];
+        (self.pop_slice(&mut x) == 1).then_some(x[0])
+    }
+    /// Fills as much of the slice as possible, returning how many were read
+    pub fn pop_slice(&mut self, out: &mut [f32]) -> usize {
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        let count = out.len().min(self.len());
+        for (i, x) in out[..count].iter_mut().enumerate() {
+            *x = self.shared.data[tail.wrapping_add(i) & self.shared.mask].load(Ordering::Relaxed);
+        }
+        self.shared
+            .tail
+            .store(tail.wrapping_add(count), Ordering::Release);
+        count
+    }
+    /// Throws away everything waiting
+    pub fn clear(&mut self) {
+        let head = self.shared.head.load(Ordering::Acquire);
+        self.shared.tail.store(head, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::spsc_queue;
+
+    #[test]
+    fn fills_and_wraps() {
+        let (mut tx, mut rx) = spsc_queue(6);
+        assert_eq!(tx.capacity(), 8);
+        assert_eq!(rx.pop(), None);
+
+        assert_eq!(tx.push_slice(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), 6);
+        let mut out = [0.0; 4];
+        assert_eq!(rx.pop_slice(&mut out), 4);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0]);
+
+        // Wraps around the end, and stops when full
+        assert_eq!(tx.push_slice(&[7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0]), 6);
+        assert_eq!(tx.free(), 0);
+        assert!(!tx.push(14.0));
+
+        let mut out = [0.0; 10];
+        assert_eq!(rx.pop_slice(&mut out), 8);
+        assert_eq!(out[..8], [5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0]);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn keeps_order_across_threads() {
+        const COUNT: usize = 10_000;
+        let (mut tx, mut rx) = spsc_queue(64);
+
+        let producer = std::thread::spawn(move || {
+            let mut n = 0;
+            while n < COUNT {
+                if tx.push(n as f32) {
+                    n += 1;
+                } else {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        let mut out = [0.0; 16];
+        while expected < COUNT {
+            let read = rx.pop_slice(&mut out);
+            if read == 0 {
+                std::thread::yield_now();
+            }
+            for x in &out[..read] {
+                assert_eq!(*x, expected as f32);
+                expected += 1;
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwapOption;
+use typenum::{U16, U64};
+
+use crate::engine::{
+    audio_context::AudioContext,
+    buffer::Buffer,
+    node::{FrameSize, Node},
+    port::PortedErased,
+    resources::{SampleKey, audio_sample::AudioSample},
+};
+
+pub const FS: f32 = 48_000.0;
+
+/// Runs a node block by block, with silent buffers sized to its ports.
+///
+/// Tests write the inputs straight into ai and ci, and read back ao and co.
+pub struct NodeRunner<AF, CF>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    pub ctx: AudioContext<AF>,
+    pub ai: Vec<Buffer<AF>>,
+    pub ao: Vec<Buffer<AF>>,
+    pub ci: Vec<Buffer<CF>>,
+    pub co: Vec<Buffer<CF>>,
+}
+
+impl NodeRunner<U64, U16> {
+    /// 64 sample blocks at 48k, with 16 control samples a block
+    pub fn new(node: &(impl PortedErased + ?Sized)) -> Self {
+        Self::with_context(context(), node)
+    }
+}
+
+impl<AF, CF> NodeRunner<AF, CF>
+where
+    AF: FrameSize,
+    CF: FrameSize,
+{
+    /// For nodes that need resources on the context, i.e samples
+    pub fn with_context(ctx: AudioContext<AF>, node: &(impl PortedErased + ?Sized)) -> Self {
+        let mut runner = Self {
+            ctx,
+            ai: vec![],
+            ao: vec![],
+            ci: vec![],
+            co: vec![],
+        };
+        runner.fit(node);
+        runner
+    }
+    /// Resizes the buffers to a node's ports, so that one runner,
+    /// and the resources on its context, can be shared between nodes.
+    pub fn fit(&mut self, node: &(impl PortedErased + ?Sized)) {
+        let len = node.get_audio_inputs().map_or(0, |x| x.len());
+        self.ai.resize(len, Buffer::silent());
+        let len = node.get_audio_outputs().map_or(0, |x| x.len());
+        self.ao.resize(len, Buffer::silent());
+        let len = node.get_control_inputs().map_or(0, |x| x.len());
+        self.ci.resize(len, Buffer::silent());
+        let len = node.get_control_outputs().map_or(0, |x| x.len());
+        self.co.resize(len, Buffer::silent());
+    }
+    pub fn process(&mut self, node: &mut (impl Node<AF, CF> + ?Sized)) {
+        node.process(
+            &mut self.ctx,
+            &self.ai,
+            &mut self.ao,
+            &self.ci,
+            &mut self.co,
+        );
+    }
+    /// Runs a number of blocks, returning each audio output end to end
+    pub fn render(
+        &mut self,
+        node: &mut (impl Node<AF, CF> + ?Sized),
+        blocks: usize,
+    ) -> Vec<Vec<f32>> {
+        let mut out = vec![Vec::with_capacity(blocks * AF::USIZE); self.ao.len()];
+        for _ in 0..blocks {
+            self.process(node);
+            for (chan, buffer) in out.iter_mut().zip(self.ao.iter()) {
+                chan.extend_from_slice(buffer);
+            }
+        }
+        out
+    }
+}
+
+/// The context NodeRunner::new uses, for adding resources before the node is made
+pub fn context() -> AudioContext<U64> {
+    AudioContext::new(FS, FS / 4.0)
+}
+
+/// Adds a loaded sample to the context, with a Vec per channel
+pub fn add_sample<AF: FrameSize>(ctx: &mut AudioContext<AF>, data: Vec<Vec<f32>>) -> SampleKey {
+    let sample = AudioSample::new(data.len(), data);
+    ctx.add_sample_resource(Arc::new(ArcSwapOption::new(Some(Arc::new(sample)))))
+}
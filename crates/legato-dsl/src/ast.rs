@@ -247,7 +247,7 @@ fn parse_value(pair: Pair<Rule>) -> Result<Value, BuildAstError> {
         Rule::object => Value::Obj(parse_object(pair)?),
         Rule::array => Value::Array(parse_array(pair)?),
         Rule::ident => Value::Ident(pair.as_str().to_string()),
-        Rule::value => {
+        Rule::value | Rule::boolean => {
             let inner = pair.into_inner().next().unwrap();
             return parse_value(inner);
         }
@@ -5,7 +5,7 @@ use legato_core::engine::{
     builder::{AddNode, RuntimeBuilder, get_runtime_builder},
     graph::{Connection, ConnectionEntry, NodeKey},
     node::FrameSize,
-    port::{PortRate, Ports},
+    port::{PortMeta, PortRate, Ports},
     runtime::{Runtime, RuntimeBackend},
 };
 use std::collections::HashMap;
@@ -106,16 +106,19 @@ where
     );
 
     let mut node_working_name_to_key_map = HashMap::<String, NodeKey>::new();
+    let mut node_handles = Vec::new();
 
     for (working_name, add_node) in ir.add_node_instructions.into_iter() {
-        let key = runtime_builder.add_node(add_node);
+        let (key, handle) = runtime_builder.add_node(add_node);
         node_working_name_to_key_map.insert(working_name.clone(), key);
+        if let Some(handle) = handle {
+            node_handles.push((working_name, handle));
+        }
     }
 
     let mut connections = Vec::<Connection>::new();
 
     for connection in ir.connections {
-        // TODO: Control logic
         // TODO: Messy enough that this needs some tests
         let source_key = node_working_name_to_key_map
             .get(&connection.source_name)
@@ -124,55 +127,53 @@ where
             .get(&connection.sink_name)
             .expect("Could not find sink key in connection");
 
-        let (_, source_audio_ports_out, _, _) = runtime_builder.get_port_info(&source_key);
-        let (sink_audio_ports_in, _, _, _) = runtime_builder.get_port_info(&sink_key);
-
-        let source_ports = source_audio_ports_out.unwrap();
-        let sink_ports = sink_audio_ports_in.unwrap();
-
-        // Assume audio for now
-
-        let manual_port_source: Option<usize> = match connection.source_port {
-            PortConnectionType::Auto => None,
-            PortConnectionType::Named { ref port } => {
-                let found = source_ports.iter().find(|x| x.meta.name == port);
-                let index = found
-                    .expect(&format!("Port {:?} not found", &port))
-                    .meta
-                    .index;
-                Some(index)
-            }
-            PortConnectionType::Indexed { port } => Some(port),
-        };
-
-        let manual_port_sink: Option<usize> = match connection.sink_port {
-            PortConnectionType::Auto => None,
-            PortConnectionType::Named { ref port } => {
-                let found = sink_ports.iter().find(|x| x.meta.name == port);
-                let index = found
-                    .expect(&format!("Port {:?} not found", &port))
-                    .meta
-                    .index;
-                Some(index)
-            }
-            PortConnectionType::Indexed { port } => Some(port),
-        };
+        let (_, source_audio_out, _, source_control_out) =
+            runtime_builder.get_port_info(source_key);
+        let (sink_audio_in, _, sink_control_in, _) = runtime_builder.get_port_info(sink_key);
+
+        let source_audio: Vec<PortMeta> =
+            source_audio_out.map_or(vec![], |ports| ports.iter().map(|x| x.meta).collect());
+        let source_control: Vec<PortMeta> =
+            source_control_out.map_or(vec![], |ports| ports.iter().map(|x| x.meta).collect());
+        let sink_audio: Vec<PortMeta> =
+            sink_audio_in.map_or(vec![], |ports| ports.iter().map(|x| x.meta).collect());
+        let sink_control: Vec<PortMeta> =
+            sink_control_in.map_or(vec![], |ports| ports.iter().map(|x| x.meta).collect());
+
+        let (source_index, source_rate) = resolve_port(
+            &connection.source_port,
+            &source_audio,
+            &source_control,
+            PortRate::Audio,
+        );
+        // Nodes like the SVF have audio and control ports with the same name,
+        // so prefer the one matching the source
+        let (sink_index, sink_rate) = resolve_port(
+            &connection.sink_port,
+            &sink_audio,
+            &sink_control,
+            source_rate,
+        );
 
         connections.push(Connection {
             source: ConnectionEntry {
                 node_key: *source_key,
-                port_index: manual_port_source.unwrap(),
-                port_rate: PortRate::Audio,
+                port_index: source_index,
+                port_rate: source_rate,
             },
             sink: ConnectionEntry {
                 node_key: *sink_key,
-                port_index: manual_port_sink.unwrap(),
-                port_rate: PortRate::Audio,
+                port_index: sink_index,
+                port_rate: sink_rate,
             },
         })
     }
 
-    let (mut runtime, backend) = runtime_builder.get_owned();
+    let (mut runtime, mut backend) = runtime_builder.get_owned();
+
+    for (working_name, handle) in node_handles {
+        backend.add_node_handle(working_name, handle);
+    }
 
     for c in connections {
         runtime.add_edge(c).unwrap();
@@ -188,3 +189,44 @@ where
 
     (runtime, backend)
 }
+
+/// Finds the index and rate of a port.
+///
+/// Names are looked up in the ports of the preferred rate first, then the other rate.
+/// Indexed and auto ports are assumed to be audio, unless the node only
+/// has control ports, i.e an LFO.
+fn resolve_port(
+    port: &PortConnectionType,
+    audio: &[PortMeta],
+    control: &[PortMeta],
+    preferred: PortRate,
+) -> (usize, PortRate) {
+    let default_rate = if audio.is_empty() && !control.is_empty() {
+        PortRate::Control
+    } else {
+        PortRate::Audio
+    };
+    match port {
+        PortConnectionType::Auto => (0, default_rate),
+        PortConnectionType::Indexed { port } => (*port, default_rate),
+        PortConnectionType::Named { port } => {
+            let find = |rate: PortRate| {
+                let ports = match rate {
+                    PortRate::Audio => audio,
+                    PortRate::Control => control,
+                };
+                ports
+                    .iter()
+                    .find(|x| x.name == port)
+                    .map(|x| (x.index, rate))
+            };
+            let other = match preferred {
+                PortRate::Audio => PortRate::Control,
+                PortRate::Control => PortRate::Audio,
+            };
+            find(preferred)
+                .or_else(|| find(other))
+                .unwrap_or_else(|| panic!("Port {:?} not found", port))
+        }
+    }
+}
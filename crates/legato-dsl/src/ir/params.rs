@@ -37,6 +37,8 @@ impl<'a> Params<'a> {
     pub fn get_u32(&self, key: &str) -> Option<u32> {
         match self.0.get(key) {
             Some(Value::U32(s)) => Some(*s),
+            // Plain integers are parsed as I32, so accept them if they're positive
+            Some(Value::I32(s)) if *s >= 0 => Some(*s as u32),
             Some(x) => panic!("Expected U32 param, found {:?}", x),
             _ => None,
         }
@@ -4,10 +4,45 @@ use std::{
     time::Duration,
 };
 
-use legato_core::engine::{builder::AddNode, node::FrameSize};
+use legato_core::{
+    engine::{builder::AddNode, node::FrameSize, port::PortRate},
+    nodes::{
+        audio::{
+            analysis::{EnvelopeFollowerParams, OnsetParams, PitchTrackerParams},
+            channel_mixer::{ChannelMixerParams, MAX_SENDS, MAX_TRACKS, SendParams},
+            drum_rack::{DrumLayer, DrumSlot, DrumSlotParams, MAX_SLOTS},
+            dynamics::{Detection, DynamicsMode, DynamicsParams},
+            envelope::{EnvelopeCurve, EnvelopeMode, Segment},
+            filters::{
+                biquad::{BiquadCascade, BiquadType},
+                svf::FilterType,
+                utility::UtilityFilterType,
+            },
+            granular::{GrainWindow, GranularParams},
+            lfo::{LfoRate, LfoShape},
+            meter::MeterParams,
+            modulation::{ChorusParams, FlangerParams, PhaserParams},
+            noise::NoiseType,
+            oscillator::Waveform,
+            panner::PanLaw,
+            reverb::{
+                blocks::ReverbBlockType,
+                fdn::{FdnMatrix, FdnParams},
+            },
+            sampler::{SamplerMode, SamplerParams},
+            spectrum::{SpectrumParams, SpectrumWindow},
+            waveshaper::{Adaa, ShapeCurve, WaveshaperParams},
+            wavetable::WavetableInterp,
+        },
+        utils::interp::Interp,
+    },
+};
 use typenum::{Prod, U2};
 
-use crate::ir::{ValidationError, params::Params};
+use crate::{
+    ast::Value,
+    ir::{ValidationError, params::Params},
+};
 
 /// A node registry trait that let's users extend the graph logic
 /// to make their own node namespaces. For example, you could make a
@@ -57,6 +92,10 @@ where
             String::from("audio"),
             Box::new(AudioRegistry::default()) as Box<dyn NodeRegistry<AF, CF>>,
         );
+        namespaces.insert(
+            String::from("reverb"),
+            Box::new(ReverbRegistry) as Box<dyn NodeRegistry<AF, CF>>,
+        );
         Self { namespaces }
     }
     pub fn get(
@@ -87,6 +126,210 @@ macro_rules! param_list {
     };
 }
 
+/// MIDI channels are zero indexed. If no channel is provided, the node listens to all channels.
+fn get_midi_channel(params: Option<&Params>) -> Result<Option<u8>, ValidationError> {
+    match params.and_then(|p| p.get_u32("channel")) {
+        Some(c) if c < 16 => Ok(Some(c as u8)),
+        Some(_) => Err(ValidationError::InvalidParameter(
+            "channel must be between 0 and 15".into(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Curves are either linear, exp, or a number for a custom curve
+fn get_envelope_curve(params: Option<&Params>) -> Result<EnvelopeCurve, ValidationError> {
+    match params.and_then(|p| p.0.get("curve")) {
+        None => Ok(EnvelopeCurve::default()),
+        Some(Value::F32(x)) => Ok(EnvelopeCurve::Curve(*x)),
+        Some(Value::I32(x)) => Ok(EnvelopeCurve::Curve(*x as f32)),
+        Some(Value::Ident(x)) | Some(Value::Str(x)) => match x.as_str() {
+            "linear" => Ok(EnvelopeCurve::Linear),
+            "exp" => Ok(EnvelopeCurve::Exponential),
+            _ => Err(ValidationError::InvalidParameter(format!(
+                "Unknown envelope curve {}",
+                x
+            ))),
+        },
+        Some(x) => Err(ValidationError::InvalidParameter(format!(
+            "Unknown envelope curve {:?}",
+            x
+        ))),
+    }
+}
+
+fn get_envelope_mode(params: Option<&Params>) -> Result<EnvelopeMode, ValidationError> {
+    match params.and_then(|p| p.get_str("mode")).as_deref() {
+        None => Ok(EnvelopeMode::default()),
+        Some("retrigger") => Ok(EnvelopeMode::Retrigger),
+        Some("legato") => Ok(EnvelopeMode::Legato),
+        Some(x) => Err(ValidationError::InvalidParameter(format!(
+            "Unknown envelope mode {}",
+            x
+        ))),
+    }
+}
+
+/// Interpolation for the nodes reading between samples, defaulting to linear
+fn get_interp(params: Option<&Params>) -> Result<Interp, ValidationError> {
+    match params.and_then(|p| p.get_str("interp")).as_deref() {
+        None | Some("linear") => Ok(Interp::Linear),
+        Some("truncate") => Ok(Interp::Truncate),
+        Some("hermite") | Some("cubic") => Ok(Interp::Hermite),
+        Some("lagrange4") => Ok(Interp::Lagrange4),
+        Some("lagrange6") => Ok(Interp::Lagrange6),
+        Some("allpass") => Ok(Interp::Allpass),
+        Some("sinc") => Ok(Interp::Sinc),
+        Some(x) => Err(ValidationError::InvalidParameter(format!(
+            "Unknown interpolation {}",
+            x
+        ))),
+    }
+}
+
+fn sampler_params() -> BTreeSet<String> {
+    param_list!(
+        "sample_name",
+        "start",
+        "end",
+        "loop_start",
+        "loop_end",
+        "mode",
+        "reverse",
+        "pitch",
+        "crossfade",
+        "volume",
+        "autoplay",
+        "interp"
+    )
+}
+
+fn get_sampler_params(p: &Params) -> Result<SamplerParams, ValidationError> {
+    let default = SamplerParams::default();
+    let mode = match p.get_str("mode").as_deref() {
+        None | Some("loop") => SamplerMode::Loop,
+        Some("one_shot") => SamplerMode::OneShot,
+        Some("ping_pong") => SamplerMode::PingPong,
+        Some(x) => {
+            return Err(ValidationError::InvalidParameter(format!(
+                "Unknown sampler mode {}, expected one_shot, loop or ping_pong",
+                x
+            )));
+        }
+    };
+    Ok(SamplerParams {
+        start: p.get_f32("start").unwrap_or(default.start),
+        end: p.get_f32("end").unwrap_or(default.end),
+        loop_start: p.get_f32("loop_start").unwrap_or(default.loop_start),
+        loop_end: p.get_f32("loop_end").unwrap_or(default.loop_end),
+        mode,
+        reverse: p.get_bool("reverse").unwrap_or(default.reverse),
+        pitch: p.get_f32("pitch").unwrap_or(default.pitch),
+        crossfade: p.get_duration("crossfade").unwrap_or(default.crossfade),
+        volume: p.get_f32("volume").unwrap_or(default.volume),
+        autoplay: p.get_bool("autoplay").unwrap_or(default.autoplay),
+        interp: get_interp(Some(p))?,
+    })
+}
+
+/// A drum rack slot, with either a single sample_name, or velocity layers
+/// like layers: [{ sample_name: "soft" }, { sample_name: "hard", velocity: 0.7 }]
+fn get_drum_slot(value: &Value) -> Result<DrumSlot<String>, ValidationError> {
+    let Value::Obj(obj) = value else {
+        return Err(ValidationError::InvalidParameter(format!(
+            "drum rack slots must be objects, got {:?}",
+            value
+        )));
+    };
+    let p = Params::new(obj);
+    p.validate(&param_list!(
+        "sample_name",
+        "layers",
+        "note",
+        "gain",
+        "pan",
+        "pitch",
+        "choke",
+        "voices"
+    ))?;
+
+    let mut layers = Vec::new();
+    if let Some(sample) = p.get_str("sample_name") {
+        layers.push(DrumLayer {
+            sample,
+            min_velocity: 0.0,
+        });
+    }
+    for layer in p.get_array("layers").unwrap_or_default() {
+        let Value::Obj(obj) = layer else {
+            return Err(ValidationError::InvalidParameter(
+                "drum rack layers must be objects".into(),
+            ));
+        };
+        let layer = Params::new(&obj);
+        layer.validate(&param_list!("sample_name", "velocity"))?;
+        layer.required(&param_list!("sample_name"))?;
+        layers.push(DrumLayer {
+            sample: layer.get_str("sample_name").unwrap(),
+            min_velocity: layer.get_f32("velocity").unwrap_or(0.0),
+        });
+    }
+    if layers.is_empty() {
+        return Err(ValidationError::MissingRequiredParameter(
+            "drum rack slots need a sample_name or layers".into(),
+        ));
+    }
+
+    let note = match p.get_u32("note") {
+        Some(n) if n < 128 => Some(n as u8),
+        Some(n) => {
+            return Err(ValidationError::InvalidParameter(format!(
+                "drum rack notes must be between 0 and 127, got {}",
+                n
+            )));
+        }
+        None => None,
+    };
+    let default = DrumSlotParams::default();
+    Ok(DrumSlot {
+        layers,
+        params: DrumSlotParams {
+            note,
+            gain: p.get_f32("gain").unwrap_or(default.gain),
+            pan: p.get_f32("pan").unwrap_or(default.pan).clamp(-1.0, 1.0),
+            pitch: p.get_f32("pitch").unwrap_or(default.pitch),
+            choke: p.get_u32("choke").map(|x| x as u8),
+            voices: p
+                .get_u32("voices")
+                .map_or(default.voices, |x| x as usize)
+                .max(1),
+        },
+    })
+}
+
+fn get_detection(params: Option<&Params>) -> Result<Detection, ValidationError> {
+    match params.and_then(|p| p.get_str("detection")).as_deref() {
+        None | Some("peak") => Ok(Detection::Peak),
+        Some("rms") => Ok(Detection::Rms),
+        Some(x) => Err(ValidationError::InvalidParameter(format!(
+            "Unknown detection {}, expected peak or rms",
+            x
+        ))),
+    }
+}
+
+/// For nodes that can run at either rate, defaulting to audio
+fn get_port_rate(params: Option<&Params>) -> Result<PortRate, ValidationError> {
+    match params.and_then(|p| p.get_str("rate")).as_deref() {
+        None | Some("audio") => Ok(PortRate::Audio),
+        Some("control") => Ok(PortRate::Control),
+        Some(x) => Err(ValidationError::InvalidParameter(format!(
+            "Unknown rate {}, expected audio or control",
+            x
+        ))),
+    }
+}
+
 /// One of the default registries, audio deals
 /// with common audio effects. This may be renamed
 /// in the future.
@@ -121,42 +364,443 @@ where
                 let freq = params.and_then(|p| p.get_f32("freq")).unwrap_or(440.0);
                 Ok(AddNode::SineStereo { freq })
             }
+            "saw" | "square" | "pulse" | "triangle" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("freq", "phase", "width", "chans"))?;
+                }
+                let waveform = match name.as_str() {
+                    "saw" => Waveform::Saw,
+                    "square" => Waveform::Square,
+                    "pulse" => Waveform::Pulse,
+                    _ => Waveform::Triangle,
+                };
+                let freq = params.and_then(|p| p.get_f32("freq")).unwrap_or(440.0);
+                let phase = params.and_then(|p| p.get_f32("phase")).unwrap_or(0.0);
+                let pulse_width = params.and_then(|p| p.get_f32("width")).unwrap_or(0.5);
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(1) as usize;
+
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "Oscillator chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+
+                Ok(AddNode::Oscillator {
+                    waveform,
+                    freq,
+                    pulse_width,
+                    phase,
+                    chans,
+                })
+            }
+            // Noise
+            "white_noise" | "pink_noise" | "brown_noise" | "velvet_noise" => {
+                if let Some(p) = params {
+                    if name == "velvet_noise" {
+                        p.validate(&param_list!("seed", "chans", "density"))?;
+                    } else {
+                        p.validate(&param_list!("seed", "chans"))?;
+                    }
+                }
+                let noise_type = match name.as_str() {
+                    "white_noise" => NoiseType::White,
+                    "pink_noise" => NoiseType::Pink,
+                    "brown_noise" => NoiseType::Brown,
+                    _ => NoiseType::Velvet {
+                        density: params.and_then(|p| p.get_f32("density")).unwrap_or(2000.0),
+                    },
+                };
+                let seed = params.and_then(|p| p.get_u32("seed")).unwrap_or(0) as u64;
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(1) as usize;
+
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "Noise chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+
+                Ok(AddNode::Noise {
+                    noise_type,
+                    seed,
+                    chans,
+                })
+            }
+            // Envelopes
+            "adsr" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "attack", "decay", "sustain", "release", "curve", "mode", "rate"
+                    ))?;
+                }
+                let duration = |key: &str, default: u64| {
+                    params
+                        .and_then(|p| p.get_duration(key))
+                        .unwrap_or(Duration::from_millis(default))
+                };
+
+                Ok(AddNode::Adsr {
+                    attack: duration("attack", 10),
+                    decay: duration("decay", 100),
+                    sustain: params.and_then(|p| p.get_f32("sustain")).unwrap_or(0.7),
+                    release: duration("release", 200),
+                    curve: get_envelope_curve(params)?,
+                    mode: get_envelope_mode(params)?,
+                    rate: get_port_rate(params)?,
+                })
+            }
+            "envelope" => {
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(
+                    "envelope requires levels and times".into(),
+                ))?;
+                p.validate(&param_list!(
+                    "levels",
+                    "times",
+                    "curve",
+                    "sustain",
+                    "loop_start",
+                    "loop_end",
+                    "mode",
+                    "rate"
+                ))?;
+                p.required(&param_list!("levels", "times"))?;
+
+                let levels = p.get_array_f32("levels").unwrap();
+                let times = p.get_array_duration_ms("times").unwrap();
+
+                if levels.is_empty() || levels.len() != times.len() {
+                    return Err(ValidationError::InvalidParameter(
+                        "envelope levels and times must be the same, non-zero length".into(),
+                    ));
+                }
+
+                let curve = get_envelope_curve(params)?;
+                let segments = levels
+                    .into_iter()
+                    .zip(times)
+                    .map(|(target, duration)| Segment::new(target, duration, curve))
+                    .collect();
+
+                let loop_points = match (p.get_u32("loop_start"), p.get_u32("loop_end")) {
+                    (Some(start), Some(end)) => Some((start as usize, end as usize)),
+                    (None, None) => None,
+                    _ => {
+                        return Err(ValidationError::MissingRequiredParameters(
+                            "envelope loops require both loop_start and loop_end".into(),
+                        ));
+                    }
+                };
+
+                Ok(AddNode::Envelope {
+                    segments,
+                    sustain: p.get_u32("sustain").map(|x| x as usize),
+                    loop_points,
+                    mode: get_envelope_mode(params)?,
+                    rate: get_port_rate(params)?,
+                })
+            }
+            // Modulation
+            "lfo" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "shape", "freq", "beats", "phase", "bipolar", "seed"
+                    ))?;
+                }
+                let shape = match params.and_then(|p| p.get_str("shape")).as_deref() {
+                    None | Some("sine") => LfoShape::Sine,
+                    Some("triangle") => LfoShape::Triangle,
+                    Some("saw") => LfoShape::Saw,
+                    Some("square") => LfoShape::Square,
+                    Some("sample_and_hold") => LfoShape::SampleAndHold,
+                    Some("smooth_random") => LfoShape::SmoothRandom,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown lfo shape {}",
+                            x
+                        )));
+                    }
+                };
+                let rate = match (
+                    params.and_then(|p| p.get_f32("freq")),
+                    params.and_then(|p| p.get_f32("beats")),
+                ) {
+                    (Some(_), Some(_)) => {
+                        return Err(ValidationError::InvalidParameter(
+                            "lfo takes either freq or beats, not both".into(),
+                        ));
+                    }
+                    (_, Some(beats)) => LfoRate::Sync(beats),
+                    (freq, None) => LfoRate::Hz(freq.unwrap_or(1.0)),
+                };
+
+                Ok(AddNode::Lfo {
+                    shape,
+                    rate,
+                    phase_offset: params.and_then(|p| p.get_f32("phase")).unwrap_or(0.0),
+                    bipolar: params.and_then(|p| p.get_bool("bipolar")).unwrap_or(true),
+                    seed: params.and_then(|p| p.get_u32("seed")).unwrap_or(0) as u64,
+                })
+            }
+            "wavetable_mono" | "wavetable_stereo" => {
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(
+                    "wavetable requires wavetable_name".into(),
+                ))?;
+                p.validate(&param_list!("wavetable_name", "freq", "frame", "interp"))?;
+                p.required(&param_list!("wavetable_name"))?;
+
+                let wavetable_name = p.get_str("wavetable_name").unwrap();
+                let freq = p.get_f32("freq").unwrap_or(440.0);
+                let frame = p.get_f32("frame").unwrap_or(0.0);
+                let interp = match p.get_str("interp").as_deref() {
+                    None => WavetableInterp::default(),
+                    Some("truncate") => WavetableInterp::Truncate,
+                    Some("linear") => WavetableInterp::Linear,
+                    Some("cubic") => WavetableInterp::Cubic,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown interpolation {}",
+                            x
+                        )));
+                    }
+                };
+
+                if name == "wavetable_mono" {
+                    Ok(AddNode::WavetableMono {
+                        wavetable_name,
+                        freq,
+                        frame,
+                        interp,
+                    })
+                } else {
+                    Ok(AddNode::WavetableStereo {
+                        wavetable_name,
+                        freq,
+                        frame,
+                        interp,
+                    })
+                }
+            }
             // Fan mono to stereo
             "stereo" => Ok(AddNode::Stereo),
-            "sampler_mono" => {
-                let p = params
-                    .ok_or(ValidationError::MissingRequiredParameter(String::from(
-                        "Sampler requires sample key",
-                    )))
-                    .unwrap();
+            // Stereo tools
+            "panner" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("law", "pan"))?;
+                }
+                let law = match params.and_then(|p| p.get_str("law")).as_deref() {
+                    Some("linear") | Some("0db") => PanLaw::Linear,
+                    None | Some("constant_power") | Some("3db") => PanLaw::ConstantPower,
+                    Some("compromise") | Some("4.5db") => PanLaw::Compromise,
+                    Some("linear_taper") | Some("6db") => PanLaw::LinearTaper,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown pan law {}",
+                            x
+                        )));
+                    }
+                };
+                let pan = params.and_then(|p| p.get_f32("pan")).unwrap_or(0.0);
+                Ok(AddNode::Panner { law, pan })
+            }
+            "balance" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("balance"))?;
+                }
+                let balance = params.and_then(|p| p.get_f32("balance")).unwrap_or(0.0);
+                Ok(AddNode::Balance { balance })
+            }
+            "ms_encode" => Ok(AddNode::MidSideEncode),
+            "ms_decode" => Ok(AddNode::MidSideDecode),
+            "stereo_width" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("width"))?;
+                }
+                let width = params.and_then(|p| p.get_f32("width")).unwrap_or(1.0);
+                Ok(AddNode::StereoWidth { width })
+            }
+            // MIDI
+            "note_to_freq" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("bend_range", "channel"))?;
+                }
+                let bend_range = params.and_then(|p| p.get_f32("bend_range")).unwrap_or(2.0);
+                let channel = get_midi_channel(params)?;
+                Ok(AddNode::NoteToFreq {
+                    bend_range,
+                    channel,
+                })
+            }
+            "gate" | "trigger" | "velocity" | "aftertouch" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("channel"))?;
+                }
+                let channel = get_midi_channel(params)?;
+                Ok(match name.as_str() {
+                    "gate" => AddNode::Gate { channel },
+                    "trigger" => AddNode::Trigger { channel },
+                    "velocity" => AddNode::Velocity { channel },
+                    _ => AddNode::Aftertouch { channel },
+                })
+            }
+            "cc" => {
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(
+                    "cc requires a cc number".into(),
+                ))?;
+                p.validate(&param_list!("cc", "smoothing", "channel"))?;
+                p.required(&param_list!("cc"))?;
+
+                let cc = p.get_u32("cc").unwrap();
+                if cc > 127 {
+                    return Err(ValidationError::InvalidParameter(
+                        "cc must be between 0 and 127".into(),
+                    ));
+                }
+                let smoothing = p
+                    .get_duration("smoothing")
+                    .unwrap_or(Duration::from_millis(10));
+                let channel = get_midi_channel(params)?;
 
-                let p_list = param_list!("sample_name");
+                Ok(AddNode::ControlChange {
+                    cc: cc as u8,
+                    smoothing,
+                    channel,
+                })
+            }
+            "sampler_mono" => {
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(String::from(
+                    "Sampler requires sample key",
+                )))?;
 
-                p.validate(&p_list).unwrap();
-                p.required(&p_list).unwrap();
+                p.validate(&sampler_params())?;
+                p.required(&param_list!("sample_name"))?;
 
-                let sampler_name = p.get_str("sampler_name").unwrap();
+                let sampler_name = p.get_str("sample_name").unwrap();
 
                 Ok(AddNode::SamplerMono {
                     sampler_name: sampler_name,
+                    params: get_sampler_params(p)?,
                 })
             }
             "sampler_stereo" => {
-                let p = params
-                    .ok_or(ValidationError::MissingRequiredParameter(String::from(
-                        "Sampler requires sample key",
-                    )))
-                    .unwrap();
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(String::from(
+                    "Sampler requires sample key",
+                )))?;
 
-                let p_list = param_list!("sample_name");
+                p.validate(&sampler_params())?;
+                p.required(&param_list!("sample_name"))?;
 
-                p.validate(&p_list).unwrap();
-                p.required(&p_list).unwrap();
-
-                let sampler_name = p.get_str("sampler_name").unwrap();
+                let sampler_name = p.get_str("sample_name").unwrap();
 
                 Ok(AddNode::SamplerStereo {
                     sampler_name: sampler_name,
+                    params: get_sampler_params(p)?,
+                })
+            }
+            "drum_rack" => {
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(
+                    "drum rack requires slots".into(),
+                ))?;
+                p.validate(&param_list!("slots", "channel", "interp", "chans"))?;
+                p.required(&param_list!("slots"))?;
+
+                let slots = p
+                    .get_array("slots")
+                    .unwrap()
+                    .iter()
+                    .map(get_drum_slot)
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !(1..=MAX_SLOTS).contains(&slots.len()) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "drum rack slots must be between 1 and {}, got {}",
+                        MAX_SLOTS,
+                        slots.len()
+                    )));
+                }
+                let chans = p.get_u32("chans").unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "drum rack chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+
+                Ok(AddNode::DrumRack {
+                    slots,
+                    channel: get_midi_channel(params)?,
+                    interp: get_interp(params)?,
+                    chans,
+                })
+            }
+            "granular" => {
+                let p = params.ok_or(ValidationError::MissingRequiredParameter(String::from(
+                    "Granular requires sample key",
+                )))?;
+
+                p.validate(&param_list!(
+                    "sample_name",
+                    "grain_size",
+                    "density",
+                    "position",
+                    "position_jitter",
+                    "pitch",
+                    "pitch_jitter",
+                    "window",
+                    "spread",
+                    "voices",
+                    "interp",
+                    "seed",
+                    "chans"
+                ))?;
+                p.required(&param_list!("sample_name"))?;
+
+                let chans = p.get_u32("chans").unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "granular chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+                let window = match p.get_str("window").as_deref() {
+                    None | Some("hann") => GrainWindow::Hann,
+                    Some("triangle") => GrainWindow::Triangle,
+                    Some("tukey") => GrainWindow::Tukey,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown grain window {}",
+                            x
+                        )));
+                    }
+                };
+                let default = GranularParams::default();
+                let voices = p.get_u32("voices").map_or(default.voices, |x| x as usize);
+                if voices == 0 {
+                    return Err(ValidationError::InvalidParameter(
+                        "granular needs at least one voice".into(),
+                    ));
+                }
+
+                Ok(AddNode::Granular {
+                    sampler_name: p.get_str("sample_name").unwrap(),
+                    params: GranularParams {
+                        grain_size: p.get_duration("grain_size").unwrap_or(default.grain_size),
+                        density: p.get_f32("density").unwrap_or(default.density),
+                        position: p.get_f32("position").unwrap_or(default.position),
+                        position_jitter: p
+                            .get_f32("position_jitter")
+                            .unwrap_or(default.position_jitter),
+                        pitch: p.get_f32("pitch").unwrap_or(default.pitch),
+                        pitch_jitter: p.get_f32("pitch_jitter").unwrap_or(default.pitch_jitter),
+                        window,
+                        spread: p
+                            .get_f32("spread")
+                            .unwrap_or(default.spread)
+                            .clamp(0.0, 1.0),
+                        voices,
+                        interp: get_interp(params)?,
+                        seed: p.get_u32("seed").map_or(default.seed, |x| x as u64),
+                    },
+                    chans,
                 })
             }
             // Delays
@@ -211,7 +855,7 @@ where
                     )))
                     .unwrap();
 
-                let p_list = param_list!("delay_name", "offsets");
+                let p_list = param_list!("delay_name", "offsets", "smoothing", "interp");
 
                 p.validate(&p_list).unwrap();
                 p.required(&param_list!("delay_name")).unwrap();
@@ -220,10 +864,15 @@ where
                 let offsets = p
                     .get_array_duration_ms("offsets")
                     .unwrap_or(vec![Duration::from_millis(200); 1]);
+                let smoothing = p
+                    .get_duration("smoothing")
+                    .unwrap_or(Duration::from_millis(50));
 
                 Ok(AddNode::DelayReadMono {
                     delay_name: delay_name,
                     offsets: offsets,
+                    smoothing,
+                    interp: get_interp(params)?,
                 })
             }
             "delay_read_stereo" => {
@@ -233,7 +882,7 @@ where
                     )))
                     .unwrap();
 
-                let p_list = param_list!("delay_name", "offsets");
+                let p_list = param_list!("delay_name", "offsets", "smoothing", "interp");
 
                 p.validate(&p_list).unwrap();
                 p.required(&param_list!("delay_name")).unwrap();
@@ -242,10 +891,15 @@ where
                 let offsets = p
                     .get_array_duration_ms("offsets")
                     .unwrap_or(vec![Duration::from_millis(200); 1]);
+                let smoothing = p
+                    .get_duration("smoothing")
+                    .unwrap_or(Duration::from_millis(50));
 
                 Ok(AddNode::DelayReadStereo {
                     delay_name: delay_name,
                     offsets: offsets,
+                    smoothing,
+                    interp: get_interp(params)?,
                 })
             }
             // FIR filters
@@ -272,6 +926,497 @@ where
                 let coeffs = p.get_array_f32("coeffs").unwrap();
                 Ok(AddNode::FirStereo { coeffs })
             }
+            "lowpass" | "highpass" | "bandpass" | "notch" | "peak" | "lowshelf" | "highshelf"
+            | "allpass" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "cutoff", "q", "gain", "chans", "order", "response"
+                    ))?;
+                }
+                let filter_type = match name.as_str() {
+                    "lowpass" => BiquadType::LowPass,
+                    "highpass" => BiquadType::HighPass,
+                    "bandpass" => BiquadType::BandPass,
+                    "notch" => BiquadType::Notch,
+                    "peak" => BiquadType::Peak,
+                    "lowshelf" => BiquadType::LowShelf,
+                    "highshelf" => BiquadType::HighShelf,
+                    _ => BiquadType::AllPass,
+                };
+
+                let order = params.and_then(|p| p.get_u32("order")).map(|x| x as usize);
+                let response = params.and_then(|p| p.get_str("response"));
+                let cascade = match (order, response.as_deref()) {
+                    (None, None) => BiquadCascade::Single,
+                    _ if !matches!(filter_type, BiquadType::LowPass | BiquadType::HighPass) => {
+                        return Err(ValidationError::InvalidParameter(
+                            "Only lowpass and highpass filters can have an order or response"
+                                .into(),
+                        ));
+                    }
+                    (order, None | Some("butterworth")) => {
+                        BiquadCascade::Butterworth(order.unwrap_or(2))
+                    }
+                    (order, Some("linkwitz_riley")) => {
+                        let order = order.unwrap_or(4);
+                        if order == 0 || order % 2 == 1 {
+                            return Err(ValidationError::InvalidParameter(format!(
+                                "Linkwitz-Riley filters need an even order, got {}",
+                                order
+                            )));
+                        }
+                        BiquadCascade::LinkwitzRiley(order)
+                    }
+                    (_, Some(x)) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown filter response {}",
+                            x
+                        )));
+                    }
+                };
+                if let BiquadCascade::Butterworth(0) = cascade {
+                    return Err(ValidationError::InvalidParameter(
+                        "Filter order must be at least 1".into(),
+                    ));
+                }
+
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(1) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "Filter chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+
+                Ok(AddNode::Biquad {
+                    filter_type,
+                    cutoff: params.and_then(|p| p.get_f32("cutoff")).unwrap_or(1000.0),
+                    q: params
+                        .and_then(|p| p.get_f32("q"))
+                        .unwrap_or(std::f32::consts::FRAC_1_SQRT_2),
+                    gain_db: params.and_then(|p| p.get_f32("gain")).unwrap_or(0.0),
+                    cascade,
+                    chans,
+                })
+            }
+            "svf_mono" | "svf_stereo" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("type", "cutoff", "q", "gain"))?;
+                }
+                let filter_type = match params.and_then(|p| p.get_str("type")).as_deref() {
+                    None | Some("lowpass") => FilterType::LowPass,
+                    Some("bandpass") => FilterType::BandPass,
+                    Some("highpass") => FilterType::HighPass,
+                    Some("notch") => FilterType::Notch,
+                    Some("peak") => FilterType::Peak,
+                    Some("allpass") => FilterType::AllPass,
+                    Some("bell") => FilterType::Bell,
+                    Some("lowshelf") => FilterType::LowShelf,
+                    Some("highshelf") => FilterType::HighShelf,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown svf type {}",
+                            x
+                        )));
+                    }
+                };
+                let cutoff = params.and_then(|p| p.get_f32("cutoff")).unwrap_or(1000.0);
+                let q = params
+                    .and_then(|p| p.get_f32("q"))
+                    .unwrap_or(std::f32::consts::FRAC_1_SQRT_2);
+                let gain = params.and_then(|p| p.get_f32("gain")).unwrap_or(0.0);
+
+                if name == "svf_mono" {
+                    Ok(AddNode::SvfMono {
+                        filter_type,
+                        cutoff,
+                        q,
+                        gain,
+                    })
+                } else {
+                    Ok(AddNode::SvfStereo {
+                        filter_type,
+                        cutoff,
+                        q,
+                        gain,
+                    })
+                }
+            }
+            "onepole_lp" | "onepole_hp" | "dc_block" | "slew" | "leaky_integrator" => {
+                if let Some(p) = params {
+                    match name.as_str() {
+                        "onepole_lp" | "onepole_hp" | "dc_block" => {
+                            p.validate(&param_list!("cutoff", "chans"))?
+                        }
+                        "slew" => p.validate(&param_list!("rise", "fall", "chans"))?,
+                        _ => p.validate(&param_list!("leak", "chans"))?,
+                    }
+                }
+                let filter_type = match name.as_str() {
+                    "onepole_lp" => UtilityFilterType::LowPass {
+                        cutoff: params.and_then(|p| p.get_f32("cutoff")).unwrap_or(1000.0),
+                    },
+                    "onepole_hp" => UtilityFilterType::HighPass {
+                        cutoff: params.and_then(|p| p.get_f32("cutoff")).unwrap_or(1000.0),
+                    },
+                    "dc_block" => UtilityFilterType::DcBlock {
+                        cutoff: params.and_then(|p| p.get_f32("cutoff")).unwrap_or(10.0),
+                    },
+                    "slew" => {
+                        // Rise and fall in units per second, the fall defaults to the rise
+                        let rise = params.and_then(|p| p.get_f32("rise")).unwrap_or(1000.0);
+                        let fall = params.and_then(|p| p.get_f32("fall")).unwrap_or(rise);
+                        UtilityFilterType::Slew { rise, fall }
+                    }
+                    _ => {
+                        let leak = params.and_then(|p| p.get_f32("leak")).unwrap_or(0.999);
+                        if !(0.0..=1.0).contains(&leak) {
+                            return Err(ValidationError::InvalidParameter(format!(
+                                "leak must be between 0.0 and 1.0, got {}",
+                                leak
+                            )));
+                        }
+                        UtilityFilterType::LeakyIntegrator { leak }
+                    }
+                };
+
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(1) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "Filter chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+
+                Ok(AddNode::UtilityFilter { filter_type, chans })
+            }
+            "fdn_reverb" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "lines",
+                        "matrix",
+                        "size",
+                        "decay",
+                        "damping",
+                        "mix",
+                        "early",
+                        "pre_delay"
+                    ))?;
+                }
+                let lines = params.and_then(|p| p.get_u32("lines")).unwrap_or(8) as usize;
+                if lines != 8 && lines != 16 {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "fdn_reverb lines must be 8 or 16, got {}",
+                        lines
+                    )));
+                }
+                let matrix = match params.and_then(|p| p.get_str("matrix")).as_deref() {
+                    None | Some("householder") => FdnMatrix::Householder,
+                    Some("hadamard") => FdnMatrix::Hadamard,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown fdn matrix {}",
+                            x
+                        )));
+                    }
+                };
+
+                let default = FdnParams::default();
+                let params = FdnParams {
+                    size: params
+                        .and_then(|p| p.get_f32("size"))
+                        .unwrap_or(default.size),
+                    decay: params
+                        .and_then(|p| p.get_f32("decay"))
+                        .unwrap_or(default.decay),
+                    damping: params
+                        .and_then(|p| p.get_f32("damping"))
+                        .unwrap_or(default.damping),
+                    mix: params.and_then(|p| p.get_f32("mix")).unwrap_or(default.mix),
+                    early: params
+                        .and_then(|p| p.get_f32("early"))
+                        .unwrap_or(default.early),
+                    pre_delay: params
+                        .and_then(|p| p.get_duration("pre_delay"))
+                        .unwrap_or(default.pre_delay),
+                };
+
+                Ok(AddNode::FdnReverb {
+                    lines,
+                    matrix,
+                    params,
+                })
+            }
+            // Saturation
+            "waveshaper" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "curve", "table", "adaa", "drive", "bias", "dc_block", "chans"
+                    ))?;
+                }
+                let curve = match params.and_then(|p| p.get_str("curve")).as_deref() {
+                    None | Some("tanh") => ShapeCurve::Tanh,
+                    Some("hard_clip") => ShapeCurve::HardClip,
+                    Some("soft_clip") => ShapeCurve::SoftClip,
+                    Some("foldback") => ShapeCurve::Foldback,
+                    Some("tube") => ShapeCurve::Tube,
+                    Some("table") => {
+                        let table = params
+                            .filter(|p| p.0.contains_key("table"))
+                            .and_then(|p| p.get_array_f32("table"))
+                            .unwrap_or_default();
+                        if table.len() < 2 {
+                            return Err(ValidationError::InvalidParameter(
+                                "waveshaper table needs at least two points".into(),
+                            ));
+                        }
+                        ShapeCurve::Table(table)
+                    }
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown waveshaper curve {}",
+                            x
+                        )));
+                    }
+                };
+                let adaa = match params.and_then(|p| p.get_u32("adaa")) {
+                    None | Some(1) => Adaa::First,
+                    Some(0) => Adaa::Off,
+                    Some(2) => Adaa::Second,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "waveshaper adaa must be 0, 1 or 2, got {}",
+                            x
+                        )));
+                    }
+                };
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(1) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "waveshaper chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+
+                let default = WaveshaperParams::default();
+                let params = WaveshaperParams {
+                    curve,
+                    adaa,
+                    drive: params
+                        .and_then(|p| p.get_f32("drive"))
+                        .unwrap_or(default.drive),
+                    bias: params
+                        .and_then(|p| p.get_f32("bias"))
+                        .unwrap_or(default.bias),
+                    dc_block: params
+                        .and_then(|p| p.get_bool("dc_block"))
+                        .unwrap_or(default.dc_block),
+                };
+
+                Ok(AddNode::Waveshaper { params, chans })
+            }
+            // Dynamics
+            "compressor" | "limiter" | "expander" | "noise_gate" => {
+                if let Some(p) = params {
+                    let mut allowed = param_list!(
+                        "threshold",
+                        "knee",
+                        "release",
+                        "makeup",
+                        "detection",
+                        "rms_window",
+                        "link",
+                        "sidechain",
+                        "chans"
+                    );
+                    match name.as_str() {
+                        "compressor" => allowed.extend(param_list!("ratio", "attack")),
+                        "limiter" => allowed.extend(param_list!("lookahead", "true_peak")),
+                        "expander" => allowed.extend(param_list!("ratio", "attack", "range")),
+                        _ => allowed.extend(param_list!("attack", "range", "hold", "hysteresis")),
+                    }
+                    p.validate(&allowed)?;
+                }
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "{} chans must be 1, 2, 4 or 8, got {}",
+                        name, chans
+                    )));
+                }
+
+                let mode = match name.as_str() {
+                    "compressor" => DynamicsMode::Compressor,
+                    "limiter" => DynamicsMode::Limiter {
+                        lookahead: params
+                            .and_then(|p| p.get_duration("lookahead"))
+                            .unwrap_or(Duration::from_millis(5)),
+                        true_peak: params
+                            .and_then(|p| p.get_bool("true_peak"))
+                            .unwrap_or(false),
+                    },
+                    "expander" => DynamicsMode::Expander,
+                    _ => DynamicsMode::Gate {
+                        hold: params
+                            .and_then(|p| p.get_duration("hold"))
+                            .unwrap_or(Duration::from_millis(50)),
+                        hysteresis: params.and_then(|p| p.get_f32("hysteresis")).unwrap_or(3.0),
+                    },
+                };
+                let detection = get_detection(params)?;
+
+                // The limiter and gate want a faster, harder default
+                let default = match mode {
+                    DynamicsMode::Compressor => DynamicsParams::default(),
+                    DynamicsMode::Limiter { .. } => DynamicsParams {
+                        threshold: -1.0,
+                        knee: 0.0,
+                        release: Duration::from_millis(50),
+                        ..Default::default()
+                    },
+                    DynamicsMode::Expander => DynamicsParams {
+                        threshold: -40.0,
+                        ratio: 2.0,
+                        ..Default::default()
+                    },
+                    DynamicsMode::Gate { .. } => DynamicsParams {
+                        threshold: -50.0,
+                        knee: 0.0,
+                        attack: Duration::from_millis(1),
+                        ..Default::default()
+                    },
+                };
+                let get = |key: &str, default: f32| {
+                    params.and_then(|p| p.get_f32(key)).unwrap_or(default)
+                };
+                let get_time = |key: &str, default: Duration| {
+                    params.and_then(|p| p.get_duration(key)).unwrap_or(default)
+                };
+
+                let params = DynamicsParams {
+                    threshold: get("threshold", default.threshold),
+                    ratio: get("ratio", default.ratio),
+                    knee: get("knee", default.knee),
+                    attack: get_time("attack", default.attack),
+                    release: get_time("release", default.release),
+                    makeup: get("makeup", default.makeup),
+                    range: get("range", default.range),
+                    detection,
+                    rms_window: get_time("rms_window", default.rms_window),
+                    link: get("link", default.link),
+                    sidechain: params
+                        .and_then(|p| p.get_bool("sidechain"))
+                        .unwrap_or(default.sidechain),
+                };
+                if params.ratio < 1.0 {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "{} ratio must be at least 1.0, got {}",
+                        name, params.ratio
+                    )));
+                }
+
+                Ok(AddNode::Dynamics {
+                    mode,
+                    params,
+                    chans,
+                })
+            }
+            // Modulation
+            "chorus" | "flanger" | "phaser" => {
+                if let Some(p) = params {
+                    match name.as_str() {
+                        "chorus" => p.validate(&param_list!(
+                            "rate", "depth", "mix", "delay", "voices", "spread", "chans"
+                        ))?,
+                        "flanger" => p.validate(&param_list!(
+                            "rate",
+                            "depth",
+                            "mix",
+                            "delay",
+                            "feedback",
+                            "spread",
+                            "through_zero",
+                            "chans"
+                        ))?,
+                        _ => p.validate(&param_list!(
+                            "rate", "depth", "mix", "center", "stages", "feedback", "spread",
+                            "chans"
+                        ))?,
+                    }
+                }
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "{} chans must be 1, 2, 4 or 8, got {}",
+                        name, chans
+                    )));
+                }
+                let get = |key: &str, default: f32| {
+                    params.and_then(|p| p.get_f32(key)).unwrap_or(default)
+                };
+
+                match name.as_str() {
+                    "chorus" => {
+                        let default = ChorusParams::default();
+                        let voices = params
+                            .and_then(|p| p.get_u32("voices"))
+                            .map(|x| x as usize)
+                            .unwrap_or(default.voices);
+                        if voices == 0 {
+                            return Err(ValidationError::InvalidParameter(
+                                "chorus needs at least one voice".into(),
+                            ));
+                        }
+                        let params = ChorusParams {
+                            rate: get("rate", default.rate),
+                            depth: get("depth", default.depth),
+                            mix: get("mix", default.mix),
+                            delay: get("delay", default.delay),
+                            voices,
+                            spread: get("spread", default.spread),
+                        };
+                        Ok(AddNode::Chorus { params, chans })
+                    }
+                    "flanger" => {
+                        let default = FlangerParams::default();
+                        let params = FlangerParams {
+                            rate: get("rate", default.rate),
+                            depth: get("depth", default.depth),
+                            mix: get("mix", default.mix),
+                            delay: get("delay", default.delay),
+                            feedback: get("feedback", default.feedback),
+                            spread: get("spread", default.spread),
+                            through_zero: params
+                                .and_then(|p| p.get_bool("through_zero"))
+                                .unwrap_or(default.through_zero),
+                        };
+                        Ok(AddNode::Flanger { params, chans })
+                    }
+                    _ => {
+                        let default = PhaserParams::default();
+                        let stages = params
+                            .and_then(|p| p.get_u32("stages"))
+                            .map(|x| x as usize)
+                            .unwrap_or(default.stages);
+                        if !(2..=12).contains(&stages) {
+                            return Err(ValidationError::InvalidParameter(format!(
+                                "phaser stages must be between 2 and 12, got {}",
+                                stages
+                            )));
+                        }
+                        let params = PhaserParams {
+                            rate: get("rate", default.rate),
+                            depth: get("depth", default.depth),
+                            mix: get("mix", default.mix),
+                            center: get("center", default.center),
+                            stages,
+                            feedback: get("feedback", default.feedback),
+                            spread: get("spread", default.spread),
+                        };
+                        Ok(AddNode::Phaser { params, chans })
+                    }
+                }
+            }
             // Ops
             "add_mono" => {
                 if let Some(p) = params {
@@ -312,6 +1457,89 @@ where
             }
 
             // Mixers
+            "mixer" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "tracks",
+                        "chans",
+                        "sends",
+                        "pre_fader",
+                        "gain",
+                        "pan",
+                        "send0",
+                        "send1",
+                        "send2",
+                        "send3",
+                        "master"
+                    ))?;
+                }
+                let tracks = params.and_then(|p| p.get_u32("tracks")).unwrap_or(2) as usize;
+                if !(1..=MAX_TRACKS).contains(&tracks) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "mixer tracks must be between 1 and {}, got {}",
+                        MAX_TRACKS, tracks
+                    )));
+                }
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(2) as usize;
+                if chans != 1 && chans != 2 {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "mixer chans must be 1 or 2, got {}",
+                        chans
+                    )));
+                }
+                let sends = params.and_then(|p| p.get_u32("sends")).unwrap_or(0) as usize;
+                if sends > MAX_SENDS {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "mixer can have up to {} sends, got {}",
+                        MAX_SENDS, sends
+                    )));
+                }
+                let pre_fader = params
+                    .and_then(|p| p.get_bool("pre_fader"))
+                    .unwrap_or(false);
+
+                let mut mixer =
+                    ChannelMixerParams::new(tracks, vec![SendParams { pre_fader }; sends]);
+                // Per track gains in dB, pans and send levels in dB, as arrays
+                for (i, key) in ["gain", "pan", "send0", "send1", "send2", "send3"]
+                    .into_iter()
+                    .enumerate()
+                {
+                    if let Some(values) = params
+                        .filter(|p| p.0.contains_key(key))
+                        .and_then(|p| p.get_array_f32(key))
+                    {
+                        if values.len() != tracks {
+                            return Err(ValidationError::InvalidParameter(format!(
+                                "mixer {} needs one value per track, got {}",
+                                key,
+                                values.len()
+                            )));
+                        }
+                        if i >= 2 && i - 2 >= sends {
+                            return Err(ValidationError::InvalidParameter(format!(
+                                "mixer {} needs at least {} sends, got {}",
+                                key,
+                                i - 1,
+                                sends
+                            )));
+                        }
+                        for (track, value) in mixer.tracks.iter_mut().zip(values) {
+                            match i {
+                                0 => track.gain = value,
+                                1 => track.pan = value,
+                                s => track.sends[s - 2] = value,
+                            }
+                        }
+                    }
+                }
+                mixer.master = params.and_then(|p| p.get_f32("master")).unwrap_or(0.0);
+
+                Ok(AddNode::ChannelMixer {
+                    chans,
+                    params: mixer,
+                })
+            }
             "stereo_mixer" => Ok(AddNode::StereoMixer),
             "stereo_to_mono" => Ok(AddNode::StereoToMono),
             "two_track_stereo_mixer" => Ok(AddNode::TwoTrackStereoMixer),
@@ -353,6 +1581,184 @@ where
                     duration,
                 })
             }
+            // Analysis
+            "env_follower" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("attack", "release", "detection", "rms_window"))?;
+                }
+                let default = EnvelopeFollowerParams::default();
+                let get =
+                    |key, default| params.and_then(|p| p.get_duration(key)).unwrap_or(default);
+                Ok(AddNode::EnvelopeFollower {
+                    params: EnvelopeFollowerParams {
+                        attack: get("attack", default.attack),
+                        release: get("release", default.release),
+                        detection: get_detection(params)?,
+                        rms_window: get("rms_window", default.rms_window),
+                    },
+                })
+            }
+            "onset" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("threshold", "floor", "min_interval"))?;
+                }
+                let default = OnsetParams::default();
+                let threshold = params
+                    .and_then(|p| p.get_f32("threshold"))
+                    .unwrap_or(default.threshold);
+                if threshold <= 0.0 {
+                    return Err(ValidationError::InvalidParameter(
+                        "onset threshold must be over 0dB".into(),
+                    ));
+                }
+                Ok(AddNode::OnsetDetector {
+                    params: OnsetParams {
+                        threshold,
+                        floor: params
+                            .and_then(|p| p.get_f32("floor"))
+                            .unwrap_or(default.floor),
+                        min_interval: params
+                            .and_then(|p| p.get_duration("min_interval"))
+                            .unwrap_or(default.min_interval),
+                    },
+                })
+            }
+            "pitch_tracker" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("min_freq", "max_freq", "threshold", "floor"))?;
+                }
+                let default = PitchTrackerParams::default();
+                let get = |key, default| params.and_then(|p| p.get_f32(key)).unwrap_or(default);
+                let params = PitchTrackerParams {
+                    min_freq: get("min_freq", default.min_freq),
+                    max_freq: get("max_freq", default.max_freq),
+                    threshold: get("threshold", default.threshold),
+                    floor: get("floor", default.floor),
+                };
+                if params.min_freq < 20.0 || params.max_freq <= params.min_freq {
+                    return Err(ValidationError::InvalidParameter(
+                        "pitch_tracker needs 20Hz <= min_freq < max_freq".into(),
+                    ));
+                }
+                Ok(AddNode::PitchTracker { params })
+            }
+            // The readings are in the backend's node handles
+            "meter" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("hold", "decay", "rms_window", "chans"))?;
+                }
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "meter chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+                let default = MeterParams::default();
+                let decay = params
+                    .and_then(|p| p.get_f32("decay"))
+                    .unwrap_or(default.decay);
+                if decay < 0.0 {
+                    return Err(ValidationError::InvalidParameter(
+                        "meter decay must not be negative".into(),
+                    ));
+                }
+                Ok(AddNode::Meter {
+                    params: MeterParams {
+                        hold: params
+                            .and_then(|p| p.get_duration("hold"))
+                            .unwrap_or(default.hold),
+                        decay,
+                        rms_window: params
+                            .and_then(|p| p.get_duration("rms_window"))
+                            .unwrap_or(default.rms_window),
+                    },
+                    chans,
+                })
+            }
+            "spectrum" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "size",
+                        "overlap",
+                        "window",
+                        "smoothing",
+                        "hold",
+                        "decay",
+                        "chans"
+                    ))?;
+                }
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "spectrum chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+                let default = SpectrumParams::default();
+                let size = params
+                    .and_then(|p| p.get_u32("size"))
+                    .map_or(default.size, |x| x as usize);
+                if !size.is_power_of_two() || !(64..=32768).contains(&size) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "spectrum size must be a power of two from 64 to 32768, got {}",
+                        size
+                    )));
+                }
+                let overlap = params
+                    .and_then(|p| p.get_f32("overlap"))
+                    .unwrap_or(default.overlap);
+                if !(0.0..=0.95).contains(&overlap) {
+                    return Err(ValidationError::InvalidParameter(
+                        "spectrum overlap must be between 0.0 and 0.95".into(),
+                    ));
+                }
+                let window = match params.and_then(|p| p.get_str("window")).as_deref() {
+                    None | Some("hann") => SpectrumWindow::Hann,
+                    Some("blackman_harris") => SpectrumWindow::BlackmanHarris,
+                    Some(x) => {
+                        return Err(ValidationError::InvalidParameter(format!(
+                            "Unknown spectrum window {}",
+                            x
+                        )));
+                    }
+                };
+                Ok(AddNode::Spectrum {
+                    params: SpectrumParams {
+                        size,
+                        overlap,
+                        window,
+                        smoothing: params
+                            .and_then(|p| p.get_f32("smoothing"))
+                            .unwrap_or(default.smoothing),
+                        hold: params
+                            .and_then(|p| p.get_duration("hold"))
+                            .unwrap_or(default.hold),
+                        decay: params
+                            .and_then(|p| p.get_f32("decay"))
+                            .unwrap_or(default.decay),
+                    },
+                    chans,
+                })
+            }
+            "tap" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("capacity", "chans"))?;
+                }
+                let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(2) as usize;
+                if ![1, 2, 4, 8].contains(&chans) {
+                    return Err(ValidationError::InvalidParameter(format!(
+                        "tap chans must be 1, 2, 4 or 8, got {}",
+                        chans
+                    )));
+                }
+                Ok(AddNode::Tap {
+                    capacity: params
+                        .and_then(|p| p.get_duration("capacity"))
+                        .unwrap_or(Duration::from_secs(1)),
+                    chans,
+                })
+            }
             _ => Err(ValidationError::NodeNotFound(format!(
                 "Could not find node with name {}",
                 name
@@ -360,3 +1766,133 @@ where
         }
     }
 }
+
+/// Building blocks for making your own reverbs, i.e
+/// Freeverb or Dattorro style networks, in a reverb { } scope.
+///
+/// Times are in milliseconds.
+#[derive(Default)]
+pub struct ReverbRegistry;
+
+impl<AF, CF> NodeRegistry<AF, CF> for ReverbRegistry
+where
+    AF: FrameSize + Mul<U2>,
+    Prod<AF, U2>: FrameSize,
+    CF: FrameSize,
+{
+    fn lower_to_ir(
+        &self,
+        name: &String,
+        params: Option<&Params>,
+    ) -> Result<AddNode<AF, CF>, ValidationError> {
+        let delay = |default: u64| {
+            params
+                .and_then(|p| p.get_duration("delay"))
+                .unwrap_or(Duration::from_millis(default))
+        };
+        let gain = params.and_then(|p| p.get_f32("gain")).unwrap_or(0.5);
+
+        let block_type = match name.as_str() {
+            "comb" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("delay", "feedback", "chans"))?;
+                }
+                ReverbBlockType::Comb {
+                    delay: delay(30),
+                    feedback: params.and_then(|p| p.get_f32("feedback")).unwrap_or(0.8),
+                }
+            }
+            "lp_comb" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("delay", "feedback", "damping", "chans"))?;
+                }
+                ReverbBlockType::LowpassComb {
+                    delay: delay(30),
+                    feedback: params.and_then(|p| p.get_f32("feedback")).unwrap_or(0.84),
+                    damping: params.and_then(|p| p.get_f32("damping")).unwrap_or(0.2),
+                }
+            }
+            "allpass" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("delay", "gain", "chans"))?;
+                }
+                ReverbBlockType::Allpass {
+                    delay: delay(5),
+                    gain,
+                }
+            }
+            "nested_allpass" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!(
+                        "delay",
+                        "gain",
+                        "inner_delay",
+                        "inner_gain",
+                        "chans"
+                    ))?;
+                }
+                ReverbBlockType::NestedAllpass {
+                    delay: delay(30),
+                    gain,
+                    inner_delay: params
+                        .and_then(|p| p.get_duration("inner_delay"))
+                        .unwrap_or(Duration::from_millis(8)),
+                    inner_gain: params.and_then(|p| p.get_f32("inner_gain")).unwrap_or(0.5),
+                }
+            }
+            "diffuser" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("delays", "gain", "chans"))?;
+                }
+                // Defaults to the Dattorro input diffusers
+                let delays = params
+                    .filter(|p| p.0.contains_key("delays"))
+                    .and_then(|p| p.get_array_duration_ms("delays"))
+                    .unwrap_or(vec![
+                        Duration::from_secs_f32(0.00477),
+                        Duration::from_secs_f32(0.00359),
+                        Duration::from_secs_f32(0.01273),
+                        Duration::from_secs_f32(0.00930),
+                    ]);
+                if delays.is_empty() {
+                    return Err(ValidationError::InvalidParameter(
+                        "diffuser needs at least one delay".into(),
+                    ));
+                }
+                ReverbBlockType::Diffuser {
+                    delays,
+                    gain: params.and_then(|p| p.get_f32("gain")).unwrap_or(0.7),
+                }
+            }
+            "mod_allpass" => {
+                if let Some(p) = params {
+                    p.validate(&param_list!("delay", "gain", "rate", "depth", "chans"))?;
+                }
+                ReverbBlockType::ModulatedAllpass {
+                    delay: delay(22),
+                    gain,
+                    rate: params.and_then(|p| p.get_f32("rate")).unwrap_or(1.0),
+                    depth: params
+                        .and_then(|p| p.get_duration("depth"))
+                        .unwrap_or(Duration::from_secs_f32(0.0005)),
+                }
+            }
+            _ => {
+                return Err(ValidationError::NodeNotFound(format!(
+                    "Could not find node with name {}",
+                    name
+                )));
+            }
+        };
+
+        let chans = params.and_then(|p| p.get_u32("chans")).unwrap_or(1) as usize;
+        if ![1, 2, 4, 8].contains(&chans) {
+            return Err(ValidationError::InvalidParameter(format!(
+                "Reverb chans must be 1, 2, 4 or 8, got {}",
+                chans
+            )));
+        }
+
+        Ok(AddNode::ReverbBlock { block_type, chans })
+    }
+}
@@ -69,3 +69,19 @@ fn ast_graph_with_connections() {
 
     assert_eq!(ast.connections.len(), 2);
 }
+
+#[test]
+fn ast_boolean_params() {
+    let ast = parse_ast(
+        r#"
+        audio {
+            lfo: wobble { sync: true, unipolar: false }
+        }
+        { wobble }
+    "#,
+    );
+
+    let params = ast.declarations[0].declarations[0].params.as_ref().unwrap();
+    assert_eq!(params["sync"], Value::Bool(true));
+    assert_eq!(params["unipolar"], Value::Bool(false));
+}
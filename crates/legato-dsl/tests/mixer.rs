@@ -0,0 +1,87 @@
+use legato_core::nodes::audio::channel_mixer::{MAX_SENDS, MAX_TRACKS};
+use legato_dsl::{ApplicationConfig, build_application};
+use typenum::{U2, U16, U64};
+
+#[test]
+fn mixer_sends_are_set_per_track() {
+    let graph = String::from(
+        r#"
+        audio {
+            sine_mono: osc { freq: 440.0 },
+            mixer: desk { tracks: 1, chans: 1, sends: 1, send0: [-6.0] }
+        }
+
+        osc[0] >> desk[0]
+
+        { desk }
+    "#,
+    );
+
+    let config = ApplicationConfig {
+        intitial_capacity: 4,
+        sample_rate: 48_000,
+        control_rate: 12_000,
+    };
+    let (mut app, _backend) =
+        build_application::<U64, U16, U2>(&graph, config).expect("Failed to build the mixer");
+
+    let mut heard = 0.0_f32;
+    for _ in 0..4 {
+        let out = app.next_block();
+        for (master, send) in out[0].iter().zip(out[1].iter()) {
+            // The send is 6dB under the master, which is at unity
+            assert!((send - master * 10f32.powf(-6.0 / 20.0)).abs() < 1e-4);
+            heard = heard.max(send.abs());
+        }
+    }
+    assert!(heard > 0.1);
+}
+
+// Runs a mixer with sends on every track, returning the outputs of the last block
+fn run_mixer(tracks: usize, sends: usize) -> Vec<Vec<f32>> {
+    let levels = vec!["-6.0"; tracks].join(", ");
+    let send_params: String = (0..sends)
+        .map(|s| format!(", send{s}: [{levels}]"))
+        .collect();
+    let graph = format!(
+        r#"
+        audio {{
+            sine_mono: osc {{ freq: 440.0 }},
+            mixer: desk {{ tracks: {tracks}, chans: 2, sends: {sends}{send_params} }}
+        }}
+
+        osc[0] >> desk[0]
+
+        {{ desk }}
+    "#
+    );
+
+    let config = ApplicationConfig {
+        intitial_capacity: 4,
+        sample_rate: 48_000,
+        control_rate: 12_000,
+    };
+    let (mut app, _backend) =
+        build_application::<U64, U16, U2>(&graph, config).expect("Failed to build the mixer");
+
+    let mut out = vec![];
+    for _ in 0..4 {
+        out = app.next_block().iter().map(|x| x.to_vec()).collect();
+    }
+    out
+}
+
+#[test]
+fn mixer_runs_with_eight_tracks() {
+    let out = run_mixer(8, 0);
+    assert_eq!(out.len(), 2);
+    assert!(out[0].iter().any(|x| x.abs() > 0.1));
+}
+
+#[test]
+fn mixer_runs_with_every_track_and_send() {
+    let out = run_mixer(MAX_TRACKS, MAX_SENDS);
+    assert_eq!(out.len(), (MAX_SENDS + 1) * 2);
+    assert!(out.iter().all(|chan| chan.iter().all(|x| x.is_finite())));
+    assert!(out[2].iter().any(|x| x.abs() > 0.05));
+}
@@ -0,0 +1,67 @@
+use legato_dsl::{
+    ApplicationConfig,
+    ast::build_ast,
+    build_application,
+    ir::{ValidationError, params::Params, registry::LegatoRegistryContainer},
+    parse::{LegatoParser, Rule},
+};
+use pest::Parser;
+use typenum::{U1, U16, U64};
+
+#[test]
+fn reverb_comb_delays_the_input() {
+    let graph = String::from(
+        r#"
+        audio {
+            sine_mono: osc { freq: 440.0 }
+        }
+
+        reverb {
+            comb: echo { delay: 1, feedback: 0.0 }
+        }
+
+        osc[0] >> echo[0]
+
+        { echo }
+    "#,
+    );
+
+    let config = ApplicationConfig {
+        intitial_capacity: 4,
+        sample_rate: 48_000,
+        control_rate: 12_000,
+    };
+    let (mut app, _backend) =
+        build_application::<U64, U16, U1>(&graph, config).expect("Failed to build the comb");
+
+    let first = app.next_block()[0].to_vec();
+    // Nothing comes out until the 1ms, or 48 samples, have passed
+    assert!(first[..40].iter().all(|x| x.abs() < 1e-6));
+    assert!(first[48..].iter().any(|x| x.abs() > 0.1));
+}
+
+#[test]
+fn reverb_blocks_only_take_supported_channel_counts() {
+    let graph = r#"
+        reverb {
+            comb: two { chans: 2 },
+            comb: three { chans: 3 }
+        }
+        { two }
+    "#;
+    let pairs = LegatoParser::parse(Rule::graph, graph).expect("PEST failed");
+    let ast = build_ast(pairs).expect("AST lowering failed");
+    let registry = LegatoRegistryContainer::<U64, U16>::new();
+
+    let lower = |i: usize| {
+        let node = &ast.declarations[0].declarations[i];
+        let params = node.params.as_ref().map(Params::new);
+        registry.get(&String::from("reverb"), &node.node_type, params.as_ref())
+    };
+
+    assert!(lower(0).is_ok());
+    assert!(matches!(
+        lower(1),
+        Err(ValidationError::InvalidParameter(_))
+    ));
+}
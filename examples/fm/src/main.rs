@@ -20,12 +20,16 @@ fn main() {
         audio {
             sine_mono: mod { freq: 550.0 },
             sine_stereo: carrier { freq: 440.0 },
-            mult_mono: fm_gain { val: 1000.0 }
+            mult_mono: fm_gain { val: 1000.0 },
+            dc_block: dc { chans: 2 }
         }
 
         mod[0] >> fm_gain[0] >> carrier[0]
 
-        { carrier }
+        carrier[0] >> dc[0]
+        carrier[1] >> dc[1]
+
+        { dc }
     "#,
     );
 